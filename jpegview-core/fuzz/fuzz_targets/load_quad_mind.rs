@@ -0,0 +1,19 @@
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate jpegview_core;
+
+use jpegview_core::quad_mind::{self, QuadMindLimits};
+
+// Small limits keep every decoded input fast
+fuzz_target!(|data: &[u8]| {
+    let limits = QuadMindLimits {
+        max_width: 1024,
+        max_height: 1024,
+        max_node_count: 1 << 16,
+        max_decompressed_size: 1 << 24,
+    };
+
+    let _ = quad_mind::load_quad_mind_from_memory(data, String::from("fuzz.qmi"), &limits);
+});
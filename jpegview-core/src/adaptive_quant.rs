@@ -0,0 +1,150 @@
+// Adaptive quantization scales the matrices of every block by its activity,
+// textured blocks hide errors so they get coarser steps and flat blocks get
+// finer ones. A region of interest mask adds its own scale on top. Scales
+// are stored as codes so encoder and decoder use the exact same value, 128
+// keeps the matrices unchanged
+
+use std::sync::Arc;
+
+pub const SCALE_CODE_NEUTRAL: u8 = 128;
+
+// Codes are 32 per octave, scales go from 1/8 to 8
+const SCALE_CODE_STEPS: f32 = 32.0f32;
+const MAX_LOG2_SCALE: f32 = 3.0f32;
+
+// Activity alone moves the scale at most an octave
+const MAX_ACTIVITY_LOG2_SCALE: f32 = 1.0f32;
+
+// Like x264 a strength of 1 moves the step an octave every 6 octaves of
+// activity away from the image average
+const ACTIVITY_OCTAVES_PER_SCALE: f32 = 6.0f32;
+
+// Variance plus the energy of horizontal and vertical edges of a block with
+// pixels around 0
+pub fn block_activity(image_block: &[f32], block_size: usize) -> f32 {
+    let pixel_count = (block_size * block_size) as f32;
+
+    let mean = image_block.iter().sum::<f32>() / pixel_count;
+    let variance = image_block
+        .iter()
+        .map(|value| (value - mean) * (value - mean))
+        .sum::<f32>()
+        / pixel_count;
+
+    let mut edge_energy = 0.0f32;
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let index = y * block_size + x;
+            if x + 1 < block_size {
+                let difference = image_block[index + 1] - image_block[index];
+                edge_energy += difference * difference;
+            }
+            if y + 1 < block_size {
+                let difference = image_block[index + block_size] - image_block[index];
+                edge_energy += difference * difference;
+            }
+        }
+    }
+
+    variance + edge_energy / pixel_count
+}
+
+pub fn scale_codes(activities: &[f32], strength: f32) -> Vec<u8> {
+    if activities.is_empty() {
+        return Vec::new();
+    }
+
+    let log_activities: Vec<f32> = activities
+        .iter()
+        .map(|activity| (activity + 1.0f32).log2())
+        .collect();
+    let mean = log_activities.iter().sum::<f32>() / log_activities.len() as f32;
+
+    log_activities
+        .iter()
+        .map(|log_activity| {
+            let log2_scale = (strength * (log_activity - mean) / ACTIVITY_OCTAVES_PER_SCALE)
+                .clamp(-MAX_ACTIVITY_LOG2_SCALE, MAX_ACTIVITY_LOG2_SCALE);
+
+            code_from_log2_scale(log2_scale)
+        })
+        .collect()
+}
+
+pub fn code_from_log2_scale(log2_scale: f32) -> u8 {
+    let log2_scale = log2_scale.clamp(-MAX_LOG2_SCALE, MAX_LOG2_SCALE);
+    (SCALE_CODE_NEUTRAL as f32 + log2_scale * SCALE_CODE_STEPS).round() as u8
+}
+
+// Multiplies the scales of two codes
+pub fn combine_codes(code: u8, other_code: u8) -> u8 {
+    let log2_scale =
+        (code as f32 + other_code as f32 - SCALE_CODE_NEUTRAL as f32 * 2.0f32) / SCALE_CODE_STEPS;
+    code_from_log2_scale(log2_scale)
+}
+
+pub fn scale_from_code(code: u8) -> f32 {
+    ((code as f32 - SCALE_CODE_NEUTRAL as f32) / SCALE_CODE_STEPS).exp2()
+}
+
+pub fn is_valid_code(code: u8) -> bool {
+    (code as f32 - SCALE_CODE_NEUTRAL as f32).abs() <= MAX_LOG2_SCALE * SCALE_CODE_STEPS
+}
+
+// Blocks smaller than the matrices only use their first values
+pub fn scale_q_matrix(q_matrix: &[f32], length: usize, code: u8) -> Vec<f32> {
+    let scale = scale_from_code(code);
+    q_matrix[..length]
+        .iter()
+        .map(|value| value * scale)
+        .collect()
+}
+
+// Matrix of a block, shared when it has no scale code
+pub fn block_q_matrix(q_matrix: &Arc<Vec<f32>>, length: usize, code: Option<u8>) -> Arc<Vec<f32>> {
+    match code {
+        Some(code) => Arc::new(scale_q_matrix(q_matrix, length, code)),
+        None => Arc::clone(q_matrix),
+    }
+}
+
+pub fn block_q_matrices(
+    q_matrix_luma: &Arc<Vec<f32>>,
+    q_matrix_chroma: &Arc<Vec<f32>>,
+    length: usize,
+    code: Option<u8>,
+) -> (Arc<Vec<f32>>, Arc<Vec<f32>>) {
+    (
+        block_q_matrix(q_matrix_luma, length, code),
+        block_q_matrix(q_matrix_chroma, length, code),
+    )
+}
+
+// Tints a block red when it is quantized coarser and blue when finer
+pub fn draw_scale_overlay(
+    image: &mut [u8],
+    width: usize,
+    height: usize,
+    box_left: usize,
+    box_top: usize,
+    block_size: usize,
+    code: u8,
+) {
+    let amount = (code as f32 - SCALE_CODE_NEUTRAL as f32) / SCALE_CODE_STEPS;
+    let tint = if amount > 0.0f32 {
+        [255.0f32, 0.0f32, 0.0f32]
+    } else {
+        [0.0f32, 0.0f32, 255.0f32]
+    };
+    let alpha = amount.abs().min(1.0f32) * 0.5f32;
+
+    for y in box_top..(box_top + block_size).min(height) {
+        for x in box_left..(box_left + block_size).min(width) {
+            let index = (y * width + x) * 3;
+            for i in 0..3 {
+                image[index + i] =
+                    (image[index + i] as f32 * (1.0f32 - alpha) + tint[i] * alpha).round() as u8;
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::{
+    coefficient_coder::{CoefficientDecoder, CoefficientEncoder},
+    jpeg::{self, Jpeg, JpegSteps},
+    my_image::{self, MyImage},
+    quad_mind,
+};
+use std::sync::Arc;
+
+// Alpha is a plane of its own next to the color planes. Lossless alpha is
+// the deflated difference of every pixel with its left neighbour, lossy
+// alpha goes through 8x8 blocks quantized with the luma table at a quality
+// of its own, always with the table DCT so every decoder gets the same plane
+const BLOCK_SIZE: usize = 8;
+const BLOCK_SIZE_INDEX: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AlphaParameters {
+    pub use_lossless: bool,
+    pub quality: f32,
+}
+
+impl AlphaParameters {
+    pub fn new(use_lossless: bool, quality: f32) -> AlphaParameters {
+        AlphaParameters {
+            use_lossless,
+            quality,
+        }
+    }
+}
+
+impl Default for AlphaParameters {
+    fn default() -> AlphaParameters {
+        AlphaParameters::new(true, 90.0f32)
+    }
+}
+
+// Codes the alpha of the image and keeps what a decoder gets back from it,
+// returns the size of the coded alpha
+pub fn render_alpha(my_image: &mut MyImage, alpha: AlphaParameters) -> usize {
+    match &my_image.original_alpha {
+        Some(original_alpha) => {
+            let (data, final_alpha) =
+                code_alpha(original_alpha, my_image.width, my_image.height, alpha);
+            my_image.final_alpha = Some(final_alpha);
+            data.len()
+        }
+        None => {
+            my_image.final_alpha = None;
+            0
+        }
+    }
+}
+
+pub fn encode_alpha(plane: &[u8], width: usize, height: usize, alpha: AlphaParameters) -> Vec<u8> {
+    code_alpha(plane, width, height, alpha).0
+}
+
+// The coded alpha and the plane a decoder gets back from it
+fn code_alpha(
+    plane: &[u8],
+    width: usize,
+    height: usize,
+    alpha: AlphaParameters,
+) -> (Vec<u8>, Vec<u8>) {
+    if alpha.use_lossless {
+        let mut differences = Vec::with_capacity(plane.len());
+        for row in plane.chunks(width) {
+            let mut left = 0u8;
+            for &value in row {
+                differences.push(value.wrapping_sub(left));
+                left = value;
+            }
+        }
+        (
+            miniz_oxide::deflate::compress_to_vec(&differences, 10),
+            plane.to_vec(),
+        )
+    } else {
+        let jpeg_steps = alpha_jpeg_steps(width);
+        let q_matrix = alpha_q_matrix(alpha.quality);
+        let zig_zag_table = quad_mind::generate_zig_zag_table(BLOCK_SIZE);
+
+        let mut coefficient_encoder = CoefficientEncoder::new();
+        let mut dct_zig_zag = vec![0i32; BLOCK_SIZE * BLOCK_SIZE];
+        let mut result = vec![0u8; width * height];
+
+        for by in 0..height.div_ceil(BLOCK_SIZE) {
+            for bx in 0..width.div_ceil(BLOCK_SIZE) {
+                // Edges are repeated past the image so they do not ring
+                let mut image_block = vec![0.0f32; BLOCK_SIZE * BLOCK_SIZE];
+                for y in 0..BLOCK_SIZE {
+                    let index_y = (by * BLOCK_SIZE + y).min(height - 1) * width;
+                    for x in 0..BLOCK_SIZE {
+                        let index_x = (bx * BLOCK_SIZE + x).min(width - 1);
+                        image_block[y * BLOCK_SIZE + x] =
+                            plane[index_y + index_x] as f32 - 128.0f32;
+                    }
+                }
+
+                let (_, dct_block) = jpeg_steps.jpeg_steps(0, &image_block, &q_matrix);
+                for (index, &coefficient) in dct_block.iter().enumerate() {
+                    dct_zig_zag[zig_zag_table[index]] = coefficient;
+                }
+                coefficient_encoder.add_block(0, BLOCK_SIZE, &dct_zig_zag);
+
+                let block = BlockPosition::new(bx, by, width, height);
+                block.reconstruct(
+                    &jpeg_steps,
+                    &q_matrix,
+                    &zig_zag_table,
+                    &dct_zig_zag,
+                    &mut result,
+                );
+            }
+        }
+        (coefficient_encoder.finish(), result)
+    }
+}
+
+pub fn decode_alpha(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    alpha: AlphaParameters,
+) -> Result<Vec<u8>, &'static str> {
+    if alpha.use_lossless {
+        let mut plane = miniz_oxide::inflate::decompress_to_vec_with_limit(data, width * height)
+            .map_err(|_| "Could not decompress alpha")?;
+        if plane.len() != width * height {
+            return Err("Alpha size does not match the image");
+        }
+
+        for row in plane.chunks_mut(width) {
+            let mut left = 0u8;
+            for value in row.iter_mut() {
+                *value = value.wrapping_add(left);
+                left = *value;
+            }
+        }
+        Ok(plane)
+    } else {
+        let jpeg_steps = alpha_jpeg_steps(width);
+        let q_matrix = alpha_q_matrix(alpha.quality);
+        let zig_zag_table = quad_mind::generate_zig_zag_table(BLOCK_SIZE);
+
+        let mut coefficient_decoder = CoefficientDecoder::new(data);
+        let mut plane = vec![0u8; width * height];
+
+        for by in 0..height.div_ceil(BLOCK_SIZE) {
+            for bx in 0..width.div_ceil(BLOCK_SIZE) {
+                let dct_zig_zag = coefficient_decoder.read_block(0, BLOCK_SIZE)?;
+
+                let block = BlockPosition::new(bx, by, width, height);
+                block.reconstruct(
+                    &jpeg_steps,
+                    &q_matrix,
+                    &zig_zag_table,
+                    &dct_zig_zag,
+                    &mut plane,
+                );
+            }
+        }
+        Ok(plane)
+    }
+}
+
+// A block of the plane, the last ones can be cut by its edges
+struct BlockPosition {
+    bx: usize,
+    by: usize,
+    width: usize,
+    height: usize,
+}
+
+impl BlockPosition {
+    fn new(bx: usize, by: usize, width: usize, height: usize) -> BlockPosition {
+        BlockPosition {
+            bx,
+            by,
+            width,
+            height,
+        }
+    }
+    // Encoder and decoder both get their plane from the coefficients
+    fn reconstruct(
+        &self,
+        jpeg_steps: &JpegSteps,
+        q_matrix: &[f32],
+        zig_zag_table: &[usize],
+        dct_zig_zag: &[i32],
+        plane: &mut [u8],
+    ) {
+        let mut dct_matrix = vec![0.0f32; BLOCK_SIZE * BLOCK_SIZE];
+        for (index, value) in dct_matrix.iter_mut().enumerate() {
+            *value = dct_zig_zag[zig_zag_table[index]] as f32;
+        }
+        jpeg_steps.de_quantize_function(0, q_matrix, &mut dct_matrix, false);
+        let result_block = jpeg_steps.inverse_dct_function(&dct_matrix);
+
+        for y in 0..BLOCK_SIZE.min(self.height - self.by * BLOCK_SIZE) {
+            for x in 0..BLOCK_SIZE.min(self.width - self.bx * BLOCK_SIZE) {
+                plane[(self.by * BLOCK_SIZE + y) * self.width + self.bx * BLOCK_SIZE + x] =
+                    my_image::min_max_color(result_block[y * BLOCK_SIZE + x] + 128.5f32);
+            }
+        }
+    }
+}
+
+fn alpha_jpeg_steps(width: usize) -> JpegSteps {
+    let jpeg = Jpeg::new(
+        BLOCK_SIZE,
+        100.0f32,
+        1.0f32,
+        BLOCK_SIZE_INDEX,
+        false,
+        false,
+        false,
+        false,
+    );
+
+    let mut jpeg_steps = JpegSteps::new(&jpeg, width);
+    jpeg_steps.dct_table = Some(Arc::new(jpeg::generate_dct_table(BLOCK_SIZE)));
+    jpeg_steps.alpha_table = Some(Arc::new(jpeg::generate_alpha_table(BLOCK_SIZE)));
+    jpeg_steps
+}
+
+fn alpha_q_matrix(quality: f32) -> Vec<f32> {
+    let mut q_matrix = jpeg::generate_q_matrix(&jpeg::Q_MATRIX_LUMA_CONST, BLOCK_SIZE, false);
+    jpeg::apply_q_matrix_factor(
+        &mut q_matrix,
+        BLOCK_SIZE,
+        jpeg::quality_factor(quality, false),
+    );
+    q_matrix
+}
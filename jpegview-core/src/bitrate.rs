@@ -0,0 +1,126 @@
+// Run lengths go up to 15 like JPEG, sizes up to 31 bits so block sizes
+// bigger than 8x8 still fit
+const RUN_COUNT: usize = 16;
+const SIZE_COUNT: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SizeEstimate {
+    pub bytes: usize,
+    pub bpp: f32,
+    pub ratio: f32,
+}
+
+impl SizeEstimate {
+    pub fn new(bytes: usize, width: usize, height: usize) -> SizeEstimate {
+        let pixel_count = (width * height) as f32;
+        SizeEstimate {
+            bytes,
+            bpp: bytes as f32 * 8.0f32 / pixel_count,
+            ratio: (pixel_count * 3.0f32) / bytes.max(1) as f32,
+        }
+    }
+}
+
+// Counts JPEG style symbols of quantized blocks (DC difference size and
+// AC run/size pairs) to estimate the size of an optimal entropy coder
+pub struct EntropyCounter {
+    dc_frequencies: Vec<Vec<u32>>,
+    ac_frequencies: Vec<Vec<u32>>,
+
+    last_dc: [i32; 3],
+    extra_bits: u64,
+}
+
+impl EntropyCounter {
+    pub fn new() -> EntropyCounter {
+        EntropyCounter {
+            dc_frequencies: vec![vec![0u32; SIZE_COUNT]; 2],
+            ac_frequencies: vec![vec![0u32; RUN_COUNT * SIZE_COUNT]; 2],
+
+            last_dc: [0i32; 3],
+            extra_bits: 0,
+        }
+    }
+    // Blocks of a channel must be added in raster order, the first channel
+    // uses the luma tables and the others the chroma tables
+    pub fn add_block(&mut self, channel: usize, dct_zig_zag: &[i32]) {
+        let table = usize::from(channel > 0);
+
+        let dc_difference = dct_zig_zag[0] - self.last_dc[channel];
+        self.last_dc[channel] = dct_zig_zag[0];
+
+        let size = magnitude_size(dc_difference);
+        self.dc_frequencies[table][size] += 1;
+        self.extra_bits += size as u64;
+
+        let mut run = 0;
+        for &coefficient in &dct_zig_zag[1..] {
+            if coefficient == 0 {
+                run += 1;
+                continue;
+            }
+
+            while run >= RUN_COUNT {
+                self.ac_frequencies[table][(RUN_COUNT - 1) * SIZE_COUNT] += 1;
+                run -= RUN_COUNT;
+            }
+
+            let size = magnitude_size(coefficient);
+            self.ac_frequencies[table][run * SIZE_COUNT + size] += 1;
+            self.extra_bits += size as u64;
+            run = 0;
+        }
+
+        if run > 0 {
+            self.ac_frequencies[table][0] += 1;
+        }
+    }
+    pub fn bits(&self) -> f64 {
+        let mut bits = self.extra_bits as f64;
+        for frequencies in self.dc_frequencies.iter().chain(self.ac_frequencies.iter()) {
+            bits += entropy_bits(frequencies);
+        }
+        bits
+    }
+    pub fn bytes(&self) -> usize {
+        (self.bits() / 8.0f64).ceil() as usize
+    }
+}
+
+impl Default for EntropyCounter {
+    fn default() -> EntropyCounter {
+        EntropyCounter::new()
+    }
+}
+
+pub fn entropy_bits(frequencies: &[u32]) -> f64 {
+    let total: f64 = frequencies.iter().map(|&count| count as f64).sum();
+    if total == 0.0f64 {
+        return 0.0f64;
+    }
+
+    frequencies
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let count = count as f64;
+            -count * (count / total).log2()
+        })
+        .sum()
+}
+
+// Rough bits of an AC coefficient after a run of zeros, for deciding which
+// levels are worth coding. Codes grow with the run and the size like the
+// typical JPEG tables, runs past 15 need an 11 bit zero run code
+pub fn ac_bits(run: usize, value: i32) -> f32 {
+    let size = magnitude_size(value);
+    let zero_runs = run / RUN_COUNT;
+    let run = run % RUN_COUNT;
+
+    let code_bits = (2.0f32 + run as f32 + size as f32 * 0.5f32).min(16.0f32);
+    zero_runs as f32 * 11.0f32 + code_bits + size as f32
+}
+
+fn magnitude_size(value: i32) -> usize {
+    (32 - value.unsigned_abs().leading_zeros()) as usize
+}
@@ -0,0 +1,930 @@
+// Subsampled chroma is stored at its real resolution, every sample is a
+// filtered average around its siting, and brought back to the image size
+// with an upsampling filter like a decoder would
+
+// Horizontal and vertical factors the chroma is divided by, up to 4 like
+// the sampling factors of a jpg
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Subsampling {
+    pub factor_x: usize,
+    pub factor_y: usize,
+}
+
+impl Subsampling {
+    pub const MAX_FACTOR: usize = 4;
+
+    // 4:4:4, 4:4:0, 4:2:2, 4:2:0, 4:1:1, 4:1:0 and 3:1:1
+    pub const PRESETS: [Subsampling; 7] = [
+        Subsampling::new(1, 1),
+        Subsampling::new(1, 2),
+        Subsampling::new(2, 1),
+        Subsampling::new(2, 2),
+        Subsampling::new(4, 1),
+        Subsampling::new(4, 2),
+        Subsampling::new(3, 1),
+    ];
+
+    pub const fn new(factor_x: usize, factor_y: usize) -> Subsampling {
+        Subsampling { factor_x, factor_y }
+    }
+    pub fn factors(self) -> (usize, usize) {
+        (self.factor_x, self.factor_y)
+    }
+    pub fn is_valid(self) -> bool {
+        (1..=Subsampling::MAX_FACTOR).contains(&self.factor_x)
+            && (1..=Subsampling::MAX_FACTOR).contains(&self.factor_y)
+    }
+    pub fn preset_index(self) -> Option<usize> {
+        Subsampling::PRESETS
+            .iter()
+            .position(|&preset| preset == self)
+    }
+
+    // J:a:b when the notation can describe it, J is 4 unless the horizontal
+    // factor does not divide it, otherwise HxV
+    pub fn name(self) -> String {
+        let j = if 4 % self.factor_x == 0 {
+            4
+        } else {
+            self.factor_x
+        };
+        let a = j / self.factor_x;
+
+        match self.factor_y {
+            1 => format!("{}:{}:{}", j, a, a),
+            2 => format!("{}:{}:0", j, a),
+            _ => format!("{}x{}", self.factor_x, self.factor_y),
+        }
+    }
+    // J:a:b, b is a for full vertical resolution and 0 for half, or the
+    // factors as HxV
+    pub fn parse(text: &str) -> Result<Subsampling, String> {
+        let invalid = || format!("Invalid subsampling: {}", text);
+
+        let numbers: Vec<usize> = text
+            .split([':', 'x'])
+            .map(|number| number.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+
+        let subsampling = match (text.contains(':'), numbers.as_slice()) {
+            (true, &[j, a, b]) if a != 0 && j % a == 0 && (b == a || b == 0) => {
+                Subsampling::new(j / a, if b == 0 { 2 } else { 1 })
+            }
+            (false, &[factor_x, factor_y]) => Subsampling::new(factor_x, factor_y),
+            _ => return Err(invalid()),
+        };
+
+        if !subsampling.is_valid() {
+            return Err(format!(
+                "Subsampling factors go from 1 to {}: {}",
+                Subsampling::MAX_FACTOR,
+                text
+            ));
+        }
+        Ok(subsampling)
+    }
+}
+
+impl Default for Subsampling {
+    fn default() -> Subsampling {
+        Subsampling::new(1, 1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DownsampleFilter {
+    Box,
+    Triangle,
+    Lanczos,
+}
+
+impl DownsampleFilter {
+    pub const ALL: [DownsampleFilter; 3] = [
+        DownsampleFilter::Box,
+        DownsampleFilter::Triangle,
+        DownsampleFilter::Lanczos,
+    ];
+
+    // Radius of the kernel in output samples
+    fn support(self) -> f32 {
+        match self {
+            DownsampleFilter::Box => 0.5f32,
+            DownsampleFilter::Triangle => 1.0f32,
+            DownsampleFilter::Lanczos => 3.0f32,
+        }
+    }
+    fn weight(self, distance: f32) -> f32 {
+        match self {
+            DownsampleFilter::Box => {
+                if (-0.5f32..0.5f32).contains(&distance) {
+                    1.0f32
+                } else {
+                    0.0f32
+                }
+            }
+            DownsampleFilter::Triangle => (1.0f32 - distance.abs()).max(0.0f32),
+            DownsampleFilter::Lanczos => {
+                if distance.abs() < 3.0f32 {
+                    sinc(distance) * sinc(distance / 3.0f32)
+                } else {
+                    0.0f32
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum UpsampleFilter {
+    Nearest,
+    Bilinear,
+    Fancy,
+}
+
+impl UpsampleFilter {
+    pub const ALL: [UpsampleFilter; 3] = [
+        UpsampleFilter::Nearest,
+        UpsampleFilter::Bilinear,
+        UpsampleFilter::Fancy,
+    ];
+}
+
+// Where a chroma sample sits over the pixels it covers, centered between
+// them like jpg, or on the top left one like the co-sited video formats
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChromaSiting {
+    Centered,
+    Cosited,
+}
+
+impl ChromaSiting {
+    pub const ALL: [ChromaSiting; 2] = [ChromaSiting::Centered, ChromaSiting::Cosited];
+
+    // Position of a sample in pixels of the full size axis
+    fn position(self, sample: usize, factor: usize) -> f32 {
+        match self {
+            ChromaSiting::Centered => (sample as f32 + 0.5f32) * factor as f32 - 0.5f32,
+            ChromaSiting::Cosited => (sample * factor) as f32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ChromaFilters {
+    pub downsample: DownsampleFilter,
+    pub upsample: UpsampleFilter,
+    pub siting: ChromaSiting,
+}
+
+impl ChromaFilters {
+    pub fn new(
+        downsample: DownsampleFilter,
+        upsample: UpsampleFilter,
+        siting: ChromaSiting,
+    ) -> ChromaFilters {
+        ChromaFilters {
+            downsample,
+            upsample,
+            siting,
+        }
+    }
+}
+
+// Averaging and libjpeg's upsampling
+impl Default for ChromaFilters {
+    fn default() -> ChromaFilters {
+        ChromaFilters::new(
+            DownsampleFilter::Box,
+            UpsampleFilter::Fancy,
+            ChromaSiting::Centered,
+        )
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0f32 {
+        1.0f32
+    } else {
+        let x = x * std::f32::consts::PI;
+        x.sin() / x
+    }
+}
+
+pub fn plane_size(width: usize, height: usize, factor_x: usize, factor_y: usize) -> (usize, usize) {
+    (width.div_ceil(factor_x), height.div_ceil(factor_y))
+}
+
+// Source pixels and normalized weights of every output sample of an axis,
+// pixels past the edges repeat the edge
+fn axis_weights(
+    size: usize,
+    factor: usize,
+    filter: DownsampleFilter,
+    siting: ChromaSiting,
+) -> Vec<Vec<(usize, f32)>> {
+    let radius = filter.support() * factor as f32;
+
+    (0..size.div_ceil(factor))
+        .map(|output| {
+            let center = siting.position(output, factor);
+
+            let first = (center - radius).ceil() as isize;
+            let last = (center + radius).floor() as isize;
+
+            let mut weights: Vec<(usize, f32)> = (first..=last)
+                .map(|input| {
+                    let weight = filter.weight((input as f32 - center) / factor as f32);
+                    (input.clamp(0, size as isize - 1) as usize, weight)
+                })
+                .filter(|&(_, weight)| weight != 0.0f32)
+                .collect();
+
+            let sum: f32 = weights.iter().map(|&(_, weight)| weight).sum();
+            for (_, weight) in &mut weights {
+                *weight /= sum;
+            }
+            weights
+        })
+        .collect()
+}
+
+// The plane of the pixels inside width and height, rows are stride apart
+pub fn downsample(
+    plane: &[f32],
+    stride: usize,
+    width: usize,
+    height: usize,
+    factors: (usize, usize),
+    filter: DownsampleFilter,
+    siting: ChromaSiting,
+) -> Vec<f32> {
+    let (factor_x, factor_y) = factors;
+    let (plane_width, plane_height) = plane_size(width, height, factor_x, factor_y);
+
+    let weights_x = axis_weights(width, factor_x, filter, siting);
+    let weights_y = axis_weights(height, factor_y, filter, siting);
+
+    let mut horizontal = vec![0.0f32; plane_width * height];
+    for y in 0..height {
+        for (x, weights) in weights_x.iter().enumerate() {
+            horizontal[y * plane_width + x] = weights
+                .iter()
+                .map(|&(input, weight)| plane[y * stride + input] * weight)
+                .sum();
+        }
+    }
+
+    let mut result = vec![0.0f32; plane_width * plane_height];
+    for (y, weights) in weights_y.iter().enumerate() {
+        for x in 0..plane_width {
+            result[y * plane_width + x] = weights
+                .iter()
+                .map(|&(input, weight)| horizontal[input * plane_width + x] * weight)
+                .sum();
+        }
+    }
+    result
+}
+
+pub fn upsample(
+    plane: &[i16],
+    plane_width: usize,
+    plane_height: usize,
+    factors: (usize, usize),
+    width: usize,
+    height: usize,
+    chroma_filters: ChromaFilters,
+) -> Vec<i16> {
+    let siting = chroma_filters.siting;
+    match (chroma_filters.upsample, siting) {
+        (UpsampleFilter::Nearest, _) => upsample_nearest(
+            plane,
+            plane_width,
+            plane_height,
+            factors,
+            width,
+            height,
+            siting,
+        ),
+        // libjpeg's filter only exists for centered samples, co-sited ones
+        // are interpolated linearly
+        (UpsampleFilter::Bilinear, _) | (UpsampleFilter::Fancy, ChromaSiting::Cosited) => {
+            upsample_bilinear(
+                plane,
+                plane_width,
+                plane_height,
+                factors,
+                width,
+                height,
+                siting,
+            )
+        }
+        (UpsampleFilter::Fancy, ChromaSiting::Centered) => {
+            upsample_fancy(plane, plane_width, plane_height, factors, width, height)
+        }
+    }
+}
+
+// The sample nearest to a pixel, ties go to the first one
+fn nearest_sample(output: usize, factor: usize, size: usize, siting: ChromaSiting) -> usize {
+    let sample = match siting {
+        ChromaSiting::Centered => output / factor,
+        ChromaSiting::Cosited => (output + (factor - 1) / 2) / factor,
+    };
+    sample.min(size - 1)
+}
+
+fn upsample_nearest(
+    plane: &[i16],
+    plane_width: usize,
+    plane_height: usize,
+    factors: (usize, usize),
+    width: usize,
+    height: usize,
+    siting: ChromaSiting,
+) -> Vec<i16> {
+    let (factor_x, factor_y) = factors;
+
+    let columns: Vec<usize> = (0..width)
+        .map(|x| nearest_sample(x, factor_x, plane_width, siting))
+        .collect();
+
+    let mut result = vec![0i16; width * height];
+    for y in 0..height {
+        let row = nearest_sample(y, factor_y, plane_height, siting) * plane_width;
+        for (x, &column) in columns.iter().enumerate() {
+            result[y * width + x] = plane[row + column];
+        }
+    }
+    result
+}
+
+// Nearest sample and how far the next one is towards an output pixel
+fn bilinear_position(
+    output: usize,
+    factor: usize,
+    size: usize,
+    siting: ChromaSiting,
+) -> (usize, usize, f32) {
+    let position = match siting {
+        ChromaSiting::Centered => (output as f32 + 0.5f32) / factor as f32 - 0.5f32,
+        ChromaSiting::Cosited => output as f32 / factor as f32,
+    }
+    .max(0.0f32);
+    let first = (position as usize).min(size - 1);
+    (first, (first + 1).min(size - 1), position - first as f32)
+}
+
+fn upsample_bilinear(
+    plane: &[i16],
+    plane_width: usize,
+    plane_height: usize,
+    factors: (usize, usize),
+    width: usize,
+    height: usize,
+    siting: ChromaSiting,
+) -> Vec<i16> {
+    let (factor_x, factor_y) = factors;
+
+    let mut horizontal = vec![0.0f32; width * plane_height];
+    for y in 0..plane_height {
+        for x in 0..width {
+            let (first, second, fraction) = bilinear_position(x, factor_x, plane_width, siting);
+            let row = &plane[y * plane_width..];
+            horizontal[y * width + x] =
+                row[first] as f32 * (1.0f32 - fraction) + row[second] as f32 * fraction;
+        }
+    }
+
+    let mut result = vec![0i16; width * height];
+    for y in 0..height {
+        let (first, second, fraction) = bilinear_position(y, factor_y, plane_height, siting);
+        for x in 0..width {
+            let value = horizontal[first * width + x] * (1.0f32 - fraction)
+                + horizontal[second * width + x] * fraction;
+            result[y * width + x] = (value + 0.5f32).floor() as i16;
+        }
+    }
+    result
+}
+
+// libjpeg's triangle filter, weights 3/4 and 1/4 on axes sampled by 2 with
+// its rounding, other factors replicate like libjpeg does
+fn upsample_fancy(
+    plane: &[i16],
+    plane_width: usize,
+    plane_height: usize,
+    factors: (usize, usize),
+    width: usize,
+    height: usize,
+) -> Vec<i16> {
+    let (factor_x, factor_y) = factors;
+    let (fancy_x, fancy_y) = (factor_x == 2, factor_y == 2);
+
+    let mut column_sums = vec![0i32; plane_width];
+    let mut result = vec![0i16; width * height];
+    for y in 0..height {
+        let row = y / factor_y;
+        let nearer_row = if !fancy_y {
+            row
+        } else if y % 2 == 0 {
+            row.saturating_sub(1)
+        } else {
+            (row + 1).min(plane_height - 1)
+        };
+
+        // Four times the vertically filtered sample
+        for (x, column_sum) in column_sums.iter_mut().enumerate() {
+            let sample = plane[row * plane_width + x] as i32;
+            *column_sum = if fancy_y {
+                sample * 3 + plane[nearer_row * plane_width + x] as i32
+            } else {
+                sample * 4
+            };
+        }
+
+        for x in 0..width {
+            let column = x / factor_x;
+            let (sum, bias) = if fancy_x {
+                let nearer_column = if x % 2 == 0 {
+                    column.saturating_sub(1)
+                } else {
+                    (column + 1).min(plane_width - 1)
+                };
+                let bias = match (fancy_y, x % 2 == 0) {
+                    (true, true) => 8,
+                    (true, false) => 7,
+                    (false, true) => 4,
+                    (false, false) => 8,
+                };
+                (column_sums[column] * 3 + column_sums[nearer_column], bias)
+            } else {
+                let bias = if fancy_y && y % 2 == 0 { 4 } else { 8 };
+                (column_sums[column] * 4, bias)
+            };
+
+            result[y * width + x] = ((sum + bias) >> 4) as i16;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(result: &[f32], expected: &[f32]) {
+        assert_eq!(result.len(), expected.len());
+        for (&value, &reference) in result.iter().zip(expected) {
+            assert!(
+                (value - reference).abs() < 1e-3f32,
+                "{:?} != {:?}",
+                result,
+                expected
+            );
+        }
+    }
+
+    fn down(
+        plane: &[f32],
+        width: usize,
+        height: usize,
+        factors: (usize, usize),
+        filter: DownsampleFilter,
+        siting: ChromaSiting,
+    ) -> Vec<f32> {
+        downsample(plane, width, width, height, factors, filter, siting)
+    }
+
+    fn up(
+        plane: &[i16],
+        plane_width: usize,
+        factors: (usize, usize),
+        width: usize,
+        height: usize,
+        upsample_filter: UpsampleFilter,
+        siting: ChromaSiting,
+    ) -> Vec<i16> {
+        let filters = ChromaFilters::new(DownsampleFilter::Box, upsample_filter, siting);
+        upsample(
+            plane,
+            plane_width,
+            plane.len() / plane_width,
+            factors,
+            width,
+            height,
+            filters,
+        )
+    }
+
+    const ROW: [f32; 4] = [10.0f32, 20.0f32, 30.0f32, 40.0f32];
+
+    #[test]
+    fn downsample_2x1_box() {
+        let centered = down(
+            &ROW,
+            4,
+            1,
+            (2, 1),
+            DownsampleFilter::Box,
+            ChromaSiting::Centered,
+        );
+        assert_close(&centered, &[15.0f32, 35.0f32]);
+        // The kernel is half open, the sample before a co-sited one counts
+        let cosited = down(
+            &ROW,
+            4,
+            1,
+            (2, 1),
+            DownsampleFilter::Box,
+            ChromaSiting::Cosited,
+        );
+        assert_close(&cosited, &[10.0f32, 25.0f32]);
+    }
+
+    #[test]
+    fn downsample_2x1_triangle() {
+        let centered = down(
+            &ROW,
+            4,
+            1,
+            (2, 1),
+            DownsampleFilter::Triangle,
+            ChromaSiting::Centered,
+        );
+        assert_close(&centered, &[16.25f32, 33.75f32]);
+        let cosited = down(
+            &ROW,
+            4,
+            1,
+            (2, 1),
+            DownsampleFilter::Triangle,
+            ChromaSiting::Cosited,
+        );
+        assert_close(&cosited, &[12.5f32, 30.0f32]);
+    }
+
+    #[test]
+    fn downsample_2x1_lanczos() {
+        let centered = down(
+            &ROW,
+            4,
+            1,
+            (2, 1),
+            DownsampleFilter::Lanczos,
+            ChromaSiting::Centered,
+        );
+        assert_close(&centered, &[14.717239f32, 35.282761f32]);
+        let cosited = down(
+            &ROW,
+            4,
+            1,
+            (2, 1),
+            DownsampleFilter::Lanczos,
+            ChromaSiting::Cosited,
+        );
+        assert_close(&cosited, &[11.381908f32, 30.555473f32]);
+
+        // Rings around a step
+        let step = [
+            0.0f32, 0.0f32, 0.0f32, 100.0f32, 100.0f32, 100.0f32, 100.0f32, 100.0f32,
+        ];
+        let result = down(
+            &step,
+            8,
+            1,
+            (2, 1),
+            DownsampleFilter::Lanczos,
+            ChromaSiting::Centered,
+        );
+        assert_close(
+            &result,
+            &[-8.189067f32, 50.0f32, 108.189067f32, 98.125472f32],
+        );
+    }
+
+    #[test]
+    fn downsample_keeps_flat_planes() {
+        let plane = [77.0f32; 5 * 3];
+        for filter in DownsampleFilter::ALL {
+            for siting in ChromaSiting::ALL {
+                for factors in [(2, 1), (2, 2), (4, 2), (3, 1)] {
+                    let result = down(&plane, 5, 3, factors, filter, siting);
+                    let (plane_width, plane_height) = plane_size(5, 3, factors.0, factors.1);
+                    assert_close(&result, &vec![77.0f32; plane_width * plane_height]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn downsample_2x2_box() {
+        let plane = [
+            10.0f32, 20.0f32, 30.0f32, 40.0f32, //
+            50.0f32, 60.0f32, 70.0f32, 80.0f32,
+        ];
+        let result = down(
+            &plane,
+            4,
+            2,
+            (2, 2),
+            DownsampleFilter::Box,
+            ChromaSiting::Centered,
+        );
+        assert_close(&result, &[35.0f32, 55.0f32]);
+    }
+
+    #[test]
+    fn downsample_4x2_box() {
+        let plane: Vec<f32> = (0..16).map(|index| index as f32 * 8.0f32).collect();
+        let result = down(
+            &plane,
+            8,
+            2,
+            (4, 2),
+            DownsampleFilter::Box,
+            ChromaSiting::Centered,
+        );
+        assert_close(&result, &[44.0f32, 76.0f32]);
+    }
+
+    #[test]
+    fn downsample_3x1_box_repeats_the_edge() {
+        let plane = [3.0f32, 6.0f32, 9.0f32, 12.0f32, 15.0f32, 18.0f32, 21.0f32];
+        let result = down(
+            &plane,
+            7,
+            1,
+            (3, 1),
+            DownsampleFilter::Box,
+            ChromaSiting::Centered,
+        );
+        assert_close(&result, &[6.0f32, 15.0f32, 21.0f32]);
+    }
+
+    #[test]
+    fn downsample_odd_size_with_stride() {
+        // The last column is padding past the width
+        let plane = [
+            1.0f32, 2.0f32, 3.0f32, 99.0f32, //
+            4.0f32, 5.0f32, 6.0f32, 99.0f32, //
+            7.0f32, 8.0f32, 9.0f32, 99.0f32,
+        ];
+        let result = downsample(
+            &plane,
+            4,
+            3,
+            3,
+            (2, 2),
+            DownsampleFilter::Box,
+            ChromaSiting::Centered,
+        );
+        assert_close(&result, &[3.0f32, 4.5f32, 7.5f32, 9.0f32]);
+    }
+
+    #[test]
+    fn upsample_2x1() {
+        let plane = [10i16, 30i16];
+        let nearest = [10i16, 10i16, 30i16, 30i16];
+        for siting in ChromaSiting::ALL {
+            assert_eq!(
+                up(&plane, 2, (2, 1), 4, 1, UpsampleFilter::Nearest, siting),
+                nearest
+            );
+        }
+
+        let centered = [10i16, 15i16, 25i16, 30i16];
+        let cosited = [10i16, 20i16, 30i16, 30i16];
+        for upsample_filter in [UpsampleFilter::Bilinear, UpsampleFilter::Fancy] {
+            assert_eq!(
+                up(
+                    &plane,
+                    2,
+                    (2, 1),
+                    4,
+                    1,
+                    upsample_filter,
+                    ChromaSiting::Centered
+                ),
+                centered
+            );
+            assert_eq!(
+                up(
+                    &plane,
+                    2,
+                    (2, 1),
+                    4,
+                    1,
+                    upsample_filter,
+                    ChromaSiting::Cosited
+                ),
+                cosited
+            );
+        }
+    }
+
+    #[test]
+    fn upsample_fancy_9_bit() {
+        let plane = [-100i16, 300i16];
+        let result = up(
+            &plane,
+            2,
+            (2, 1),
+            4,
+            1,
+            UpsampleFilter::Fancy,
+            ChromaSiting::Centered,
+        );
+        assert_eq!(result, [-100i16, 0i16, 200i16, 300i16]);
+    }
+
+    #[test]
+    fn upsample_2x2() {
+        let plane = [10i16, 30i16, 50i16, 70i16];
+
+        let nearest = up(
+            &plane,
+            2,
+            (2, 2),
+            4,
+            4,
+            UpsampleFilter::Nearest,
+            ChromaSiting::Centered,
+        );
+        #[rustfmt::skip]
+        assert_eq!(nearest, [
+            10, 10, 30, 30,
+            10, 10, 30, 30,
+            50, 50, 70, 70,
+            50, 50, 70, 70,
+        ]);
+
+        // libjpeg's h2v2 rounding gives the same plane as bilinear here
+        #[rustfmt::skip]
+        let centered = [
+            10, 15, 25, 30,
+            20, 25, 35, 40,
+            40, 45, 55, 60,
+            50, 55, 65, 70,
+        ];
+        for upsample_filter in [UpsampleFilter::Bilinear, UpsampleFilter::Fancy] {
+            assert_eq!(
+                up(
+                    &plane,
+                    2,
+                    (2, 2),
+                    4,
+                    4,
+                    upsample_filter,
+                    ChromaSiting::Centered
+                ),
+                centered
+            );
+        }
+
+        #[rustfmt::skip]
+        let cosited = [
+            10, 20, 30, 30,
+            30, 40, 50, 50,
+            50, 60, 70, 70,
+            50, 60, 70, 70,
+        ];
+        for upsample_filter in [UpsampleFilter::Bilinear, UpsampleFilter::Fancy] {
+            assert_eq!(
+                up(
+                    &plane,
+                    2,
+                    (2, 2),
+                    4,
+                    4,
+                    upsample_filter,
+                    ChromaSiting::Cosited
+                ),
+                cosited
+            );
+        }
+    }
+
+    #[test]
+    fn upsample_4x2() {
+        let plane = [10i16, 50i16, 90i16, 130i16];
+
+        let nearest = up(
+            &plane,
+            2,
+            (4, 2),
+            8,
+            4,
+            UpsampleFilter::Nearest,
+            ChromaSiting::Centered,
+        );
+        #[rustfmt::skip]
+        assert_eq!(nearest, [
+            10, 10, 10, 10, 50, 50, 50, 50,
+            10, 10, 10, 10, 50, 50, 50, 50,
+            90, 90, 90, 90, 130, 130, 130, 130,
+            90, 90, 90, 90, 130, 130, 130, 130,
+        ]);
+
+        #[rustfmt::skip]
+        let bilinear = [
+            10, 10, 15, 25, 35, 45, 50, 50,
+            30, 30, 35, 45, 55, 65, 70, 70,
+            70, 70, 75, 85, 95, 105, 110, 110,
+            90, 90, 95, 105, 115, 125, 130, 130,
+        ];
+        assert_eq!(
+            up(
+                &plane,
+                2,
+                (4, 2),
+                8,
+                4,
+                UpsampleFilter::Bilinear,
+                ChromaSiting::Centered
+            ),
+            bilinear
+        );
+
+        // Only the axis sampled by 2 is filtered, the other one replicates
+        #[rustfmt::skip]
+        let fancy = [
+            10, 10, 10, 10, 50, 50, 50, 50,
+            30, 30, 30, 30, 70, 70, 70, 70,
+            70, 70, 70, 70, 110, 110, 110, 110,
+            90, 90, 90, 90, 130, 130, 130, 130,
+        ];
+        assert_eq!(
+            up(
+                &plane,
+                2,
+                (4, 2),
+                8,
+                4,
+                UpsampleFilter::Fancy,
+                ChromaSiting::Centered
+            ),
+            fancy
+        );
+    }
+
+    #[test]
+    fn upsample_3x1_odd_width() {
+        let plane = [10i16, 40i16, 70i16];
+        let centered = ChromaSiting::Centered;
+        let cosited = ChromaSiting::Cosited;
+
+        let nearest_centered = [10i16, 10i16, 10i16, 40i16, 40i16, 40i16, 70i16];
+        assert_eq!(
+            up(&plane, 3, (3, 1), 7, 1, UpsampleFilter::Nearest, centered),
+            nearest_centered
+        );
+        assert_eq!(
+            up(&plane, 3, (3, 1), 7, 1, UpsampleFilter::Nearest, cosited),
+            [10i16, 10i16, 40i16, 40i16, 40i16, 70i16, 70i16]
+        );
+
+        assert_eq!(
+            up(&plane, 3, (3, 1), 7, 1, UpsampleFilter::Bilinear, centered),
+            [10i16, 10i16, 20i16, 30i16, 40i16, 50i16, 60i16]
+        );
+        let ramp = [10i16, 20i16, 30i16, 40i16, 50i16, 60i16, 70i16];
+        assert_eq!(
+            up(&plane, 3, (3, 1), 7, 1, UpsampleFilter::Bilinear, cosited),
+            ramp
+        );
+
+        // libjpeg replicates factors other than 2
+        assert_eq!(
+            up(&plane, 3, (3, 1), 7, 1, UpsampleFilter::Fancy, centered),
+            nearest_centered
+        );
+        assert_eq!(
+            up(&plane, 3, (3, 1), 7, 1, UpsampleFilter::Fancy, cosited),
+            ramp
+        );
+    }
+
+    #[test]
+    fn upsample_2x2_odd_size() {
+        let plane = [10i16, 30i16, 50i16, 70i16];
+        #[rustfmt::skip]
+        assert_eq!(
+            up(&plane, 2, (2, 2), 3, 3, UpsampleFilter::Nearest, ChromaSiting::Centered),
+            [
+                10, 10, 30,
+                10, 10, 30,
+                50, 50, 70,
+            ]
+        );
+        #[rustfmt::skip]
+        assert_eq!(
+            up(&plane, 2, (2, 2), 3, 3, UpsampleFilter::Fancy, ChromaSiting::Centered),
+            [
+                10, 15, 25,
+                20, 25, 35,
+                40, 45, 55,
+            ]
+        );
+    }
+}
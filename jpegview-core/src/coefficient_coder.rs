@@ -0,0 +1,485 @@
+// Adaptive binary range coder (LZMA style) for quantized zig-zag blocks.
+// Every block stores its DC difference, the count of non zero AC
+// coefficients and then run/level pairs, each symbol is an adaptive
+// Exp-Golomb code with contexts per channel and block size
+
+const PROBABILITY_BITS: u32 = 11;
+const PROBABILITY_ONE: u16 = 1 << PROBABILITY_BITS;
+const PROBABILITY_INIT: u16 = PROBABILITY_ONE / 2;
+const ADAPTATION_SHIFT: u32 = 5;
+
+const RANGE_TOP: u32 = 1 << 24;
+
+const GOLOMB_PREFIX_MAX: usize = 32;
+
+// Block sizes are 2^1 to 2^9, index 0 is unused
+const BLOCK_SIZE_COUNT: usize = 10;
+const CHANNEL_COUNT: usize = 3;
+
+// Zig-zag positions are grouped by their log2, a 512x512 block needs 18
+const BAND_COUNT: usize = 18;
+
+pub struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    output: Vec<u8>,
+}
+
+impl RangeEncoder {
+    pub fn new() -> RangeEncoder {
+        RangeEncoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            output: Vec::new(),
+        }
+    }
+    pub fn encode_bit(&mut self, probability: &mut u16, bit: bool) {
+        let bound = (self.range >> PROBABILITY_BITS) * *probability as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+            *probability -= *probability >> ADAPTATION_SHIFT;
+        } else {
+            self.range = bound;
+            *probability += (PROBABILITY_ONE - *probability) >> ADAPTATION_SHIFT;
+        }
+        self.normalize();
+    }
+    // Bits with a fixed probability of one half
+    pub fn encode_direct_bits(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            self.range >>= 1;
+            if (value >> i) & 1 == 1 {
+                self.low += self.range as u64;
+            }
+            self.normalize();
+        }
+    }
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.output
+    }
+    fn normalize(&mut self) {
+        while self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+    fn shift_low(&mut self) {
+        if self.low < 0xFF000000 || self.low > u32::MAX as u64 {
+            let carry = (self.low >> 32) as u8;
+            let mut temp = self.cache;
+            loop {
+                self.output.push(temp.wrapping_add(carry));
+                temp = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FFFFFF) << 8;
+    }
+}
+
+impl Default for RangeEncoder {
+    fn default() -> RangeEncoder {
+        RangeEncoder::new()
+    }
+}
+
+pub struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> RangeDecoder<'a> {
+        let mut range_decoder = RangeDecoder {
+            data,
+            position: 0,
+            range: u32::MAX,
+            code: 0,
+        };
+        for _ in 0..5 {
+            range_decoder.code = (range_decoder.code << 8) | range_decoder.next_byte() as u32;
+        }
+        range_decoder
+    }
+    pub fn decode_bit(&mut self, probability: &mut u16) -> bool {
+        let bound = (self.range >> PROBABILITY_BITS) * *probability as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            *probability += (PROBABILITY_ONE - *probability) >> ADAPTATION_SHIFT;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *probability -= *probability >> ADAPTATION_SHIFT;
+            true
+        };
+        self.normalize();
+        bit
+    }
+    pub fn decode_direct_bits(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit = self.code >= self.range;
+            if bit {
+                self.code -= self.range;
+            }
+            value = (value << 1) | bit as u32;
+            self.normalize();
+        }
+        value
+    }
+    // A truncated stream reads as zeros, the caller checks the result
+    pub fn is_overrun(&self) -> bool {
+        self.position > self.data.len()
+    }
+    fn normalize(&mut self) {
+        while self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte() as u32;
+        }
+    }
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.data.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        byte
+    }
+}
+
+// Exp-Golomb code with an adaptive prefix and an adaptive first mantissa bit
+#[derive(Clone)]
+struct GolombModel {
+    prefix: [u16; GOLOMB_PREFIX_MAX],
+    mantissa: [u16; GOLOMB_PREFIX_MAX + 1],
+}
+
+impl GolombModel {
+    fn new() -> GolombModel {
+        GolombModel {
+            prefix: [PROBABILITY_INIT; GOLOMB_PREFIX_MAX],
+            mantissa: [PROBABILITY_INIT; GOLOMB_PREFIX_MAX + 1],
+        }
+    }
+    fn encode(&mut self, range_encoder: &mut RangeEncoder, value: u32) {
+        let value = value as u64 + 1;
+        let length = (63 - value.leading_zeros()) as usize;
+
+        for i in 0..length {
+            range_encoder.encode_bit(&mut self.prefix[i], true);
+        }
+        if length < GOLOMB_PREFIX_MAX {
+            range_encoder.encode_bit(&mut self.prefix[length], false);
+        }
+
+        if length > 0 {
+            let high_bit = (value >> (length - 1)) & 1 == 1;
+            range_encoder.encode_bit(&mut self.mantissa[length], high_bit);
+            range_encoder.encode_direct_bits(value as u32, length as u32 - 1);
+        }
+    }
+    fn decode(&mut self, range_decoder: &mut RangeDecoder) -> u32 {
+        let mut length = 0;
+        while length < GOLOMB_PREFIX_MAX && range_decoder.decode_bit(&mut self.prefix[length]) {
+            length += 1;
+        }
+
+        let mut value = 1u64;
+        if length > 0 {
+            let high_bit = range_decoder.decode_bit(&mut self.mantissa[length]);
+            value = (value << 1) | high_bit as u64;
+            value = (value << (length - 1))
+                | range_decoder.decode_direct_bits(length as u32 - 1) as u64;
+        }
+        (value - 1).min(u32::MAX as u64) as u32
+    }
+}
+
+#[derive(Clone)]
+struct BlockContext {
+    dc: GolombModel,
+    count: GolombModel,
+    run: Vec<GolombModel>,
+    level: Vec<GolombModel>,
+}
+
+impl BlockContext {
+    fn new() -> BlockContext {
+        BlockContext {
+            dc: GolombModel::new(),
+            count: GolombModel::new(),
+            run: vec![GolombModel::new(); BAND_COUNT],
+            level: vec![GolombModel::new(); BAND_COUNT],
+        }
+    }
+}
+
+fn contexts() -> Vec<BlockContext> {
+    vec![BlockContext::new(); CHANNEL_COUNT * BLOCK_SIZE_COUNT]
+}
+
+fn context_index(channel: usize, block_size: usize) -> usize {
+    let block_size_log2 = block_size.trailing_zeros() as usize;
+    channel * BLOCK_SIZE_COUNT + block_size_log2.min(BLOCK_SIZE_COUNT - 1)
+}
+
+fn band(position: usize) -> usize {
+    ((usize::BITS - 1 - position.leading_zeros()) as usize).min(BAND_COUNT - 1)
+}
+
+pub struct CoefficientEncoder {
+    range_encoder: RangeEncoder,
+    contexts: Vec<BlockContext>,
+    last_dc: Vec<i32>,
+}
+
+impl CoefficientEncoder {
+    pub fn new() -> CoefficientEncoder {
+        CoefficientEncoder {
+            range_encoder: RangeEncoder::new(),
+            contexts: contexts(),
+            last_dc: vec![0i32; CHANNEL_COUNT * BLOCK_SIZE_COUNT],
+        }
+    }
+    // The DC is predicted from the last block of the same channel and size
+    pub fn add_block(&mut self, channel: usize, block_size: usize, dct_zig_zag: &[i32]) {
+        let index = context_index(channel, block_size);
+        let context = &mut self.contexts[index];
+        let range_encoder = &mut self.range_encoder;
+
+        let dc_difference = dct_zig_zag[0].wrapping_sub(self.last_dc[index]);
+        self.last_dc[index] = dct_zig_zag[0];
+
+        context
+            .dc
+            .encode(range_encoder, dc_difference.unsigned_abs());
+        if dc_difference != 0 {
+            range_encoder.encode_direct_bits((dc_difference < 0) as u32, 1);
+        }
+
+        let ac = &dct_zig_zag[1..];
+        let non_zero_count = ac.iter().filter(|&&coefficient| coefficient != 0).count();
+        context.count.encode(range_encoder, non_zero_count as u32);
+
+        let mut position = 1;
+        for (i, &coefficient) in ac.iter().enumerate() {
+            if coefficient == 0 {
+                continue;
+            }
+
+            let run = i + 1 - position;
+            context.run[band(position)].encode(range_encoder, run as u32);
+            position += run;
+
+            context.level[band(position)].encode(range_encoder, coefficient.unsigned_abs() - 1);
+            range_encoder.encode_direct_bits((coefficient < 0) as u32, 1);
+            position += 1;
+        }
+    }
+    pub fn finish(self) -> Vec<u8> {
+        self.range_encoder.finish()
+    }
+}
+
+impl Default for CoefficientEncoder {
+    fn default() -> CoefficientEncoder {
+        CoefficientEncoder::new()
+    }
+}
+
+pub struct CoefficientDecoder<'a> {
+    range_decoder: RangeDecoder<'a>,
+    contexts: Vec<BlockContext>,
+    last_dc: Vec<i32>,
+}
+
+impl<'a> CoefficientDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> CoefficientDecoder<'a> {
+        CoefficientDecoder {
+            range_decoder: RangeDecoder::new(data),
+            contexts: contexts(),
+            last_dc: vec![0i32; CHANNEL_COUNT * BLOCK_SIZE_COUNT],
+        }
+    }
+    pub fn is_overrun(&self) -> bool {
+        self.range_decoder.is_overrun()
+    }
+    // Blocks must be read in the same order and sizes they were added
+    pub fn read_block(
+        &mut self,
+        channel: usize,
+        block_size: usize,
+    ) -> Result<Vec<i32>, &'static str> {
+        let index = context_index(channel, block_size);
+        let context = &mut self.contexts[index];
+        let range_decoder = &mut self.range_decoder;
+
+        let block_length = block_size * block_size;
+        let mut dct_zig_zag = vec![0i32; block_length];
+
+        let mut dc_difference = context.dc.decode(range_decoder) as i32;
+        if dc_difference != 0 && range_decoder.decode_direct_bits(1) == 1 {
+            dc_difference = dc_difference.wrapping_neg();
+        }
+        dct_zig_zag[0] = self.last_dc[index].wrapping_add(dc_difference);
+        self.last_dc[index] = dct_zig_zag[0];
+
+        let non_zero_count = context.count.decode(range_decoder) as usize;
+        if non_zero_count >= block_length {
+            return Err("Too many coefficients in block");
+        }
+        // Past the end everything decodes as zeros, stop as soon as it is
+        if range_decoder.is_overrun() {
+            return Err("Coefficient data is truncated");
+        }
+
+        let mut position = 1;
+        for _ in 0..non_zero_count {
+            let run = context.run[band(position)].decode(range_decoder) as usize;
+            position += run;
+            if position >= block_length {
+                return Err("Coefficient run out of block");
+            }
+
+            // A magnitude of 2^31 only round trips as i32::MIN
+            let level = context.level[band(position)].decode(range_decoder) as u64 + 1;
+            let level = level.min(1 << 31) as u32 as i32;
+            dct_zig_zag[position] = if range_decoder.decode_direct_bits(1) == 1 {
+                level.wrapping_neg()
+            } else {
+                level
+            };
+            position += 1;
+
+            if range_decoder.is_overrun() {
+                return Err("Coefficient data is truncated");
+            }
+        }
+
+        Ok(dct_zig_zag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Blocks of every channel and a few sizes: empty ones, long zero runs
+    // ending in a coefficient, dense small levels and the i32 extremes
+    fn blocks() -> Vec<(usize, usize, Vec<i32>)> {
+        let mut seed = 0x2545F491u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+
+        let mut blocks = Vec::new();
+        for i in 0..60 {
+            let channel = i % 3;
+            let block_size = 2 << (i % 5);
+            let length = block_size * block_size;
+
+            let mut dct_zig_zag = vec![0i32; length];
+            match i % 4 {
+                0 => dct_zig_zag[0] = -1024 + i as i32,
+                1 => {
+                    dct_zig_zag[0] = (1 << 20) * if i % 8 == 1 { 1 } else { -1 };
+                    dct_zig_zag[length - 1] = -3;
+                }
+                2 => {
+                    for coefficient in dct_zig_zag.iter_mut() {
+                        *coefficient = (random() % 7) as i32 - 3;
+                    }
+                }
+                _ => {
+                    dct_zig_zag[0] = i32::MIN;
+                    dct_zig_zag[1] = i32::MAX;
+                    dct_zig_zag[length / 2] = i32::MIN;
+                    dct_zig_zag[length - 1] = 65536;
+                }
+            }
+            blocks.push((channel, block_size, dct_zig_zag));
+        }
+        blocks
+    }
+
+    fn encode(blocks: &[(usize, usize, Vec<i32>)]) -> Vec<u8> {
+        let mut coefficient_encoder = CoefficientEncoder::new();
+        for (channel, block_size, dct_zig_zag) in blocks {
+            coefficient_encoder.add_block(*channel, *block_size, dct_zig_zag);
+        }
+        coefficient_encoder.finish()
+    }
+
+    #[test]
+    fn round_trip_mixed_block_sizes() {
+        let blocks = blocks();
+        let data = encode(&blocks);
+
+        let mut coefficient_decoder = CoefficientDecoder::new(&data);
+        for (channel, block_size, dct_zig_zag) in &blocks {
+            assert_eq!(
+                &coefficient_decoder
+                    .read_block(*channel, *block_size)
+                    .unwrap(),
+                dct_zig_zag
+            );
+        }
+        assert!(!coefficient_decoder.is_overrun());
+    }
+
+    #[test]
+    fn round_trip_empty_blocks() {
+        let blocks = vec![(0, 8, vec![0i32; 64]); 100];
+        let data = encode(&blocks);
+
+        let mut coefficient_decoder = CoefficientDecoder::new(&data);
+        for (channel, block_size, dct_zig_zag) in &blocks {
+            assert_eq!(
+                &coefficient_decoder
+                    .read_block(*channel, *block_size)
+                    .unwrap(),
+                dct_zig_zag
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_data_fails() {
+        let blocks = blocks();
+        let data = encode(&blocks);
+
+        let mut coefficient_decoder = CoefficientDecoder::new(&data[..data.len() / 2]);
+        assert!(blocks
+            .iter()
+            .map(|(channel, block_size, _)| coefficient_decoder.read_block(*channel, *block_size))
+            .any(|result| result.is_err()));
+    }
+
+    #[test]
+    fn run_out_of_block_fails() {
+        // The run to the last coefficient of a 4x4 block goes past a 2x2 one
+        let mut dct_zig_zag = vec![0i32; 16];
+        dct_zig_zag[15] = 1;
+        let data = encode(&[(0, 4, dct_zig_zag)]);
+
+        let mut coefficient_decoder = CoefficientDecoder::new(&data);
+        assert!(coefficient_decoder.read_block(0, 2).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+#![allow(clippy::excessive_precision)]
+
+use crate::my_image;
+
+// Transforms that decorrelate RGB into a luma like component and two chroma
+// like ones, with the chroma centered on 128. The reversible YCoCg-R and RCT
+// keep their 9 bit chroma in planes wider than a byte so they round trip
+// exactly
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorTransform {
+    Ycbcr,
+    Ycocg,
+    YcocgR,
+    Rct,
+    // JPEG 2000's irreversible transform, BT.601 full range YCbCr
+    Ict,
+    Cielab,
+    Oklab,
+}
+
+impl ColorTransform {
+    pub const ALL: [ColorTransform; 7] = [
+        ColorTransform::Ycbcr,
+        ColorTransform::Ycocg,
+        ColorTransform::YcocgR,
+        ColorTransform::Rct,
+        ColorTransform::Ict,
+        ColorTransform::Cielab,
+        ColorTransform::Oklab,
+    ];
+}
+
+// Luma weights of red and blue, green takes the rest
+#[derive(Clone, Copy, PartialEq)]
+pub enum YcbcrMatrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl YcbcrMatrix {
+    pub const ALL: [YcbcrMatrix; 3] = [YcbcrMatrix::Bt601, YcbcrMatrix::Bt709, YcbcrMatrix::Bt2020];
+
+    fn weights(self) -> (f32, f32) {
+        match self {
+            YcbcrMatrix::Bt601 => (0.299f32, 0.114f32),
+            YcbcrMatrix::Bt709 => (0.2126f32, 0.0722f32),
+            YcbcrMatrix::Bt2020 => (0.2627f32, 0.0593f32),
+        }
+    }
+}
+
+// Full range uses every level like jpg, limited keeps luma in 16-235 and
+// chroma in 16-240 like video
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorRange {
+    Full,
+    Limited,
+}
+
+impl ColorRange {
+    pub const ALL: [ColorRange; 2] = [ColorRange::Full, ColorRange::Limited];
+
+    // Luma scale and offset and chroma scale from full range
+    fn scales(self) -> (f32, f32, f32) {
+        match self {
+            ColorRange::Full => (1.0f32, 0.0f32, 1.0f32),
+            ColorRange::Limited => (219.0f32 / 255.0f32, 16.0f32, 224.0f32 / 255.0f32),
+        }
+    }
+}
+
+// The matrix and range only apply to YCbCr
+#[derive(Clone, Copy, PartialEq)]
+pub struct ColorSpace {
+    pub transform: ColorTransform,
+    pub matrix: YcbcrMatrix,
+    pub range: ColorRange,
+}
+
+// How far colors move going to the transform and back once both are stored
+// in their planes
+pub struct RoundTripError {
+    pub max: u8,
+    pub mean: f32,
+}
+
+impl ColorSpace {
+    pub fn new(transform: ColorTransform, matrix: YcbcrMatrix, range: ColorRange) -> ColorSpace {
+        ColorSpace {
+            transform,
+            matrix,
+            range,
+        }
+    }
+
+    pub fn forward(self, r: f32, g: f32, b: f32) -> [f32; 3] {
+        match self.transform {
+            ColorTransform::Ycbcr => self.to_ycbcr(r, g, b),
+            ColorTransform::Ycocg => [
+                r * 0.25f32 + g * 0.5f32 + b * 0.25f32,
+                (r - b) * 0.5f32 + 128.0f32,
+                (g * 2.0f32 - r - b) * 0.25f32 + 128.0f32,
+            ],
+            ColorTransform::YcocgR => {
+                let (r, g, b) = (r as i32, g as i32, b as i32);
+                let co = r - b;
+                let t = b + (co >> 1);
+                let cg = g - t;
+                let y = t + (cg >> 1);
+                [y as f32, (co + 128) as f32, (cg + 128) as f32]
+            }
+            ColorTransform::Rct => {
+                let (r, g, b) = (r as i32, g as i32, b as i32);
+                let y = (r + 2 * g + b) >> 2;
+                [y as f32, (b - g + 128) as f32, (r - g + 128) as f32]
+            }
+            ColorTransform::Ict => ColorSpace::default().to_ycbcr(r, g, b),
+            ColorTransform::Cielab => {
+                let [l, a, b] = rgb_to_cielab(r, g, b);
+                [l * 2.55f32, a + 128.0f32, b + 128.0f32]
+            }
+            ColorTransform::Oklab => {
+                let [l, a, b] = rgb_to_oklab(r, g, b);
+                [
+                    l * 255.0f32,
+                    a * OKLAB_CHROMA_SCALE + 128.0f32,
+                    b * OKLAB_CHROMA_SCALE + 128.0f32,
+                ]
+            }
+        }
+    }
+    pub fn inverse(self, c0: f32, c1: f32, c2: f32) -> [f32; 3] {
+        match self.transform {
+            ColorTransform::Ycbcr => self.to_rgb(c0, c1, c2),
+            ColorTransform::Ycocg => {
+                let (co, cg) = (c1 - 128.0f32, c2 - 128.0f32);
+                let t = c0 - cg;
+                [t + co, c0 + cg, t - co]
+            }
+            ColorTransform::YcocgR => {
+                let (y, co, cg) = (c0.round() as i32, wide_chroma(c1), wide_chroma(c2));
+                let t = y - (cg >> 1);
+                let g = cg + t;
+                let b = t - (co >> 1);
+                [(b + co) as f32, g as f32, b as f32]
+            }
+            ColorTransform::Rct => {
+                let (y, cb, cr) = (c0.round() as i32, wide_chroma(c1), wide_chroma(c2));
+                let g = y - ((cb + cr) >> 2);
+                [(cr + g) as f32, g as f32, (cb + g) as f32]
+            }
+            ColorTransform::Ict => ColorSpace::default().to_rgb(c0, c1, c2),
+            ColorTransform::Cielab => cielab_to_rgb(c0 / 2.55f32, c1 - 128.0f32, c2 - 128.0f32),
+            ColorTransform::Oklab => oklab_to_rgb(
+                c0 / 255.0f32,
+                (c1 - 128.0f32) / OKLAB_CHROMA_SCALE,
+                (c2 - 128.0f32) / OKLAB_CHROMA_SCALE,
+            ),
+        }
+    }
+
+    // Chroma is centered on 128
+    pub fn to_ycbcr(self, r: f32, g: f32, b: f32) -> [f32; 3] {
+        let (kr, kb) = self.matrix.weights();
+        let (luma_scale, luma_offset, chroma_scale) = self.range.scales();
+
+        let y = kr * r + (1.0f32 - kr - kb) * g + kb * b;
+        let cb = (b - y) / (2.0f32 * (1.0f32 - kb));
+        let cr = (r - y) / (2.0f32 * (1.0f32 - kr));
+
+        [
+            y * luma_scale + luma_offset,
+            cb * chroma_scale + 128.0f32,
+            cr * chroma_scale + 128.0f32,
+        ]
+    }
+    pub fn to_rgb(self, y: f32, cb: f32, cr: f32) -> [f32; 3] {
+        let (kr, kb) = self.matrix.weights();
+        let (luma_scale, luma_offset, chroma_scale) = self.range.scales();
+
+        let y = (y - luma_offset) / luma_scale;
+        let cb = (cb - 128.0f32) / chroma_scale;
+        let cr = (cr - 128.0f32) / chroma_scale;
+
+        let r = y + 2.0f32 * (1.0f32 - kr) * cr;
+        let b = y + 2.0f32 * (1.0f32 - kb) * cb;
+        let g = (y - kr * r - kb * b) / (1.0f32 - kr - kb);
+
+        [r, g, b]
+    }
+
+    // Range of the planes of every component
+    pub fn component_ranges(self) -> [(i16, i16); 3] {
+        match self.transform {
+            ColorTransform::YcocgR | ColorTransform::Rct => {
+                [my_image::BYTE_RANGE, WIDE_CHROMA_RANGE, WIDE_CHROMA_RANGE]
+            }
+            _ => [my_image::BYTE_RANGE; 3],
+        }
+    }
+
+    // Over every fifth level of each channel, as the pipeline rounds them
+    pub fn round_trip_error(self) -> RoundTripError {
+        let ranges = self.component_ranges();
+
+        let mut max = 0u8;
+        let mut sum = 0u64;
+        let mut count = 0u64;
+
+        for r in (0..=255u8).step_by(5) {
+            for g in (0..=255u8).step_by(5) {
+                for b in (0..=255u8).step_by(5) {
+                    let components = self.forward(r as f32, g as f32, b as f32);
+                    let components: Vec<f32> = components
+                        .iter()
+                        .zip(ranges)
+                        .map(|(&value, range)| {
+                            my_image::min_max_component(value + 0.5f32, range) as f32
+                        })
+                        .collect();
+                    let rgb = self
+                        .inverse(components[0], components[1], components[2])
+                        .map(|value| my_image::min_max_color(value + 0.5f32));
+
+                    for (original, result) in [r, g, b].iter().zip(rgb) {
+                        let error = original.abs_diff(result);
+                        max = max.max(error);
+                        sum += error as u64;
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        RoundTripError {
+            max,
+            mean: sum as f32 / count as f32,
+        }
+    }
+}
+
+// What jpg files use
+impl Default for ColorSpace {
+    fn default() -> ColorSpace {
+        ColorSpace::new(ColorTransform::Ycbcr, YcbcrMatrix::Bt601, ColorRange::Full)
+    }
+}
+
+// OKLab's a and b stay within 0.32 of zero for sRGB colors
+const OKLAB_CHROMA_SCALE: f32 = 400.0f32;
+
+// Differences of two bytes centered on 128
+const WIDE_CHROMA_RANGE: (i16, i16) = (-127, 383);
+
+fn wide_chroma(value: f32) -> i32 {
+    value.round() as i32 - 128
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    let value = value / 255.0f32;
+    if value <= 0.04045f32 {
+        value / 12.92f32
+    } else {
+        ((value + 0.055f32) / 1.055f32).powf(2.4f32)
+    }
+}
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0f32, 1.0f32);
+    let value = if value <= 0.0031308f32 {
+        value * 12.92f32
+    } else {
+        1.055f32 * value.powf(1.0f32 / 2.4f32) - 0.055f32
+    };
+    value * 255.0f32
+}
+
+// D65 white
+const CIELAB_WHITE: [f32; 3] = [0.95047f32, 1.0f32, 1.08883f32];
+const CIELAB_DELTA: f32 = 6.0f32 / 29.0f32;
+
+fn cielab_f(t: f32) -> f32 {
+    if t > CIELAB_DELTA * CIELAB_DELTA * CIELAB_DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0f32 * CIELAB_DELTA * CIELAB_DELTA) + 4.0f32 / 29.0f32
+    }
+}
+fn cielab_f_inverse(t: f32) -> f32 {
+    if t > CIELAB_DELTA {
+        t * t * t
+    } else {
+        3.0f32 * CIELAB_DELTA * CIELAB_DELTA * (t - 4.0f32 / 29.0f32)
+    }
+}
+
+// L from 0 to 100, a and b within 110 of zero for sRGB colors
+#[rustfmt::skip]
+fn rgb_to_cielab(r: f32, g: f32, b: f32) -> [f32; 3] {
+    let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+
+    let x = (0.4124564f32 * r) + (0.3575761f32 * g) + (0.1804375f32 * b);
+    let y = (0.2126729f32 * r) + (0.7151522f32 * g) + (0.0721750f32 * b);
+    let z = (0.0193339f32 * r) + (0.1191920f32 * g) + (0.9503041f32 * b);
+
+    let fx = cielab_f(x / CIELAB_WHITE[0]);
+    let fy = cielab_f(y / CIELAB_WHITE[1]);
+    let fz = cielab_f(z / CIELAB_WHITE[2]);
+
+    [116.0f32 * fy - 16.0f32, 500.0f32 * (fx - fy), 200.0f32 * (fy - fz)]
+}
+#[rustfmt::skip]
+fn cielab_to_rgb(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let fy = (l + 16.0f32) / 116.0f32;
+
+    let x = cielab_f_inverse(fy + a / 500.0f32) * CIELAB_WHITE[0];
+    let y = cielab_f_inverse(fy) * CIELAB_WHITE[1];
+    let z = cielab_f_inverse(fy - b / 200.0f32) * CIELAB_WHITE[2];
+
+    [
+        linear_to_srgb(( 3.2404542f32 * x) + (-1.5371385f32 * y) + (-0.4985314f32 * z)),
+        linear_to_srgb((-0.9692660f32 * x) + ( 1.8760108f32 * y) + ( 0.0415560f32 * z)),
+        linear_to_srgb(( 0.0556434f32 * x) + (-0.2040259f32 * y) + ( 1.0572252f32 * z)),
+    ]
+}
+
+// Björn Ottosson's OKLab, L from 0 to 1
+#[rustfmt::skip]
+fn rgb_to_oklab(r: f32, g: f32, b: f32) -> [f32; 3] {
+    let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+
+    let l = ((0.4122214708f32 * r) + (0.5363325363f32 * g) + (0.0514459929f32 * b)).cbrt();
+    let m = ((0.2119034982f32 * r) + (0.6806995451f32 * g) + (0.1073969566f32 * b)).cbrt();
+    let s = ((0.0883024619f32 * r) + (0.2817188376f32 * g) + (0.6299787005f32 * b)).cbrt();
+
+    [
+        (0.2104542553f32 * l) + ( 0.7936177850f32 * m) + (-0.0040720468f32 * s),
+        (1.9779984951f32 * l) + (-2.4285922050f32 * m) + ( 0.4505937099f32 * s),
+        (0.0259040371f32 * l) + ( 0.7827717662f32 * m) + (-0.8086757660f32 * s),
+    ]
+}
+#[rustfmt::skip]
+fn oklab_to_rgb(l: f32, a: f32, b: f32) -> [f32; 3] {
+    let l_ = l + ( 0.3963377774f32 * a) + ( 0.2158037573f32 * b);
+    let m_ = l + (-0.1055613458f32 * a) + (-0.0638541728f32 * b);
+    let s_ = l + (-0.0894841775f32 * a) + (-1.2914855480f32 * b);
+
+    let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+
+    [
+        linear_to_srgb(( 4.0767416621f32 * l) + (-3.3077115913f32 * m) + ( 0.2309699292f32 * s)),
+        linear_to_srgb((-1.2684380046f32 * l) + ( 2.6097574011f32 * m) + (-0.3413193965f32 * s)),
+        linear_to_srgb((-0.0041960863f32 * l) + (-0.7034186147f32 * m) + ( 1.7076147010f32 * s)),
+    ]
+}
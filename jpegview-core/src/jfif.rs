@@ -0,0 +1,625 @@
+#![allow(clippy::needless_range_loop)]
+
+use crate::{
+    chroma::{self, ChromaSiting, Subsampling},
+    color_space::ColorSpace,
+    jpeg::{self, Jpeg, JpegSteps},
+    my_image::MyImage,
+    quad_mind,
+};
+use std::{fs::File, io::Write, path::Path, sync::Arc};
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOF0: u8 = 0xC0;
+const DHT: u8 = 0xC4;
+const DQT: u8 = 0xDB;
+const SOS: u8 = 0xDA;
+const APP0: u8 = 0xE0;
+const APP14: u8 = 0xEE;
+
+// JPEG Annex K.3
+#[rustfmt::skip]
+const DC_LUMA_BITS: [u8; 16] = [
+    0x00, 0x01, 0x05, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+#[rustfmt::skip]
+const DC_CHROMA_BITS: [u8; 16] = [
+    0x00, 0x03, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+#[rustfmt::skip]
+const DC_VALUES: [u8; 12] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B,
+];
+#[rustfmt::skip]
+const AC_LUMA_BITS: [u8; 16] = [
+    0x00, 0x02, 0x01, 0x03, 0x03, 0x02, 0x04, 0x03, 0x05, 0x05, 0x04, 0x04, 0x00, 0x00, 0x01, 0x7D,
+];
+#[rustfmt::skip]
+const AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+#[rustfmt::skip]
+const AC_CHROMA_BITS: [u8; 16] = [
+    0x00, 0x02, 0x01, 0x02, 0x04, 0x04, 0x03, 0x04, 0x07, 0x05, 0x04, 0x04, 0x00, 0x01, 0x02, 0x77,
+];
+#[rustfmt::skip]
+const AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+    0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+    0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+pub struct HuffmanTable {
+    pub bits: [u8; 16],
+    pub values: Vec<u8>,
+
+    codes: Vec<(u16, u8)>,
+}
+
+impl HuffmanTable {
+    pub fn new(bits: &[u8; 16], values: &[u8]) -> HuffmanTable {
+        let mut codes = vec![(0u16, 0u8); 256];
+
+        let mut code = 0u16;
+        let mut k = 0;
+        for length in 0..16 {
+            for _ in 0..bits[length] {
+                codes[values[k] as usize] = (code, length as u8 + 1);
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+
+        HuffmanTable {
+            bits: *bits,
+            values: values.to_vec(),
+
+            codes,
+        }
+    }
+    // Port of libjpeg jpeg_gen_optimal_table, codes are limited to 16 bits
+    // and the all ones code is never used
+    pub fn optimal(frequencies: &[u32; 256]) -> HuffmanTable {
+        let mut freq = [0i64; 257];
+        let mut code_size = [0usize; 257];
+        let mut others = [-1i32; 257];
+
+        for i in 0..256 {
+            freq[i] = frequencies[i] as i64;
+        }
+        freq[256] = 1;
+
+        loop {
+            let mut c1 = -1i32;
+            let mut v = i64::MAX;
+            for i in 0..257 {
+                if freq[i] > 0 && freq[i] <= v {
+                    v = freq[i];
+                    c1 = i as i32;
+                }
+            }
+
+            let mut c2 = -1i32;
+            v = i64::MAX;
+            for i in 0..257 {
+                if freq[i] > 0 && freq[i] <= v && i as i32 != c1 {
+                    v = freq[i];
+                    c2 = i as i32;
+                }
+            }
+
+            if c2 < 0 {
+                break;
+            }
+
+            let (mut c1, mut c2) = (c1 as usize, c2 as usize);
+
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+
+            code_size[c1] += 1;
+            while others[c1] >= 0 {
+                c1 = others[c1] as usize;
+                code_size[c1] += 1;
+            }
+
+            others[c1] = c2 as i32;
+
+            code_size[c2] += 1;
+            while others[c2] >= 0 {
+                c2 = others[c2] as usize;
+                code_size[c2] += 1;
+            }
+        }
+
+        let mut bits = [0u8; 33];
+        for i in 0..257 {
+            if code_size[i] > 0 {
+                bits[code_size[i]] += 1;
+            }
+        }
+
+        for i in (17..33).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+
+        let mut i = 16;
+        while bits[i] == 0 {
+            i -= 1;
+        }
+        bits[i] -= 1;
+
+        let mut values = Vec::new();
+        for length in 1..33 {
+            for symbol in 0..256 {
+                if code_size[symbol] == length {
+                    values.push(symbol as u8);
+                }
+            }
+        }
+
+        let mut table_bits = [0u8; 16];
+        table_bits.copy_from_slice(&bits[1..17]);
+
+        HuffmanTable::new(&table_bits, &values)
+    }
+}
+
+struct Symbol {
+    table: usize,
+    value: u8,
+    extra: u16,
+    extra_length: u8,
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u32,
+    length: u32,
+}
+
+impl BitWriter {
+    fn new(data: Vec<u8>) -> BitWriter {
+        BitWriter {
+            data,
+            buffer: 0,
+            length: 0,
+        }
+    }
+    fn write_bits(&mut self, bits: u16, length: u8) {
+        self.buffer = (self.buffer << length) | (bits as u32 & ((1 << length) - 1));
+        self.length += length as u32;
+
+        while self.length >= 8 {
+            let byte = (self.buffer >> (self.length - 8)) as u8;
+            self.data.push(byte);
+            if byte == 0xFF {
+                self.data.push(0x00);
+            }
+            self.length -= 8;
+        }
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.length > 0 {
+            let padding = 8 - self.length as u8;
+            self.write_bits(0xFF, padding);
+        }
+        self.data
+    }
+}
+
+struct Component {
+    id: u8,
+    table: usize,
+
+    h: usize,
+    v: usize,
+
+    width: usize,
+    plane: Vec<f32>,
+}
+
+pub fn encode_jfif(
+    my_image: &MyImage,
+    jpeg: &Jpeg,
+    use_color_transform: bool,
+    subsampling: Subsampling,
+    use_optimized_huffman: bool,
+) -> Result<Vec<u8>, &'static str> {
+    if jpeg.block_size != 8 {
+        return Err("Baseline JPEG only supports 8x8 blocks");
+    }
+    if my_image.width == 0 || my_image.height == 0 {
+        return Err("Image is empty");
+    }
+    if my_image.width > 0xFFFF || my_image.height > 0xFFFF {
+        return Err("Image is too large for JPEG");
+    }
+
+    let q_tables = q_tables(jpeg);
+
+    // Chroma is always sampled 1x1 and the luma takes the factors
+    let (h_max, v_max) = if use_color_transform {
+        subsampling.factors()
+    } else {
+        (1, 1)
+    };
+    if h_max * v_max + 2 > 10 {
+        return Err("Subsampling needs more than 10 blocks per MCU");
+    }
+
+    let mcu_width_count = my_image.width.div_ceil(8 * h_max);
+    let mcu_height_count = my_image.height.div_ceil(8 * v_max);
+
+    let full_width = mcu_width_count * 8 * h_max;
+    let full_height = mcu_height_count * 8 * v_max;
+
+    let full_planes = image_to_planes(my_image, use_color_transform, full_width, full_height);
+
+    let component_ids: [u8; 3] = if use_color_transform {
+        [1, 2, 3]
+    } else {
+        *b"RGB"
+    };
+
+    let mut components = Vec::with_capacity(3);
+    for (i, full_plane) in full_planes.into_iter().enumerate() {
+        let (h, v) = if i == 0 { (h_max, v_max) } else { (1, 1) };
+
+        let width = mcu_width_count * 8 * h;
+
+        components.push(Component {
+            id: component_ids[i],
+            table: usize::from(i != 0),
+
+            h,
+            v,
+
+            width,
+            plane: chroma::downsample(
+                &full_plane,
+                full_width,
+                full_width,
+                full_height,
+                (h_max / h, v_max / v),
+                jpeg.chroma_filters.downsample,
+                // Decoders expect the samples centered
+                ChromaSiting::Centered,
+            ),
+        });
+    }
+
+    let mut jpeg_steps = JpegSteps::new(jpeg, full_width);
+    if !jpeg.use_fast_dct {
+        jpeg_steps.dct_table = Some(Arc::new(jpeg::generate_dct_table(8)));
+        jpeg_steps.alpha_table = Some(Arc::new(jpeg::generate_alpha_table(8)));
+    }
+
+    let zig_zag_table = quad_mind::generate_zig_zag_table(8);
+
+    let q_values: Vec<Vec<f32>> = q_tables
+        .iter()
+        .map(|q_table| q_table.iter().map(|&value| value as f32).collect())
+        .collect();
+
+    let mut symbols = Vec::new();
+    let mut last_dc = [0i32; 3];
+
+    for my in 0..mcu_height_count {
+        for mx in 0..mcu_width_count {
+            for (i, component) in components.iter().enumerate() {
+                for by in 0..component.v {
+                    for bx in 0..component.h {
+                        let start_x = (mx * component.h + bx) * 8;
+                        let start_y = (my * component.v + by) * 8;
+
+                        let mut image_block = vec![0.0f32; 64];
+                        for y in 0..8 {
+                            for x in 0..8 {
+                                image_block[y * 8 + x] =
+                                    component.plane[(start_y + y) * component.width + start_x + x];
+                            }
+                        }
+
+                        let mut dct_matrix = jpeg_steps.dct_function(&image_block);
+                        for k in 0..64 {
+                            dct_matrix[k] /= q_values[component.table][k];
+                        }
+
+                        if jpeg.use_rdo_quantization {
+                            jpeg::rdo_quantize(
+                                &mut dct_matrix,
+                                &q_values[component.table],
+                                8,
+                                jpeg.rdo_lambda,
+                            );
+                        } else {
+                            for value in dct_matrix.iter_mut() {
+                                *value = jpeg.quantizer.quantize(*value);
+                            }
+                        }
+
+                        // The reconstruction bias can not be signaled, decoders
+                        // always reconstruct at the middle. Baseline DC goes to
+                        // 2047 and AC to 1023
+                        let mut dct_zig_zag = [0i32; 64];
+                        for k in 0..64 {
+                            let limit = if k == 0 { 2047 } else { 1023 };
+                            let value = dct_matrix[k] as i32;
+                            dct_zig_zag[zig_zag_table[k]] = value.clamp(-limit, limit);
+                        }
+
+                        push_block_symbols(
+                            &mut symbols,
+                            &dct_zig_zag,
+                            &mut last_dc[i],
+                            component.table,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    let huffman_tables = if use_optimized_huffman {
+        let mut frequencies = [[0u32; 256]; 4];
+        for symbol in &symbols {
+            frequencies[symbol.table][symbol.value as usize] += 1;
+        }
+
+        [
+            HuffmanTable::optimal(&frequencies[0]),
+            HuffmanTable::optimal(&frequencies[1]),
+            HuffmanTable::optimal(&frequencies[2]),
+            HuffmanTable::optimal(&frequencies[3]),
+        ]
+    } else {
+        [
+            HuffmanTable::new(&DC_LUMA_BITS, &DC_VALUES),
+            HuffmanTable::new(&DC_CHROMA_BITS, &DC_VALUES),
+            HuffmanTable::new(&AC_LUMA_BITS, &AC_LUMA_VALUES),
+            HuffmanTable::new(&AC_CHROMA_BITS, &AC_CHROMA_VALUES),
+        ]
+    };
+
+    let mut data = vec![0xFF, SOI];
+
+    if use_color_transform {
+        write_segment(
+            &mut data,
+            APP0,
+            &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0],
+        );
+    } else {
+        // Adobe marker with transform 0 so decoders do not convert from YCbCr
+        write_segment(
+            &mut data,
+            APP14,
+            &[b'A', b'd', b'o', b'b', b'e', 0, 100, 0, 0, 0, 0, 0],
+        );
+    }
+
+    let mut dqt = Vec::with_capacity(130);
+    for (i, q_table) in q_tables.iter().enumerate() {
+        dqt.push(i as u8);
+        let mut q_table_zig_zag = [0u8; 64];
+        for k in 0..64 {
+            q_table_zig_zag[zig_zag_table[k]] = q_table[k];
+        }
+        dqt.extend_from_slice(&q_table_zig_zag);
+    }
+    write_segment(&mut data, DQT, &dqt);
+
+    let mut sof = vec![8];
+    sof.extend_from_slice(&(my_image.height as u16).to_be_bytes());
+    sof.extend_from_slice(&(my_image.width as u16).to_be_bytes());
+    sof.push(3);
+    for component in &components {
+        sof.push(component.id);
+        sof.push(((component.h << 4) | component.v) as u8);
+        sof.push(component.table as u8);
+    }
+    write_segment(&mut data, SOF0, &sof);
+
+    let mut dht = Vec::new();
+    for (i, huffman_table) in huffman_tables.iter().enumerate() {
+        dht.push((((i / 2) << 4) | (i % 2)) as u8);
+        dht.extend_from_slice(&huffman_table.bits);
+        dht.extend_from_slice(&huffman_table.values);
+    }
+    write_segment(&mut data, DHT, &dht);
+
+    let mut sos = vec![3];
+    for component in &components {
+        sos.push(component.id);
+        sos.push(((component.table << 4) | component.table) as u8);
+    }
+    sos.extend_from_slice(&[0, 63, 0]);
+    write_segment(&mut data, SOS, &sos);
+
+    let mut bit_writer = BitWriter::new(data);
+    for symbol in &symbols {
+        let (code, length) = huffman_tables[symbol.table].codes[symbol.value as usize];
+        bit_writer.write_bits(code, length);
+        if symbol.extra_length > 0 {
+            bit_writer.write_bits(symbol.extra, symbol.extra_length);
+        }
+    }
+
+    let mut data = bit_writer.finish();
+    data.extend_from_slice(&[0xFF, EOI]);
+
+    Ok(data)
+}
+
+pub fn save_jfif(
+    path: &Path,
+    my_image: &MyImage,
+    jpeg: &Jpeg,
+    use_color_transform: bool,
+    subsampling: Subsampling,
+    use_optimized_huffman: bool,
+) -> Result<usize, &'static str> {
+    let data = encode_jfif(
+        my_image,
+        jpeg,
+        use_color_transform,
+        subsampling,
+        use_optimized_huffman,
+    )?;
+
+    let mut file = File::create(path).map_err(|_| "Could not create file")?;
+    file.write_all(&data)
+        .map_err(|_| "Could not write to file")?;
+
+    Ok(data.len())
+}
+
+pub fn q_tables(jpeg: &Jpeg) -> [[u8; 64]; 2] {
+    let (q_matrix_luma, q_matrix_chroma) = jpeg.base_q_matrices(8);
+    let factor = jpeg.quality_factor(jpeg.quality);
+
+    let mut q_tables = [[0u8; 64]; 2];
+    for (q_table, mut q_matrix) in q_tables.iter_mut().zip([q_matrix_luma, q_matrix_chroma]) {
+        jpeg::apply_q_matrix_factor(&mut q_matrix, 8, factor);
+
+        for i in 0..64 {
+            q_table[i] = q_matrix[i].round().clamp(1.0f32, 255.0f32) as u8;
+        }
+    }
+    q_tables
+}
+
+fn image_to_planes(
+    my_image: &MyImage,
+    use_color_transform: bool,
+    width: usize,
+    height: usize,
+) -> Vec<Vec<f32>> {
+    let mut planes = vec![vec![0.0f32; width * height]; 3];
+    for y in 0..height {
+        for x in 0..width {
+            let index_original =
+                (y.min(my_image.height - 1) * my_image.width + x.min(my_image.width - 1)) * 3;
+            let index_plane = y * width + x;
+
+            let r = my_image.original_image[index_original] as f32;
+            let g = my_image.original_image[index_original + 1] as f32;
+            let b = my_image.original_image[index_original + 2] as f32;
+
+            // Decoders expect BT.601 full range whatever the image was encoded with
+            if use_color_transform {
+                let ycbcr = ColorSpace::default().to_ycbcr(r, g, b);
+                planes[0][index_plane] = ycbcr[0] - 128.0f32;
+                planes[1][index_plane] = ycbcr[1] - 128.0f32;
+                planes[2][index_plane] = ycbcr[2] - 128.0f32;
+            } else {
+                planes[0][index_plane] = r - 128.0f32;
+                planes[1][index_plane] = g - 128.0f32;
+                planes[2][index_plane] = b - 128.0f32;
+            }
+        }
+    }
+    planes
+}
+
+fn push_block_symbols(
+    symbols: &mut Vec<Symbol>,
+    dct_zig_zag: &[i32; 64],
+    last_dc: &mut i32,
+    table: usize,
+) {
+    let diff = dct_zig_zag[0] - *last_dc;
+    *last_dc = dct_zig_zag[0];
+
+    let (extra, extra_length) = magnitude_bits(diff);
+    symbols.push(Symbol {
+        table,
+        value: extra_length,
+        extra,
+        extra_length,
+    });
+
+    let mut run = 0u8;
+    for k in 1..64 {
+        if dct_zig_zag[k] == 0 {
+            run += 1;
+            continue;
+        }
+
+        while run > 15 {
+            symbols.push(Symbol {
+                table: table + 2,
+                value: 0xF0,
+                extra: 0,
+                extra_length: 0,
+            });
+            run -= 16;
+        }
+
+        let (extra, extra_length) = magnitude_bits(dct_zig_zag[k]);
+        symbols.push(Symbol {
+            table: table + 2,
+            value: (run << 4) | extra_length,
+            extra,
+            extra_length,
+        });
+        run = 0;
+    }
+
+    if run > 0 {
+        symbols.push(Symbol {
+            table: table + 2,
+            value: 0x00,
+            extra: 0,
+            extra_length: 0,
+        });
+    }
+}
+
+fn magnitude_bits(value: i32) -> (u16, u8) {
+    let length = (32 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 {
+        value + (1 << length) - 1
+    } else {
+        value
+    };
+    (bits as u16, length)
+}
+
+fn write_segment(data: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    data.push(0xFF);
+    data.push(marker);
+    data.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    data.extend_from_slice(payload);
+}
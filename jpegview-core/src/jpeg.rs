@@ -0,0 +1,1047 @@
+#![allow(clippy::needless_range_loop)]
+
+use crate::{
+    adaptive_quant,
+    alpha::{self, AlphaParameters},
+    bitrate::{self, EntropyCounter},
+    chroma::{self, ChromaFilters, Subsampling},
+    color_space::ColorSpace,
+    my_image::{self, MyImage},
+    quad_mind,
+    quant_table::{self, QuantTables},
+    roi_mask::RoiMask,
+    unwrap_arc_mutex, Vec2d,
+};
+use std::{
+    f32,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+type FunctionDct = fn(&[f32], &mut [f32]);
+
+const FUNCTIONS_FAST_DCT: [FunctionDct; 9] = [
+    fast_generated_dct::dct2::fast_dct,
+    fast_generated_dct::dct4::fast_dct,
+    fast_generated_dct::dct8::fast_dct,
+    fast_generated_dct::dct16::fast_dct,
+    fast_generated_dct::dct32::fast_dct,
+    fast_generated_dct::dct64::fast_dct,
+    fast_generated_dct::dct128::fast_dct,
+    fast_generated_dct::dct256::fast_dct,
+    fast_generated_dct::dct512::fast_dct,
+];
+const FUNCTIONS_FAST_IDCT: [FunctionDct; 9] = [
+    fast_generated_dct::dct2::fast_idct,
+    fast_generated_dct::dct4::fast_idct,
+    fast_generated_dct::dct8::fast_idct,
+    fast_generated_dct::dct16::fast_idct,
+    fast_generated_dct::dct32::fast_idct,
+    fast_generated_dct::dct64::fast_idct,
+    fast_generated_dct::dct128::fast_idct,
+    fast_generated_dct::dct256::fast_idct,
+    fast_generated_dct::dct512::fast_idct,
+];
+
+#[rustfmt::skip]
+pub const Q_MATRIX_LUMA_CONST: [f32; 64] = [
+	16.0f32, 11.0f32, 10.0f32, 16.0f32,  24.0f32,  40.0f32,  51.0f32,  61.0f32,
+	12.0f32, 12.0f32, 14.0f32, 19.0f32,  26.0f32,  58.0f32,  60.0f32,  55.0f32,
+	14.0f32, 13.0f32, 16.0f32, 24.0f32,  40.0f32,  57.0f32,  69.0f32,  56.0f32,
+	14.0f32, 17.0f32, 22.0f32, 29.0f32,  51.0f32,  87.0f32,  80.0f32,  62.0f32,
+	18.0f32, 22.0f32, 37.0f32, 56.0f32,  68.0f32, 109.0f32, 103.0f32,  77.0f32,
+	24.0f32, 35.0f32, 55.0f32, 64.0f32,  81.0f32, 104.0f32, 113.0f32,  92.0f32,
+	49.0f32, 64.0f32, 78.0f32, 87.0f32, 103.0f32, 121.0f32, 120.0f32, 101.0f32,
+	72.0f32, 92.0f32, 95.0f32, 98.0f32, 112.0f32, 100.0f32, 103.0f32,  99.0f32
+];
+#[rustfmt::skip]
+pub const Q_MATRIX_CHROMA_CONST: [f32; 64] = [
+	17.0f32, 18.0f32, 24.0f32, 47.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	18.0f32, 21.0f32, 26.0f32, 66.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	24.0f32, 26.0f32, 56.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	47.0f32, 66.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32,
+	99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32, 99.0f32
+];
+
+#[derive(Clone)]
+pub struct Jpeg {
+    pub block_size: usize,
+    pub block_size_index: usize,
+
+    pub quality: f32,
+    pub quality_start: f32,
+
+    pub use_threads: bool,
+    pub use_fast_dct: bool,
+    pub use_gen_qtable: bool,
+    pub use_compression_rate: bool,
+
+    // Resamples the tables to the block size instead of tiling them
+    pub use_resampled_qtable: bool,
+
+    // Builds the tables from a contrast sensitivity function instead
+    pub use_csf_qtable: bool,
+    pub csf: CsfParameters,
+
+    // Rounds down or zeroes coefficients when the bits saved are worth
+    // more than the distortion added, weighted by the lambda
+    pub use_rdo_quantization: bool,
+    pub rdo_lambda: f32,
+
+    pub quantizer: QuantizerParameters,
+
+    // Scales the matrices of every block by its activity
+    pub use_adaptive_quantization: bool,
+    pub use_draw_adaptive_scale: bool,
+    pub adaptive_strength: f32,
+    // Scale codes of the blocks of the last render, activity and mask combined
+    pub adaptive_scale_codes: Vec<u8>,
+
+    // Maps the mask of every block to a quality between the start quality
+    // and the quality, ignored by compression rate
+    pub roi_mask: Option<RoiMask>,
+
+    pub chroma_filters: ChromaFilters,
+    pub color_space: ColorSpace,
+
+    // Codes the alpha of images that have one
+    pub alpha: AlphaParameters,
+
+    // Replaces the built in tables when set
+    pub q_tables: Option<QuantTables>,
+}
+
+impl Jpeg {
+    pub fn new(
+        block_size: usize,
+        quality: f32,
+        quality_start: f32,
+        block_size_index: usize,
+        use_gen_qtable: bool,
+        use_threads: bool,
+        use_fast_dct: bool,
+        use_compression_rate: bool,
+    ) -> Jpeg {
+        Jpeg {
+            block_size,
+            block_size_index,
+
+            quality,
+            quality_start,
+
+            use_threads,
+            use_fast_dct,
+            use_gen_qtable,
+            use_compression_rate,
+
+            use_resampled_qtable: false,
+
+            use_csf_qtable: false,
+            csf: CsfParameters::default(),
+
+            use_rdo_quantization: false,
+            rdo_lambda: 100.0f32,
+
+            quantizer: QuantizerParameters::default(),
+
+            use_adaptive_quantization: false,
+            use_draw_adaptive_scale: false,
+            adaptive_strength: 1.0f32,
+            adaptive_scale_codes: Vec::new(),
+
+            roi_mask: None,
+
+            chroma_filters: ChromaFilters::default(),
+            color_space: ColorSpace::default(),
+
+            alpha: AlphaParameters::default(),
+
+            q_tables: None,
+        }
+    }
+    // Quantization matrices of a block size before the quality is applied
+    pub fn base_q_matrices(&self, block_size: usize) -> (Vec<f32>, Vec<f32>) {
+        match &self.q_tables {
+            Some(q_tables) => (
+                q_tables.q_matrix(&q_tables.luma, block_size, self.use_resampled_qtable),
+                q_tables.q_matrix(&q_tables.chroma, block_size, self.use_resampled_qtable),
+            ),
+            None if self.use_csf_qtable && !self.use_gen_qtable => (
+                generate_csf_q_matrix(block_size, &self.csf, false),
+                generate_csf_q_matrix(block_size, &self.csf, true),
+            ),
+            None if self.use_resampled_qtable && !self.use_gen_qtable => (
+                resample_q_matrix(&Q_MATRIX_LUMA_CONST, 8, block_size),
+                resample_q_matrix(&Q_MATRIX_CHROMA_CONST, 8, block_size),
+            ),
+            None => (
+                generate_q_matrix(&Q_MATRIX_LUMA_CONST, block_size, self.use_gen_qtable),
+                generate_q_matrix(&Q_MATRIX_CHROMA_CONST, block_size, self.use_gen_qtable),
+            ),
+        }
+    }
+    // Compression rate applies the quality per column while quantizing
+    pub fn q_matrices(&self, block_size: usize) -> (Vec<f32>, Vec<f32>) {
+        let (mut q_matrix_luma, mut q_matrix_chroma) = self.base_q_matrices(block_size);
+
+        if !self.use_compression_rate {
+            let factor = self.quality_factor(self.quality);
+
+            apply_q_matrix_factor(&mut q_matrix_luma, block_size, factor);
+            apply_q_matrix_factor(&mut q_matrix_chroma, block_size, factor);
+        }
+
+        (q_matrix_luma, q_matrix_chroma)
+    }
+    pub fn quality_factor(&self, quality: f32) -> f32 {
+        if self.q_tables.is_some() {
+            quant_table::quality_factor(quality)
+        } else {
+            quality_factor(quality, self.use_gen_qtable)
+        }
+    }
+    pub fn use_roi_mask(&self) -> bool {
+        self.roi_mask.is_some() && !self.use_compression_rate
+    }
+    // Blocks get their own scaled matrices
+    pub fn use_block_scales(&self) -> bool {
+        self.use_adaptive_quantization || self.use_roi_mask()
+    }
+    // Adds the scale of the quality the mask gives a block to its code
+    pub fn roi_scale_code(
+        &self,
+        code: u8,
+        box_left: usize,
+        box_top: usize,
+        block_size: usize,
+        width: usize,
+        height: usize,
+    ) -> u8 {
+        let roi_mask = match &self.roi_mask {
+            Some(roi_mask) if !self.use_compression_rate => roi_mask,
+            _ => return code,
+        };
+
+        let weight = roi_mask.block_weight(box_left, box_top, block_size, width, height);
+        let quality = self.quality_start + weight * (self.quality - self.quality_start);
+
+        // Quality 100 of the built in scaling makes the factor 0
+        let factor = self.quality_factor(quality).max(0.01f32);
+        let base_factor = self.quality_factor(self.quality).max(0.01f32);
+
+        adaptive_quant::combine_codes(
+            code,
+            adaptive_quant::code_from_log2_scale((factor / base_factor).log2()),
+        )
+    }
+    pub fn render(
+        &mut self,
+        my_image: &mut MyImage,
+        use_color_transform: bool,
+        use_threads: bool,
+        subsampling: Subsampling,
+    ) -> usize {
+        self.use_threads = use_threads;
+
+        my_image.round_up_size(self.block_size);
+
+        if use_color_transform {
+            my_image.image_to_ycbcr(self.color_space);
+        } else {
+            my_image.image_to_rgb();
+        }
+        my_image.fill_outbound();
+
+        let (q_matrix_luma, q_matrix_chroma) = self.q_matrices(self.block_size);
+
+        let factors = subsampling.factors();
+        let component_factors = [
+            if use_color_transform { (1, 1) } else { factors },
+            factors,
+            factors,
+        ];
+
+        let estimated_bytes =
+            self.encode(my_image, q_matrix_luma, q_matrix_chroma, component_factors)
+                + alpha::render_alpha(my_image, self.alpha);
+
+        if use_color_transform {
+            my_image.ycbcr_to_image(self.color_space);
+        } else {
+            my_image.rgb_to_image();
+        }
+
+        if self.use_block_scales() && self.use_draw_adaptive_scale {
+            let block_width_count = my_image.mwidth / self.block_size;
+
+            for (index, &code) in self.adaptive_scale_codes.iter().enumerate() {
+                adaptive_quant::draw_scale_overlay(
+                    &mut my_image.final_image,
+                    my_image.width,
+                    my_image.height,
+                    (index % block_width_count) * self.block_size,
+                    (index / block_width_count) * self.block_size,
+                    self.block_size,
+                    code,
+                );
+            }
+        }
+
+        estimated_bytes
+    }
+    // Subsampled components are encoded at their real size on their own
+    // block grid and upsampled back
+    pub fn encode(
+        &mut self,
+        my_image: &mut MyImage,
+        q_matrix_luma: Vec<f32>,
+        q_matrix_chroma: Vec<f32>,
+        component_factors: [(usize, usize); 3],
+    ) -> usize {
+        let mut jpeg_steps = JpegSteps::new(self, my_image.mwidth);
+
+        if !self.use_fast_dct {
+            jpeg_steps.dct_table = Some(Arc::new(generate_dct_table(self.block_size)));
+            jpeg_steps.alpha_table = Some(Arc::new(generate_alpha_table(self.block_size)));
+        }
+
+        let jpeg_steps = Arc::new(jpeg_steps);
+
+        let block_width_count = my_image.mwidth / self.block_size;
+        let block_length = self.block_size * self.block_size;
+
+        // Scale codes belong to the blocks of the full size grid
+        self.adaptive_scale_codes = if self.use_block_scales() {
+            let luma_blocks = plane_blocks(
+                &my_image.image_converted[0],
+                my_image.mwidth,
+                my_image.mheight,
+                self.block_size,
+            );
+            self.scale_codes(
+                luma_blocks.iter().map(|block| &block[..]),
+                block_width_count,
+                my_image.width,
+                my_image.height,
+            )
+        } else {
+            Vec::new()
+        };
+
+        let q_matrix_luma = Arc::new(q_matrix_luma);
+        let q_matrix_chroma = Arc::new(q_matrix_chroma);
+
+        let pool = if self.use_threads {
+            let cpu_threads = thread::available_parallelism().unwrap().get();
+            Some(threadpool::ThreadPool::with_name(
+                String::from("jpegview-worker"),
+                cpu_threads,
+            ))
+        } else {
+            None
+        };
+
+        let zig_zag_table = quad_mind::generate_zig_zag_table(self.block_size);
+
+        let mut entropy_counter = EntropyCounter::new();
+        let mut dct_zig_zag = vec![0i32; block_length];
+
+        for (i, &factors) in component_factors.iter().enumerate() {
+            let (factor_x, factor_y) = factors;
+            let q_matrix = if i == 0 {
+                &q_matrix_luma
+            } else {
+                &q_matrix_chroma
+            };
+
+            let (plane_width, plane_height) =
+                chroma::plane_size(my_image.width, my_image.height, factor_x, factor_y);
+            let plane_mwidth = plane_width.next_multiple_of(self.block_size);
+            let plane_mheight = plane_height.next_multiple_of(self.block_size);
+
+            let mut plane = if factors == (1, 1) {
+                std::mem::take(&mut my_image.image_converted[i])
+            } else {
+                let sub_sampled_plane = my_image.sub_sampled_plane(i, factors, self.chroma_filters);
+
+                let mut plane = vec![128i16; plane_mwidth * plane_mheight];
+                for y in 0..plane_height {
+                    plane[y * plane_mwidth..y * plane_mwidth + plane_width].copy_from_slice(
+                        &sub_sampled_plane[y * plane_width..(y + 1) * plane_width],
+                    );
+                }
+                plane
+            };
+
+            let plane_block_width_count = plane_mwidth / self.block_size;
+            let image_block = plane_blocks(&plane, plane_mwidth, plane_mheight, self.block_size);
+
+            let final_block = encode_blocks(
+                &jpeg_steps,
+                pool.as_ref(),
+                image_block,
+                |index| {
+                    let bx = index % plane_block_width_count;
+                    let by = index / plane_block_width_count;
+
+                    adaptive_quant::block_q_matrix(
+                        q_matrix,
+                        block_length,
+                        self.adaptive_scale_codes
+                            .get(by * factor_y * block_width_count + bx * factor_x)
+                            .copied(),
+                    )
+                },
+                |index| (index % plane_block_width_count) * self.block_size * factor_x,
+            );
+
+            for (index, (result_block, dct_block)) in final_block.iter().enumerate() {
+                let bx = index % plane_block_width_count;
+                let by = index / plane_block_width_count;
+
+                for y in 0..self.block_size {
+                    for x in 0..self.block_size {
+                        let index_result =
+                            (by * self.block_size + y) * plane_mwidth + (bx * self.block_size + x);
+
+                        plane[index_result] = my_image::min_max_component(
+                            result_block[y * self.block_size + x] + 128.0f32,
+                            my_image.component_ranges[i],
+                        );
+                    }
+                }
+
+                for (index, &coefficient) in dct_block.iter().enumerate() {
+                    dct_zig_zag[zig_zag_table[index]] = coefficient;
+                }
+                entropy_counter.add_block(i, &dct_zig_zag);
+            }
+
+            if factors == (1, 1) {
+                my_image.image_converted[i] = plane;
+            } else {
+                let mut sub_sampled_plane = Vec::with_capacity(plane_width * plane_height);
+                for y in 0..plane_height {
+                    sub_sampled_plane.extend_from_slice(
+                        &plane[y * plane_mwidth..y * plane_mwidth + plane_width],
+                    );
+                }
+
+                let upsampled = chroma::upsample(
+                    &sub_sampled_plane,
+                    plane_width,
+                    plane_height,
+                    factors,
+                    my_image.width,
+                    my_image.height,
+                    self.chroma_filters,
+                );
+
+                for y in 0..my_image.height {
+                    my_image.image_converted[i]
+                        [y * my_image.mwidth..y * my_image.mwidth + my_image.width]
+                        .copy_from_slice(&upsampled[y * my_image.width..(y + 1) * my_image.width]);
+                }
+            }
+        }
+
+        entropy_counter.bytes()
+    }
+    // Scale codes from the luma and the mask of every block
+    fn scale_codes<'a>(
+        &self,
+        luma_blocks: impl Iterator<Item = &'a [f32]>,
+        block_width_count: usize,
+        width: usize,
+        height: usize,
+    ) -> Vec<u8> {
+        let codes = if self.use_adaptive_quantization {
+            let activities: Vec<f32> = luma_blocks
+                .map(|block| adaptive_quant::block_activity(block, self.block_size))
+                .collect();
+
+            adaptive_quant::scale_codes(&activities, self.adaptive_strength)
+        } else {
+            luma_blocks
+                .map(|_| adaptive_quant::SCALE_CODE_NEUTRAL)
+                .collect()
+        };
+
+        codes
+            .into_iter()
+            .enumerate()
+            .map(|(index, code)| {
+                self.roi_scale_code(
+                    code,
+                    (index % block_width_count) * self.block_size,
+                    (index / block_width_count) * self.block_size,
+                    self.block_size,
+                    width,
+                    height,
+                )
+            })
+            .collect()
+    }
+}
+
+// Blocks of a plane with pixels around 0
+fn plane_blocks(plane: &[i16], width: usize, height: usize, block_size: usize) -> Vec2d<f32> {
+    let mut image_block = Vec::with_capacity((width / block_size) * (height / block_size));
+    for by in 0..height / block_size {
+        for bx in 0..width / block_size {
+            let mut solo_image_block = vec![0.0f32; block_size * block_size];
+            for y in 0..block_size {
+                for x in 0..block_size {
+                    let index_plane = (by * block_size + y) * width + (bx * block_size + x);
+                    solo_image_block[y * block_size + x] = plane[index_plane] as f32 - 128.0f32;
+                }
+            }
+            image_block.push(solo_image_block);
+        }
+    }
+    image_block
+}
+
+// Reconstructed blocks and their quantized coefficients, on the pool when
+// there is one
+fn encode_blocks(
+    jpeg_steps: &Arc<JpegSteps>,
+    pool: Option<&threadpool::ThreadPool>,
+    image_block: Vec2d<f32>,
+    block_q_matrix: impl Fn(usize) -> Arc<Vec<f32>>,
+    block_start_x: impl Fn(usize) -> usize,
+) -> Vec<(Vec<f32>, Vec<i32>)> {
+    let pool = match pool {
+        Some(pool) => pool,
+        None => {
+            return image_block
+                .iter()
+                .enumerate()
+                .map(|(index, solo_image_block)| {
+                    jpeg_steps.jpeg_steps(
+                        block_start_x(index),
+                        solo_image_block,
+                        &block_q_matrix(index),
+                    )
+                })
+                .collect()
+        }
+    };
+
+    let mut final_block = Vec::with_capacity(image_block.len());
+
+    for (index, solo_image_block) in image_block.into_iter().enumerate() {
+        let arc_jpeg_steps = Arc::clone(jpeg_steps);
+        let arc_q_matrix = block_q_matrix(index);
+        let arc_final_block = Arc::new(Mutex::new((Vec::new(), Vec::new())));
+        let start_x = block_start_x(index);
+
+        final_block.push(Arc::clone(&arc_final_block));
+
+        pool.execute(move || {
+            *arc_final_block.lock().unwrap() =
+                arc_jpeg_steps.jpeg_steps(start_x, &solo_image_block, &arc_q_matrix);
+        });
+    }
+    pool.join();
+
+    final_block.into_iter().map(unwrap_arc_mutex::<_>).collect()
+}
+
+#[derive(Clone)]
+pub struct JpegSteps {
+    pub dct_table: Option<Arc<Vec2d<f32>>>,
+    pub alpha_table: Option<Arc<Vec<f32>>>,
+
+    pub mwidth: usize,
+
+    pub block_size: usize,
+    pub quality_start: f32,
+    pub block_size_index: usize,
+
+    pub use_fast_dct: bool,
+    pub use_gen_qtable: bool,
+    pub use_custom_qtable: bool,
+    pub use_compression_rate: bool,
+    pub use_rdo_quantization: bool,
+
+    pub rdo_lambda: f32,
+    pub quantizer: QuantizerParameters,
+
+    pub q_control: f32,
+    pub two_block_size: f32,
+}
+
+impl JpegSteps {
+    pub fn new(jpeg: &Jpeg, mwidth: usize) -> JpegSteps {
+        JpegSteps {
+            dct_table: None,
+            alpha_table: None,
+
+            mwidth,
+
+            block_size: jpeg.block_size,
+            quality_start: jpeg.quality_start,
+            block_size_index: jpeg.block_size_index,
+
+            use_fast_dct: jpeg.use_fast_dct,
+            use_gen_qtable: jpeg.use_gen_qtable,
+            use_custom_qtable: jpeg.q_tables.is_some(),
+            use_compression_rate: jpeg.use_compression_rate,
+            use_rdo_quantization: jpeg.use_rdo_quantization,
+
+            rdo_lambda: jpeg.rdo_lambda,
+            quantizer: jpeg.quantizer,
+
+            q_control: 100.0f32 - jpeg.quality_start,
+            two_block_size: 2.0f32 / jpeg.block_size as f32,
+        }
+    }
+    pub fn dct_function(&self, image_block: &[f32]) -> Vec<f32> {
+        let mut dct_matrix: Vec<f32> = vec![0.0f32; self.block_size * self.block_size];
+        if self.use_fast_dct {
+            FUNCTIONS_FAST_DCT[self.block_size_index](image_block, &mut dct_matrix);
+        } else {
+            let dct_table = self.dct_table.as_ref().unwrap();
+            let alpha_table = self.alpha_table.as_ref().unwrap();
+            for v in 0..self.block_size {
+                let vblock = v * self.block_size;
+
+                for u in 0..self.block_size {
+                    let ublock = u * self.block_size;
+
+                    let mut sum = 0.0f32;
+                    for y in 0..self.block_size {
+                        let yv = dct_table[0][vblock + y];
+
+                        for x in 0..self.block_size {
+                            let xu = dct_table[0][ublock + x];
+
+                            let index_image = y * self.block_size + x;
+                            sum += image_block[index_image] * xu * yv;
+                        }
+                    }
+
+                    let index_matrix = vblock + u;
+                    dct_matrix[index_matrix] =
+                        alpha_table[index_matrix] * sum * self.two_block_size;
+                }
+            }
+        }
+        dct_matrix
+    }
+    pub fn inverse_dct_function(&self, dct_matrix: &[f32]) -> Vec<f32> {
+        let mut image_block: Vec<f32> = vec![0.0f32; self.block_size * self.block_size];
+        if self.use_fast_dct {
+            FUNCTIONS_FAST_IDCT[self.block_size_index](dct_matrix, &mut image_block);
+        } else {
+            let dct_table = self.dct_table.as_ref().unwrap();
+            let alpha_table = self.alpha_table.as_ref().unwrap();
+            for y in 0..self.block_size {
+                let yblock = y * self.block_size;
+
+                for x in 0..self.block_size {
+                    let xblock = x * self.block_size;
+
+                    let mut sum = 0.0f32;
+                    for v in 0..self.block_size {
+                        let vblock = v * self.block_size;
+
+                        let yv = dct_table[1][yblock + v];
+
+                        for u in 0..self.block_size {
+                            let xu = dct_table[1][xblock + u];
+
+                            let index_matrix = vblock + u;
+                            sum += alpha_table[index_matrix] * dct_matrix[index_matrix] * xu * yv;
+                        }
+                    }
+
+                    let index_image = y * self.block_size + x;
+                    image_block[index_image] = sum * self.two_block_size;
+                }
+            }
+        }
+        image_block
+    }
+    fn compression_rate_value(&self, x: usize, index: usize, q_matrix: &[f32]) -> f32 {
+        let quality = self.quality_start + (x as f32 / self.mwidth as f32) * self.q_control;
+
+        let factor = if self.use_custom_qtable {
+            quant_table::quality_factor(quality)
+        } else {
+            quality_factor(quality, self.use_gen_qtable)
+        };
+
+        1.0f32 + (q_matrix[index] - 1.0f32) * factor
+    }
+    pub fn quantize_function(
+        &self,
+        start_x: usize,
+        q_matrix: &[f32],
+        dct_matrix: &mut [f32],
+        use_compression_rate: bool,
+    ) {
+        let mut q_values = vec![0.0f32; self.block_size * self.block_size];
+        for y in 0..self.block_size {
+            for x in 0..self.block_size {
+                let index = y * self.block_size + x;
+
+                let q_matrix_value = if use_compression_rate {
+                    self.compression_rate_value(start_x + x, index, q_matrix)
+                } else {
+                    q_matrix[index]
+                };
+
+                dct_matrix[index] /= q_matrix_value;
+                q_values[index] = q_matrix_value;
+            }
+        }
+
+        if self.use_rdo_quantization {
+            rdo_quantize(dct_matrix, &q_values, self.block_size, self.rdo_lambda);
+        } else {
+            for value in dct_matrix.iter_mut() {
+                *value = self.quantizer.quantize(*value);
+            }
+        }
+    }
+    pub fn de_quantize_function(
+        &self,
+        start_x: usize,
+        q_matrix: &[f32],
+        dct_matrix: &mut [f32],
+        use_compression_rate: bool,
+    ) {
+        for y in 0..self.block_size {
+            for x in 0..self.block_size {
+                let index = y * self.block_size + x;
+
+                let q_matrix_value = if use_compression_rate {
+                    self.compression_rate_value(start_x + x, index, q_matrix)
+                } else {
+                    q_matrix[index]
+                };
+
+                dct_matrix[index] = self.quantizer.reconstruct(dct_matrix[index]) * q_matrix_value;
+            }
+        }
+    }
+    // Returns the reconstructed block and its quantized coefficients
+    pub fn jpeg_steps(
+        &self,
+        start_x: usize,
+        image_block: &[f32],
+        q_matrix: &[f32],
+    ) -> (Vec<f32>, Vec<i32>) {
+        let mut dct_matrix = self.dct_function(image_block);
+        self.quantize_function(
+            start_x,
+            q_matrix,
+            &mut dct_matrix,
+            self.use_compression_rate,
+        );
+        let dct_quantized = dct_matrix.iter().map(|&value| value as i32).collect();
+        self.de_quantize_function(
+            start_x,
+            q_matrix,
+            &mut dct_matrix,
+            self.use_compression_rate,
+        );
+        (self.inverse_dct_function(&dct_matrix), dct_quantized)
+    }
+}
+
+pub fn generate_q_matrix(
+    q_matrix_base: &[f32],
+    block_size: usize,
+    use_gen_qtable: bool,
+) -> Vec<f32> {
+    let mut q_matrix: Vec<f32> = vec![0.0f32; block_size * block_size];
+    if use_gen_qtable {
+        for y in 0..block_size {
+            for x in 0..block_size {
+                q_matrix[y * block_size + x] = (x + y + 1) as f32;
+            }
+        }
+    } else {
+        for y in 0..block_size {
+            for x in 0..block_size {
+                q_matrix[y * block_size + x] = q_matrix_base[(y % 8) * 8 + (x % 8)];
+            }
+        }
+    }
+    q_matrix
+}
+
+// Maps the normalized frequency of every coefficient onto the base table
+// with bilinear interpolation, so high frequencies of large blocks are
+// quantized like the high frequencies of the base table
+pub fn resample_q_matrix(q_matrix_base: &[f32], base_size: usize, block_size: usize) -> Vec<f32> {
+    let scale = base_size as f32 / block_size as f32;
+    let position = |i: usize| {
+        let position = i as f32 * scale;
+        let position_0 = (position as usize).min(base_size - 1);
+        let position_1 = (position_0 + 1).min(base_size - 1);
+        (position_0, position_1, position - position_0 as f32)
+    };
+
+    let mut q_matrix: Vec<f32> = vec![0.0f32; block_size * block_size];
+    for y in 0..block_size {
+        let (y0, y1, fy) = position(y);
+        for x in 0..block_size {
+            let (x0, x1, fx) = position(x);
+
+            let top = q_matrix_base[y0 * base_size + x0] * (1.0f32 - fx)
+                + q_matrix_base[y0 * base_size + x1] * fx;
+            let bottom = q_matrix_base[y1 * base_size + x0] * (1.0f32 - fx)
+                + q_matrix_base[y1 * base_size + x1] * fx;
+
+            q_matrix[y * block_size + x] = top * (1.0f32 - fy) + bottom * fy;
+        }
+    }
+    q_matrix
+}
+
+#[derive(Clone, Copy)]
+pub struct CsfParameters {
+    // Centimeters from the eye to the display
+    pub viewing_distance: f32,
+    pub display_dpi: f32,
+}
+
+// A desktop display at arm's length
+impl Default for CsfParameters {
+    fn default() -> CsfParameters {
+        CsfParameters::new(50.0f32, 96.0f32)
+    }
+}
+
+impl CsfParameters {
+    pub fn new(viewing_distance: f32, display_dpi: f32) -> CsfParameters {
+        CsfParameters {
+            viewing_distance,
+            display_dpi,
+        }
+    }
+    pub fn pixels_per_degree(&self) -> f32 {
+        let viewing_distance_inches = self.viewing_distance / 2.54f32;
+        self.display_dpi * viewing_distance_inches * 1.0f32.to_radians().tan()
+    }
+}
+
+// Mannos-Sakrison contrast sensitivity for a frequency in cycles per degree,
+// it peaks at about 8 cycles per degree
+fn mannos_sakrison(frequency: f32) -> f32 {
+    2.6f32 * (0.0192f32 + 0.114f32 * frequency) * (-(0.114f32 * frequency).powf(1.1f32)).exp()
+}
+
+const CSF_PEAK_FREQUENCY: f32 = 8.0f32;
+
+// Steps are inversely proportional to the sensitivity at the frequency of
+// every coefficient, frequencies below the peak keep the smallest step so
+// flat areas do not band. Chroma sensitivity falls off about twice as fast
+pub fn generate_csf_q_matrix(block_size: usize, csf: &CsfParameters, use_chroma: bool) -> Vec<f32> {
+    let (q_min, frequency_scale) = if use_chroma {
+        (Q_MATRIX_CHROMA_CONST[0], 2.0f32)
+    } else {
+        (Q_MATRIX_LUMA_CONST[0], 1.0f32)
+    };
+
+    let peak_sensitivity = mannos_sakrison(CSF_PEAK_FREQUENCY);
+    let cycles_per_degree =
+        csf.pixels_per_degree() * frequency_scale / (2.0f32 * block_size as f32);
+
+    let mut q_matrix: Vec<f32> = vec![0.0f32; block_size * block_size];
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let frequency = ((x * x + y * y) as f32).sqrt() * cycles_per_degree;
+
+            let sensitivity = if frequency <= CSF_PEAK_FREQUENCY {
+                1.0f32
+            } else {
+                mannos_sakrison(frequency) / peak_sensitivity
+            };
+
+            q_matrix[y * block_size + x] = (q_min / sensitivity.max(1e-3f32)).min(255.0f32);
+        }
+    }
+    q_matrix
+}
+
+// Shape of the scalar quantizer in quantization steps, the defaults round to
+// nearest and reconstruct at the middle of the interval
+#[derive(Clone, Copy)]
+pub struct QuantizerParameters {
+    // Values below it become zero, above 0.5 it widens the zero interval
+    pub dead_zone: f32,
+    // Added before truncating, below 0.5 it rounds towards zero like x264
+    pub rounding_offset: f32,
+    // Pulls reconstructed values towards zero, coefficients are denser there
+    pub reconstruction_bias: f32,
+}
+
+impl Default for QuantizerParameters {
+    fn default() -> QuantizerParameters {
+        QuantizerParameters::new(0.5f32, 0.5f32, 0.0f32)
+    }
+}
+
+impl QuantizerParameters {
+    pub fn new(
+        dead_zone: f32,
+        rounding_offset: f32,
+        reconstruction_bias: f32,
+    ) -> QuantizerParameters {
+        QuantizerParameters {
+            dead_zone,
+            rounding_offset,
+            reconstruction_bias,
+        }
+    }
+    pub fn quantize(&self, value: f32) -> f32 {
+        if value.abs() < self.dead_zone {
+            return 0.0f32;
+        }
+        (value.abs() + self.rounding_offset).floor().copysign(value)
+    }
+    pub fn reconstruct(&self, level: f32) -> f32 {
+        if level == 0.0f32 {
+            return 0.0f32;
+        }
+        (level.abs() - self.reconstruction_bias).copysign(level)
+    }
+}
+
+// Rounds coefficients already divided by their quantization step. Every AC
+// coefficient in zig-zag order keeps its rounded level, one less or zero,
+// whichever costs less squared error of the coefficient plus lambda times
+// its estimated bits, a lambda of 0 only rounds.
+// Trailing coefficients are then zeroed while the end of block saves more
+// than they cost
+pub fn rdo_quantize(dct_matrix: &mut [f32], q_values: &[f32], block_size: usize, lambda: f32) {
+    let mut zig_zag_order = vec![0usize; block_size * block_size];
+    for (index, n) in quad_mind::generate_zig_zag_table(block_size)
+        .into_iter()
+        .enumerate()
+    {
+        zig_zag_order[n] = index;
+    }
+
+    let values: Vec<f32> = dct_matrix.iter().map(|value| value.abs()).collect();
+
+    dct_matrix[0] = dct_matrix[0].round();
+
+    let mut run = 0;
+    for &index in &zig_zag_order[1..] {
+        let value = values[index];
+        let rounded = value.round();
+        let q_squared = q_values[index] * q_values[index];
+
+        let zero_cost = value * value * q_squared
+            + lambda * (bitrate::ac_bits(run + 1, 1) - bitrate::ac_bits(run, 1));
+
+        let mut best_level = 0.0f32;
+        let mut best_cost = zero_cost;
+        for level in [rounded, rounded - 1.0f32] {
+            if level < 1.0f32 {
+                continue;
+            }
+            let cost = (value - level) * (value - level) * q_squared
+                + lambda * bitrate::ac_bits(run, level as i32);
+            if cost < best_cost {
+                best_level = level;
+                best_cost = cost;
+            }
+        }
+
+        dct_matrix[index] = best_level.copysign(dct_matrix[index]);
+        if best_level == 0.0f32 {
+            run += 1;
+        } else {
+            run = 0;
+        }
+    }
+
+    let last_non_zero = |end: usize, dct_matrix: &[f32]| {
+        (1..end)
+            .rev()
+            .find(|&n| dct_matrix[zig_zag_order[n]] != 0.0f32)
+    };
+
+    let mut position = last_non_zero(zig_zag_order.len(), dct_matrix);
+    while let Some(current) = position {
+        let previous = last_non_zero(current, dct_matrix);
+
+        let value = values[zig_zag_order[current]];
+        let level = dct_matrix[zig_zag_order[current]].abs();
+        let q_squared = q_values[zig_zag_order[current]] * q_values[zig_zag_order[current]];
+
+        let run = current - previous.unwrap_or(0) - 1;
+        let saved_bits = bitrate::ac_bits(run, level as i32);
+        let added_distortion = (value * value - (value - level) * (value - level)) * q_squared;
+        if lambda * saved_bits <= added_distortion {
+            break;
+        }
+
+        dct_matrix[zig_zag_order[current]] = 0.0f32;
+        position = previous;
+    }
+}
+
+pub fn quality_factor(quality: f32, use_gen_qtable: bool) -> f32 {
+    if use_gen_qtable {
+        if quality >= 50.0f32 {
+            200.0f32 - (quality * 2.0f32)
+        } else {
+            5000.0f32 / quality
+        }
+    } else {
+        25.0f32 * ((101.0f32 - quality) * 0.01f32)
+    }
+}
+
+pub fn apply_q_matrix_factor(q_matrix: &mut [f32], block_size: usize, factor: f32) {
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let index = y * block_size + x;
+            q_matrix[index] = 1.0f32 + (q_matrix[index] - 1.0f32) * factor;
+        }
+    }
+}
+
+pub fn generate_dct_table(block_size: usize) -> Vec2d<f32> {
+    let mut dct_table: Vec2d<f32> = vec![vec![0.0f32; block_size * block_size]; 2];
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let cos_calc =
+                (((2 * y + 1) as f32 * x as f32 * f32::consts::PI) / (2 * block_size) as f32).cos();
+            dct_table[0][x * block_size + y] = cos_calc;
+            dct_table[1][y * block_size + x] = cos_calc;
+        }
+    }
+    dct_table
+}
+
+pub fn generate_alpha_table(block_size: usize) -> Vec<f32> {
+    let mut alpha_table: Vec<f32> = vec![0.0f32; block_size * block_size];
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let x_value = if x == 0 {
+                f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.0f32
+            };
+            let y_value = if y == 0 {
+                f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.0f32
+            };
+            alpha_table[y * block_size + x] = x_value * y_value;
+        }
+    }
+    alpha_table
+}
@@ -0,0 +1,898 @@
+#![allow(clippy::needless_range_loop)]
+
+use crate::{
+    chroma::Subsampling,
+    color_space::ColorSpace,
+    jpeg::{self, Jpeg, JpegSteps},
+    my_image::{self, MyImage},
+    quad_mind,
+};
+use std::{fs::File, io::Read, path::Path, sync::Arc};
+
+pub struct JpegComponent {
+    pub id: u8,
+
+    pub h: usize,
+    pub v: usize,
+    pub q_table_index: usize,
+
+    pub width: usize,
+    pub height: usize,
+
+    pub block_width_count: usize,
+    pub block_height_count: usize,
+
+    // Quantized coefficients of every block in natural (row-major) order
+    pub blocks: Vec<[i32; 64]>,
+    pub non_zero_count: usize,
+
+    dc_table_index: usize,
+    ac_table_index: usize,
+}
+
+pub struct JpegFile {
+    pub width: usize,
+    pub height: usize,
+
+    pub progressive: bool,
+    pub adobe_transform: Option<u8>,
+
+    // Quantization tables in natural (row-major) order
+    pub q_tables: [Option<[u16; 64]>; 4],
+    pub components: Vec<JpegComponent>,
+}
+
+impl JpegFile {
+    pub fn max_sampling(&self) -> (usize, usize) {
+        let mut h_max = 1;
+        let mut v_max = 1;
+        for component in &self.components {
+            h_max = h_max.max(component.h);
+            v_max = v_max.max(component.v);
+        }
+        (h_max, v_max)
+    }
+    pub fn subsampling(&self) -> Option<Subsampling> {
+        if self.components.len() != 3 {
+            return None;
+        }
+
+        let chroma = (self.components[1].h, self.components[1].v);
+        if chroma != (self.components[2].h, self.components[2].v)
+            || !self.components[0].h.is_multiple_of(chroma.0)
+            || !self.components[0].v.is_multiple_of(chroma.1)
+        {
+            return None;
+        }
+
+        Some(Subsampling::new(
+            self.components[0].h / chroma.0,
+            self.components[0].v / chroma.1,
+        ))
+    }
+    pub fn to_rgb(&self, use_fast_dct: bool) -> Vec<u8> {
+        let (h_max, v_max) = self.max_sampling();
+
+        let jpeg = Jpeg::new(8, 100.0f32, 1.0f32, 2, false, false, use_fast_dct, false);
+
+        let mut jpeg_steps = JpegSteps::new(&jpeg, self.width);
+        if !use_fast_dct {
+            jpeg_steps.dct_table = Some(Arc::new(jpeg::generate_dct_table(8)));
+            jpeg_steps.alpha_table = Some(Arc::new(jpeg::generate_alpha_table(8)));
+        }
+
+        let mut planes = Vec::with_capacity(self.components.len());
+
+        for component in &self.components {
+            let q_table = self.q_tables[component.q_table_index].unwrap_or([1u16; 64]);
+
+            let plane_width = component.block_width_count * 8;
+            let mut plane = vec![0u8; plane_width * component.block_height_count * 8];
+
+            for by in 0..component.block_height_count {
+                for bx in 0..component.block_width_count {
+                    let block = &component.blocks[by * component.block_width_count + bx];
+
+                    let mut dct_matrix = vec![0.0f32; 64];
+                    for i in 0..64 {
+                        dct_matrix[i] = (block[i] * q_table[i] as i32) as f32;
+                    }
+
+                    let image_block = jpeg_steps.inverse_dct_function(&dct_matrix);
+
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            plane[(by * 8 + y) * plane_width + bx * 8 + x] =
+                                my_image::min_max_color(image_block[y * 8 + x] + 128.5f32);
+                        }
+                    }
+                }
+            }
+
+            planes.push((plane, plane_width));
+        }
+
+        let mut rgb = vec![0u8; self.width * self.height * 3];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut samples = [0.0f32; 3];
+                for (i, (plane, plane_width)) in planes.iter().enumerate() {
+                    let component = &self.components[i];
+                    let sx = x * component.h / h_max;
+                    let sy = y * component.v / v_max;
+                    samples[i] = plane[sy * plane_width + sx] as f32;
+                }
+
+                let index = (y * self.width + x) * 3;
+
+                if self.components.len() == 1 {
+                    let gray = samples[0] as u8;
+                    rgb[index] = gray;
+                    rgb[index + 1] = gray;
+                    rgb[index + 2] = gray;
+                } else if self.adobe_transform == Some(0) {
+                    rgb[index] = samples[0] as u8;
+                    rgb[index + 1] = samples[1] as u8;
+                    rgb[index + 2] = samples[2] as u8;
+                } else {
+                    let color = ColorSpace::default().to_rgb(samples[0], samples[1], samples[2]);
+                    for (result, value) in rgb[index..index + 3].iter_mut().zip(color) {
+                        *result = my_image::min_max_color(value + 0.5f32);
+                    }
+                }
+            }
+        }
+
+        rgb
+    }
+}
+
+struct HuffmanTable {
+    lookup: Vec<(u8, u8)>,
+
+    max_code: [i32; 18],
+    val_offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+const LOOKUP_BITS: u32 = 9;
+
+impl HuffmanTable {
+    fn new(bits: &[u8; 16], values: Vec<u8>) -> Result<HuffmanTable, &'static str> {
+        let total: usize = bits.iter().map(|&count| count as usize).sum();
+        if total > values.len() || total > 256 {
+            return Err("Invalid Huffman table");
+        }
+
+        let mut lookup = vec![(0u8, 0u8); 1 << LOOKUP_BITS];
+        let mut max_code = [-1i32; 18];
+        let mut val_offset = [0i32; 17];
+
+        let mut code = 0i32;
+        let mut k = 0usize;
+        for length in 1..17 {
+            val_offset[length] = k as i32 - code;
+
+            for _ in 0..bits[length - 1] {
+                // Codes of a length run out before they could index past it
+                if code >= (1 << length) {
+                    return Err("Invalid Huffman table");
+                }
+                if length as u32 <= LOOKUP_BITS {
+                    let shift = LOOKUP_BITS - length as u32;
+                    for fill in 0..(1 << shift) {
+                        lookup[((code as usize) << shift) | fill] = (length as u8, values[k]);
+                    }
+                }
+                code += 1;
+                k += 1;
+            }
+
+            if bits[length - 1] > 0 {
+                max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        max_code[17] = i32::MAX;
+
+        Ok(HuffmanTable {
+            lookup,
+
+            max_code,
+            val_offset,
+            values,
+        })
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+
+    buffer: u32,
+    length: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            position,
+
+            buffer: 0,
+            length: 0,
+        }
+    }
+    fn fill(&mut self) {
+        while self.length <= 24 {
+            let mut byte = 0u8;
+            if self.position < self.data.len() {
+                byte = self.data[self.position];
+                if byte == 0xFF {
+                    match self.data.get(self.position + 1) {
+                        Some(0x00) => self.position += 2,
+                        // A marker ends the entropy coded data, pad with zeros
+                        _ => byte = 0,
+                    }
+                } else {
+                    self.position += 1;
+                }
+            }
+            self.buffer |= (byte as u32) << (24 - self.length);
+            self.length += 8;
+        }
+    }
+    fn read_bits(&mut self, count: u32) -> i32 {
+        if count == 0 {
+            return 0;
+        }
+        self.fill();
+        let value = self.buffer >> (32 - count);
+        self.buffer <<= count;
+        self.length -= count;
+        value as i32
+    }
+    fn read_bit(&mut self) -> bool {
+        self.read_bits(1) == 1
+    }
+    fn receive_extend(&mut self, count: u32) -> i32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = self.read_bits(count);
+        if value < (1 << (count - 1)) {
+            value - (1 << count) + 1
+        } else {
+            value
+        }
+    }
+    fn decode(&mut self, table: &HuffmanTable) -> Result<u8, &'static str> {
+        self.fill();
+
+        let (length, value) = table.lookup[(self.buffer >> (32 - LOOKUP_BITS)) as usize];
+        if length > 0 {
+            self.buffer <<= length;
+            self.length -= length as u32;
+            return Ok(value);
+        }
+
+        let mut length = LOOKUP_BITS as usize + 1;
+        let mut code = (self.buffer >> (32 - length)) as i32;
+        while code > table.max_code[length] {
+            length += 1;
+            code = (self.buffer >> (32 - length.min(16))) as i32;
+        }
+        if length > 16 {
+            return Err("Invalid Huffman code");
+        }
+
+        self.buffer <<= length;
+        self.length -= length as u32;
+
+        let index = (table.val_offset[length] + code) as usize;
+        table
+            .values
+            .get(index)
+            .copied()
+            .ok_or("Invalid Huffman code")
+    }
+    // Skips the RSTn marker found at the end of a restart interval
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.length = 0;
+
+        while self.position + 1 < self.data.len() {
+            if self.data[self.position] == 0xFF
+                && (0xD0..=0xD7).contains(&self.data[self.position + 1])
+            {
+                self.position += 2;
+                return;
+            }
+            self.position += 1;
+        }
+    }
+}
+
+struct Scan {
+    components: Vec<usize>,
+    ss: usize,
+    se: usize,
+    ah: u32,
+    al: u32,
+}
+
+pub fn load_jpeg(path: &Path, use_fast_dct: bool) -> Result<(MyImage, JpegFile), &'static str> {
+    let mut contents: Vec<u8> = Vec::new();
+    let mut file = File::open(path).map_err(|_| "Could not open file")?;
+    file.read_to_end(&mut contents)
+        .map_err(|_| "Could not read file")?;
+
+    let jpeg_file = decode_jpeg(&contents)?;
+
+    let my_image = MyImage::new(
+        jpeg_file.to_rgb(use_fast_dct),
+        jpeg_file.width,
+        jpeg_file.height,
+        path.to_str().unwrap().to_string(),
+    );
+
+    Ok((my_image, jpeg_file))
+}
+
+// Coefficients of every block are kept for the whole file, 1 GiB of them at
+// most
+const MAX_BLOCK_COUNT: usize = 1 << 22;
+
+pub fn decode_jpeg(data: &[u8]) -> Result<JpegFile, &'static str> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return Err("Wrong JPEG SOI signature");
+    }
+
+    let zig_zag_table = quad_mind::generate_zig_zag_table(8);
+    let mut natural_order = [0usize; 64];
+    for i in 0..64 {
+        natural_order[zig_zag_table[i]] = i;
+    }
+
+    let mut jpeg_file = JpegFile {
+        width: 0,
+        height: 0,
+
+        progressive: false,
+        adobe_transform: None,
+
+        q_tables: [None; 4],
+        components: Vec::new(),
+    };
+
+    let mut dc_tables: Vec<Option<HuffmanTable>> = vec![None, None, None, None];
+    let mut ac_tables: Vec<Option<HuffmanTable>> = vec![None, None, None, None];
+
+    let mut restart_interval = 0usize;
+    let mut position = 2;
+
+    loop {
+        while position < data.len() && data[position] != 0xFF {
+            position += 1;
+        }
+        while position < data.len() && data[position] == 0xFF {
+            position += 1;
+        }
+        if position >= data.len() {
+            return Err("Missing JPEG EOI marker");
+        }
+
+        let marker = data[position];
+        position += 1;
+
+        match marker {
+            0xD9 => break,
+            0xD0..=0xD7 | 0x01 => continue,
+            _ => {}
+        }
+
+        if position + 2 > data.len() {
+            return Err("Truncated JPEG segment");
+        }
+        let length = u16::from_be_bytes([data[position], data[position + 1]]) as usize;
+        if length < 2 || position + length > data.len() {
+            return Err("Truncated JPEG segment");
+        }
+        let segment = &data[position + 2..position + length];
+        position += length;
+
+        match marker {
+            0xDB => {
+                let mut offset = 0;
+                while offset < segment.len() {
+                    let precision = segment[offset] >> 4;
+                    let table_index = (segment[offset] & 0x0F) as usize;
+                    offset += 1;
+
+                    let size = if precision == 0 { 64 } else { 128 };
+                    if table_index > 3 || offset + size > segment.len() {
+                        return Err("Invalid JPEG DQT segment");
+                    }
+
+                    let mut q_table = [0u16; 64];
+                    for k in 0..64 {
+                        q_table[natural_order[k]] = if precision == 0 {
+                            segment[offset + k] as u16
+                        } else {
+                            u16::from_be_bytes([
+                                segment[offset + k * 2],
+                                segment[offset + k * 2 + 1],
+                            ])
+                        };
+                    }
+                    offset += size;
+
+                    jpeg_file.q_tables[table_index] = Some(q_table);
+                }
+            }
+            0xC4 => {
+                let mut offset = 0;
+                while offset < segment.len() {
+                    if offset + 17 > segment.len() {
+                        return Err("Invalid JPEG DHT segment");
+                    }
+
+                    let class = segment[offset] >> 4;
+                    let table_index = (segment[offset] & 0x0F) as usize;
+
+                    let mut bits = [0u8; 16];
+                    bits.copy_from_slice(&segment[offset + 1..offset + 17]);
+                    offset += 17;
+
+                    let count: usize = bits.iter().map(|&count| count as usize).sum();
+                    if class > 1 || table_index > 3 || offset + count > segment.len() {
+                        return Err("Invalid JPEG DHT segment");
+                    }
+
+                    let table = HuffmanTable::new(&bits, segment[offset..offset + count].to_vec())?;
+                    offset += count;
+
+                    if class == 0 {
+                        dc_tables[table_index] = Some(table);
+                    } else {
+                        ac_tables[table_index] = Some(table);
+                    }
+                }
+            }
+            0xC0..=0xC2 => {
+                if segment.len() < 6 || segment[0] != 8 {
+                    return Err("Only 8-bit JPEG is supported");
+                }
+                if !jpeg_file.components.is_empty() {
+                    return Err("Multiple JPEG SOF segments");
+                }
+
+                jpeg_file.progressive = marker == 0xC2;
+                jpeg_file.height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                jpeg_file.width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+
+                let component_count = segment[5] as usize;
+                if jpeg_file.width == 0 || jpeg_file.height == 0 {
+                    return Err("Invalid JPEG dimensions");
+                }
+                if (component_count != 1 && component_count != 3)
+                    || segment.len() < 6 + component_count * 3
+                {
+                    return Err("Only grayscale and three component JPEG are supported");
+                }
+
+                for i in 0..component_count {
+                    let offset = 6 + i * 3;
+                    let h = (segment[offset + 1] >> 4) as usize;
+                    let v = (segment[offset + 1] & 0x0F) as usize;
+                    let q_table_index = segment[offset + 2] as usize;
+
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) || q_table_index > 3 {
+                        return Err("Invalid JPEG SOF component");
+                    }
+
+                    jpeg_file.components.push(JpegComponent {
+                        id: segment[offset],
+
+                        h,
+                        v,
+                        q_table_index,
+
+                        width: 0,
+                        height: 0,
+
+                        block_width_count: 0,
+                        block_height_count: 0,
+
+                        blocks: Vec::new(),
+                        non_zero_count: 0,
+
+                        dc_table_index: 0,
+                        ac_table_index: 0,
+                    });
+                }
+
+                let (h_max, v_max) = jpeg_file.max_sampling();
+                let mcu_width_count = jpeg_file.width.div_ceil(8 * h_max);
+                let mcu_height_count = jpeg_file.height.div_ceil(8 * v_max);
+
+                // Checked before the coefficients of any block are allocated
+                let block_count: usize = jpeg_file
+                    .components
+                    .iter()
+                    .map(|component| mcu_width_count * component.h * mcu_height_count * component.v)
+                    .sum();
+                if block_count > MAX_BLOCK_COUNT {
+                    return Err("JPEG dimensions are too large");
+                }
+
+                for component in &mut jpeg_file.components {
+                    component.width = (jpeg_file.width * component.h).div_ceil(h_max);
+                    component.height = (jpeg_file.height * component.v).div_ceil(v_max);
+
+                    component.block_width_count = mcu_width_count * component.h;
+                    component.block_height_count = mcu_height_count * component.v;
+
+                    component.blocks = vec![
+                        [0i32; 64];
+                        component.block_width_count
+                            * component.block_height_count
+                    ];
+                }
+            }
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err("Unsupported JPEG process");
+            }
+            0xDD => {
+                if segment.len() < 2 {
+                    return Err("Invalid JPEG DRI segment");
+                }
+                restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+            }
+            0xEE if segment.len() >= 12 && &segment[0..5] == b"Adobe" => {
+                jpeg_file.adobe_transform = Some(segment[11]);
+            }
+            0xDA => {
+                if jpeg_file.components.is_empty() {
+                    return Err("JPEG SOS before SOF");
+                }
+
+                let component_count = *segment.first().ok_or("Invalid JPEG SOS segment")? as usize;
+                if component_count == 0 || segment.len() < 1 + component_count * 2 + 3 {
+                    return Err("Invalid JPEG SOS segment");
+                }
+
+                let mut scan_components = Vec::with_capacity(component_count);
+                for i in 0..component_count {
+                    let id = segment[1 + i * 2];
+                    let tables = segment[2 + i * 2];
+
+                    let index = jpeg_file
+                        .components
+                        .iter()
+                        .position(|component| component.id == id)
+                        .ok_or("Unknown JPEG SOS component")?;
+
+                    jpeg_file.components[index].dc_table_index = (tables >> 4) as usize & 3;
+                    jpeg_file.components[index].ac_table_index = (tables & 0x0F) as usize & 3;
+
+                    scan_components.push(index);
+                }
+
+                let offset = 1 + component_count * 2;
+                let scan = Scan {
+                    components: scan_components,
+                    ss: segment[offset] as usize,
+                    se: segment[offset + 1] as usize,
+                    ah: (segment[offset + 2] >> 4) as u32,
+                    al: (segment[offset + 2] & 0x0F) as u32,
+                };
+
+                if scan.ss > scan.se || scan.se > 63 || scan.al > 13 {
+                    return Err("Invalid JPEG SOS spectral selection");
+                }
+
+                position = decode_scan(
+                    data,
+                    position,
+                    &mut jpeg_file,
+                    &scan,
+                    &dc_tables,
+                    &ac_tables,
+                    restart_interval,
+                    &natural_order,
+                )?;
+            }
+            _ => {}
+        }
+    }
+
+    if jpeg_file.components.is_empty() {
+        return Err("Missing JPEG SOF segment");
+    }
+
+    for component in &mut jpeg_file.components {
+        component.non_zero_count = component
+            .blocks
+            .iter()
+            .map(|block| {
+                block
+                    .iter()
+                    .filter(|&&coefficient| coefficient != 0)
+                    .count()
+            })
+            .sum();
+    }
+
+    Ok(jpeg_file)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+    data: &[u8],
+    position: usize,
+    jpeg_file: &mut JpegFile,
+    scan: &Scan,
+    dc_tables: &[Option<HuffmanTable>],
+    ac_tables: &[Option<HuffmanTable>],
+    restart_interval: usize,
+    natural_order: &[usize; 64],
+) -> Result<usize, &'static str> {
+    let mut bit_reader = BitReader::new(data, position);
+
+    let (h_max, v_max) = jpeg_file.max_sampling();
+
+    // A scan with a single component is not interleaved, its MCU is one block
+    let (mcu_width_count, mcu_height_count) = if scan.components.len() == 1 {
+        let component = &jpeg_file.components[scan.components[0]];
+        (component.width.div_ceil(8), component.height.div_ceil(8))
+    } else {
+        (
+            jpeg_file.width.div_ceil(8 * h_max),
+            jpeg_file.height.div_ceil(8 * v_max),
+        )
+    };
+
+    for &index in &scan.components {
+        let component = &jpeg_file.components[index];
+        if scan.ss == 0 && scan.ah == 0 && dc_tables[component.dc_table_index].is_none() {
+            return Err("Missing JPEG DC Huffman table");
+        }
+        if scan.se > 0 && ac_tables[component.ac_table_index].is_none() {
+            return Err("Missing JPEG AC Huffman table");
+        }
+    }
+
+    let mut last_dc = vec![0i32; jpeg_file.components.len()];
+    let mut eob_run = 0u32;
+
+    let mcu_count = mcu_width_count * mcu_height_count;
+
+    for mcu in 0..mcu_count {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            bit_reader.restart();
+            last_dc.iter_mut().for_each(|dc| *dc = 0);
+            eob_run = 0;
+        }
+
+        let mx = mcu % mcu_width_count;
+        let my = mcu / mcu_width_count;
+
+        for &index in &scan.components {
+            let component = &mut jpeg_file.components[index];
+
+            let (h, v) = if scan.components.len() == 1 {
+                (1, 1)
+            } else {
+                (component.h, component.v)
+            };
+
+            for by in 0..v {
+                for bx in 0..h {
+                    let block_x = mx * h + bx;
+                    let block_y = my * v + by;
+
+                    let block =
+                        &mut component.blocks[block_y * component.block_width_count + block_x];
+
+                    if scan.ss == 0 {
+                        if scan.ah == 0 {
+                            let table = dc_tables[component.dc_table_index].as_ref().unwrap();
+                            let size = bit_reader.decode(table)? as u32;
+                            if size > 11 {
+                                return Err("Invalid JPEG DC coefficient");
+                            }
+                            last_dc[index] += bit_reader.receive_extend(size);
+                            block[0] = last_dc[index] * (1 << scan.al);
+                        } else if bit_reader.read_bit() {
+                            block[0] |= 1 << scan.al;
+                        }
+                    }
+
+                    if scan.se == 0 {
+                        continue;
+                    }
+
+                    let table = ac_tables[component.ac_table_index].as_ref().unwrap();
+                    let start = scan.ss.max(1);
+
+                    if !jpeg_file.progressive {
+                        decode_ac_sequential(&mut bit_reader, table, block, natural_order)?;
+                    } else if scan.ah == 0 {
+                        decode_ac_first(
+                            &mut bit_reader,
+                            table,
+                            block,
+                            natural_order,
+                            start,
+                            scan.se,
+                            scan.al,
+                            &mut eob_run,
+                        )?;
+                    } else {
+                        decode_ac_refine(
+                            &mut bit_reader,
+                            table,
+                            block,
+                            natural_order,
+                            start,
+                            scan.se,
+                            scan.al,
+                            &mut eob_run,
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(bit_reader.position)
+}
+
+fn decode_ac_sequential(
+    bit_reader: &mut BitReader,
+    table: &HuffmanTable,
+    block: &mut [i32; 64],
+    natural_order: &[usize; 64],
+) -> Result<(), &'static str> {
+    let mut k = 1;
+    while k < 64 {
+        let rs = bit_reader.decode(table)?;
+        let run = (rs >> 4) as usize;
+        let size = (rs & 0x0F) as u32;
+
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+
+        k += run;
+        if k > 63 {
+            return Err("Invalid JPEG AC coefficient");
+        }
+        block[natural_order[k]] = bit_reader.receive_extend(size);
+        k += 1;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decode_ac_first(
+    bit_reader: &mut BitReader,
+    table: &HuffmanTable,
+    block: &mut [i32; 64],
+    natural_order: &[usize; 64],
+    start: usize,
+    end: usize,
+    al: u32,
+    eob_run: &mut u32,
+) -> Result<(), &'static str> {
+    if *eob_run > 0 {
+        *eob_run -= 1;
+        return Ok(());
+    }
+
+    let mut k = start;
+    while k <= end {
+        let rs = bit_reader.decode(table)?;
+        let run = (rs >> 4) as u32;
+        let size = (rs & 0x0F) as u32;
+
+        if size == 0 {
+            if run < 15 {
+                *eob_run = (1 << run) - 1;
+                if run > 0 {
+                    *eob_run += bit_reader.read_bits(run) as u32;
+                }
+                break;
+            }
+            k += 16;
+            continue;
+        }
+
+        k += run as usize;
+        if k > end {
+            return Err("Invalid JPEG AC coefficient");
+        }
+        block[natural_order[k]] = bit_reader.receive_extend(size) * (1 << al);
+        k += 1;
+    }
+    Ok(())
+}
+
+// Port of libjpeg decode_mcu_AC_refine
+#[allow(clippy::too_many_arguments)]
+fn decode_ac_refine(
+    bit_reader: &mut BitReader,
+    table: &HuffmanTable,
+    block: &mut [i32; 64],
+    natural_order: &[usize; 64],
+    start: usize,
+    end: usize,
+    al: u32,
+    eob_run: &mut u32,
+) -> Result<(), &'static str> {
+    let p1 = 1i32 << al;
+    let m1 = -1i32 << al;
+
+    let mut k = start;
+
+    if *eob_run == 0 {
+        while k <= end {
+            let rs = bit_reader.decode(table)?;
+            let mut run = (rs >> 4) as i32;
+            let size = rs & 0x0F;
+
+            let mut value = 0;
+            if size != 0 {
+                value = if bit_reader.read_bit() { p1 } else { m1 };
+            } else if run != 15 {
+                *eob_run = 1 << run;
+                if run > 0 {
+                    *eob_run += bit_reader.read_bits(run as u32) as u32;
+                }
+                break;
+            }
+
+            while k <= end {
+                let coefficient = &mut block[natural_order[k]];
+                if *coefficient != 0 {
+                    if bit_reader.read_bit() && (*coefficient & p1) == 0 {
+                        *coefficient += if *coefficient >= 0 { p1 } else { m1 };
+                    }
+                } else {
+                    run -= 1;
+                    if run < 0 {
+                        break;
+                    }
+                }
+                k += 1;
+            }
+
+            if value != 0 {
+                if k > end {
+                    return Err("Invalid JPEG AC refinement");
+                }
+                block[natural_order[k]] = value;
+            }
+            k += 1;
+        }
+    }
+
+    if *eob_run > 0 {
+        while k <= end {
+            let coefficient = &mut block[natural_order[k]];
+            if *coefficient != 0 && bit_reader.read_bit() && (*coefficient & p1) == 0 {
+                *coefficient += if *coefficient >= 0 { p1 } else { m1 };
+            }
+            k += 1;
+        }
+        *eob_run -= 1;
+    }
+
+    Ok(())
+}
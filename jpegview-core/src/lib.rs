@@ -0,0 +1,101 @@
+extern crate bincode;
+extern crate image;
+extern crate miniz_oxide;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate threadpool;
+
+extern crate fast_generated_dct;
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+pub mod adaptive_quant;
+pub mod alpha;
+pub mod bitrate;
+pub mod chroma;
+pub mod coefficient_coder;
+pub mod color_space;
+pub mod jfif;
+pub mod jpeg;
+pub mod jpeg_file;
+pub mod metrics;
+pub mod my_image;
+pub mod qmi;
+pub mod quad_mind;
+pub mod quad_tree;
+pub mod quant_table;
+pub mod rate_control;
+pub mod roi_mask;
+pub mod sweep;
+
+use chroma::{ChromaFilters, Subsampling};
+use color_space::ColorSpace;
+use my_image::MyImage;
+use quad_mind::QuadMindConfig;
+
+pub fn load_image(
+    path: &Path,
+    use_color_transform: bool,
+    subsampling: Subsampling,
+    chroma_filters: ChromaFilters,
+    color_space: ColorSpace,
+) -> Result<(MyImage, Option<QuadMindConfig>), String> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+
+    let (my_image, quad_mind_config) = match ext {
+        "jpg" | "jpeg" | "png" | "bmp" => {
+            let native_jpeg = match ext {
+                "jpg" | "jpeg" => jpeg_file::load_jpeg(path, true).ok(),
+                _ => None,
+            };
+
+            let mut my_image = match native_jpeg {
+                Some((my_image, _)) => my_image,
+                None => {
+                    let image = image::io::Reader::open(path)
+                        .map_err(|error| format!("Could not open image: {}", error))?
+                        .decode()
+                        .map_err(|error| format!("Could not decode image: {}", error))?;
+
+                    MyImage::from_dynamic_image(image, path.to_string_lossy().to_string())
+                }
+            };
+
+            my_image.apply_transform(
+                use_color_transform,
+                subsampling,
+                chroma_filters,
+                color_space,
+            );
+
+            (my_image, None)
+        }
+        "qmi" => {
+            let quad_mind = quad_mind::load_quad_mind(path)
+                .map_err(|error| format!("Could not load quad mind image: {}", error))?;
+            (quad_mind.0, Some(quad_mind.1))
+        }
+        _ => {
+            return Err(format!("Unsupported image format: {}", ext));
+        }
+    };
+
+    Ok((my_image, quad_mind_config))
+}
+
+pub type Vec2d<T> = Vec<Vec<T>>;
+pub type Vec3d<T> = Vec<Vec<Vec<T>>>;
+
+pub fn unwrap_arc_mutex<T>(value: Arc<Mutex<T>>) -> T
+where
+    T: std::fmt::Debug,
+{
+    Arc::try_unwrap(value).unwrap().into_inner().unwrap()
+}
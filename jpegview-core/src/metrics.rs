@@ -0,0 +1,296 @@
+#![allow(clippy::identity_op)]
+
+use crate::{color_space::ColorSpace, my_image::MyImage};
+use std::thread;
+
+// Wang et al. constants for SSIM with an 11x11 gaussian window
+const SSIM_K1: f32 = 0.01f32;
+const SSIM_K2: f32 = 0.03f32;
+const SSIM_SIGMA: f32 = 1.5f32;
+const SSIM_RADIUS: usize = 5;
+
+const MS_SSIM_WEIGHTS: [f32; 5] = [0.0448f32, 0.2856f32, 0.3001f32, 0.2363f32, 0.1333f32];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelMetrics {
+    pub mse: f32,
+    pub psnr: f32,
+    pub ssim: f32,
+    pub ms_ssim: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metrics {
+    pub mse: f32,
+    pub psnr: f32,
+
+    pub y: ChannelMetrics,
+    pub cb: ChannelMetrics,
+    pub cr: ChannelMetrics,
+
+    // Images with alpha compare it on its own
+    pub alpha: Option<ChannelMetrics>,
+}
+
+impl Metrics {
+    pub fn new(my_image: &MyImage) -> Metrics {
+        let mut metrics = Metrics::compare(
+            &my_image.original_image,
+            &my_image.final_image,
+            my_image.width,
+            my_image.height,
+        );
+
+        if let (Some(original), Some(result)) = (&my_image.original_alpha, &my_image.final_alpha) {
+            let original: Vec<f32> = original.iter().map(|&value| value as f32).collect();
+            let result: Vec<f32> = result.iter().map(|&value| value as f32).collect();
+
+            metrics.alpha = Some(channel_metrics(
+                &original,
+                &result,
+                my_image.width,
+                my_image.height,
+            ));
+        }
+        metrics
+    }
+    pub fn compare(original: &[u8], result: &[u8], width: usize, height: usize) -> Metrics {
+        let mse = mean_squared_error(original, result);
+
+        let original_planes = rgb_to_ycbcr_planes(original, width, height);
+        let result_planes = rgb_to_ycbcr_planes(result, width, height);
+
+        let channel = |index: usize| {
+            channel_metrics(
+                &original_planes[index],
+                &result_planes[index],
+                width,
+                height,
+            )
+        };
+
+        let channels: Vec<ChannelMetrics> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..3)
+                .map(|index| scope.spawn(move || channel(index)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        Metrics {
+            mse,
+            psnr: psnr(mse),
+
+            y: channels[0],
+            cb: channels[1],
+            cr: channels[2],
+
+            alpha: None,
+        }
+    }
+}
+
+fn channel_metrics(
+    original: &[f32],
+    result: &[f32],
+    width: usize,
+    height: usize,
+) -> ChannelMetrics {
+    let mse = mean_squared_error(original, result);
+    let (ssim, ms_ssim) = structural_similarity(original, result, width, height);
+
+    ChannelMetrics {
+        mse,
+        psnr: psnr(mse),
+        ssim,
+        ms_ssim,
+    }
+}
+
+pub fn psnr(mse: f32) -> f32 {
+    10.0f32 * (255.0f32 * 255.0f32 / mse).log10()
+}
+
+pub fn mean_squared_error<T>(original: &[T], result: &[T]) -> f32
+where
+    T: Copy + Into<f32>,
+{
+    let sum: f64 = original
+        .iter()
+        .zip(result.iter())
+        .map(|(&a, &b)| {
+            let difference = (a.into() - b.into()) as f64;
+            difference * difference
+        })
+        .sum();
+    (sum / original.len() as f64) as f32
+}
+
+#[rustfmt::skip]
+fn rgb_to_ycbcr_planes(image: &[u8], width: usize, height: usize) -> Vec<Vec<f32>> {
+    let mut planes = vec![vec![0.0f32; width * height]; 3];
+    for index in 0..width * height {
+        let r = image[index * 3 + 0] as f32;
+        let g = image[index * 3 + 1] as f32;
+        let b = image[index * 3 + 2] as f32;
+
+        let ycbcr = ColorSpace::default().to_ycbcr(r, g, b);
+        planes[0][index] = ycbcr[0];
+        planes[1][index] = ycbcr[1];
+        planes[2][index] = ycbcr[2];
+    }
+    planes
+}
+
+// Returns SSIM and MS-SSIM, SSIM is the first MS-SSIM scale. Scales that
+// no longer fit the gaussian window are dropped and the weights renormalized
+pub fn structural_similarity(
+    original: &[f32],
+    result: &[f32],
+    width: usize,
+    height: usize,
+) -> (f32, f32) {
+    let window_size = SSIM_RADIUS * 2 + 1;
+
+    let mut scale_count = 1;
+    while scale_count < MS_SSIM_WEIGHTS.len()
+        && (width >> scale_count) >= window_size
+        && (height >> scale_count) >= window_size
+    {
+        scale_count += 1;
+    }
+
+    let weight_sum: f32 = MS_SSIM_WEIGHTS[..scale_count].iter().sum();
+
+    let mut original = original.to_vec();
+    let mut result = result.to_vec();
+    let mut width = width;
+    let mut height = height;
+
+    let mut ssim = 0.0f32;
+    let mut value = 1.0f32;
+    for (scale, weight) in MS_SSIM_WEIGHTS[..scale_count].iter().enumerate() {
+        let (luminance, contrast_structure) = ssim_components(&original, &result, width, height);
+        let weight = weight / weight_sum;
+
+        if scale == 0 {
+            ssim = luminance * contrast_structure;
+        }
+
+        value *= contrast_structure.max(0.0f32).powf(weight);
+        if scale == scale_count - 1 {
+            value *= luminance.max(0.0f32).powf(weight);
+        } else {
+            original = down_sample(&original, width, height);
+            result = down_sample(&result, width, height);
+            width /= 2;
+            height /= 2;
+        }
+    }
+    (ssim, value)
+}
+
+// Returns the mean luminance and contrast-structure terms of SSIM
+fn ssim_components(original: &[f32], result: &[f32], width: usize, height: usize) -> (f32, f32) {
+    let c1 = (SSIM_K1 * 255.0f32).powi(2);
+    let c2 = (SSIM_K2 * 255.0f32).powi(2);
+
+    let kernel = gaussian_kernel();
+
+    let mu_x = gaussian_blur(original, width, height, &kernel);
+    let mu_y = gaussian_blur(result, width, height, &kernel);
+
+    let xx: Vec<f32> = original.iter().map(|x| x * x).collect();
+    let yy: Vec<f32> = result.iter().map(|y| y * y).collect();
+    let xy: Vec<f32> = original.iter().zip(result).map(|(x, y)| x * y).collect();
+
+    let sigma_xx = gaussian_blur(&xx, width, height, &kernel);
+    let sigma_yy = gaussian_blur(&yy, width, height, &kernel);
+    let sigma_xy = gaussian_blur(&xy, width, height, &kernel);
+
+    let mut luminance_sum = 0.0f64;
+    let mut contrast_structure_sum = 0.0f64;
+    for i in 0..width * height {
+        let mu_xx = mu_x[i] * mu_x[i];
+        let mu_yy = mu_y[i] * mu_y[i];
+        let mu_xy = mu_x[i] * mu_y[i];
+
+        let variance_x = sigma_xx[i] - mu_xx;
+        let variance_y = sigma_yy[i] - mu_yy;
+        let covariance = sigma_xy[i] - mu_xy;
+
+        luminance_sum += ((2.0f32 * mu_xy + c1) / (mu_xx + mu_yy + c1)) as f64;
+        contrast_structure_sum +=
+            ((2.0f32 * covariance + c2) / (variance_x + variance_y + c2)) as f64;
+    }
+
+    let count = (width * height) as f64;
+    (
+        (luminance_sum / count) as f32,
+        (contrast_structure_sum / count) as f32,
+    )
+}
+
+fn gaussian_kernel() -> Vec<f32> {
+    let mut kernel: Vec<f32> = (0..SSIM_RADIUS * 2 + 1)
+        .map(|i| {
+            let x = i as f32 - SSIM_RADIUS as f32;
+            (-(x * x) / (2.0f32 * SSIM_SIGMA * SSIM_SIGMA)).exp()
+        })
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|value| *value /= sum);
+    kernel
+}
+
+// Separable blur, borders are clamped to the edge
+fn gaussian_blur(plane: &[f32], width: usize, height: usize, kernel: &[f32]) -> Vec<f32> {
+    let radius = kernel.len() / 2;
+
+    let mut horizontal = vec![0.0f32; width * height];
+    let mut padded_row = vec![0.0f32; width + radius * 2];
+    for y in 0..height {
+        let row = &plane[y * width..(y + 1) * width];
+        for (i, value) in padded_row.iter_mut().enumerate() {
+            *value = row[i.saturating_sub(radius).min(width - 1)];
+        }
+        for x in 0..width {
+            horizontal[y * width + x] = padded_row[x..x + kernel.len()]
+                .iter()
+                .zip(kernel)
+                .map(|(value, weight)| value * weight)
+                .sum();
+        }
+    }
+
+    let mut blurred = vec![0.0f32; width * height];
+    for y in 0..height {
+        let blurred_row = &mut blurred[y * width..(y + 1) * width];
+        for (k, weight) in kernel.iter().enumerate() {
+            let sy = (y + k).saturating_sub(radius).min(height - 1);
+            let row = &horizontal[sy * width..(sy + 1) * width];
+            for (value, source) in blurred_row.iter_mut().zip(row) {
+                *value += source * weight;
+            }
+        }
+    }
+    blurred
+}
+
+fn down_sample(plane: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let new_width = width / 2;
+    let new_height = height / 2;
+
+    let mut result = vec![0.0f32; new_width * new_height];
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let index = y * 2 * width + x * 2;
+            result[y * new_width + x] =
+                (plane[index] + plane[index + 1] + plane[index + width] + plane[index + width + 1])
+                    * 0.25f32;
+        }
+    }
+    result
+}
@@ -0,0 +1,296 @@
+#![allow(clippy::identity_op)]
+
+use crate::{
+    chroma::{self, ChromaFilters, Subsampling},
+    color_space::ColorSpace,
+    Vec2d,
+};
+use std::path::Path;
+
+pub struct MyImage {
+    pub final_image: Vec<u8>,
+    pub original_image: Vec<u8>,
+    pub image_converted: Vec2d<i16>,
+    // Range of every converted plane, wider than a byte for 9 bit chroma
+    pub component_ranges: [(i16, i16); 3],
+
+    // Alpha planes of the original and the result, None when opaque
+    pub original_alpha: Option<Vec<u8>>,
+    pub final_alpha: Option<Vec<u8>>,
+
+    pub file_path: String,
+
+    pub width: usize,
+    pub height: usize,
+    pub mwidth: usize,
+    pub mheight: usize,
+}
+
+impl MyImage {
+    pub fn new(original: Vec<u8>, width: usize, height: usize, file_path: String) -> MyImage {
+        MyImage {
+            final_image: Vec::new(),
+            image_converted: Vec::new(),
+            component_ranges: [BYTE_RANGE; 3],
+            original_image: original,
+
+            original_alpha: None,
+            final_alpha: None,
+
+            file_path,
+
+            width,
+            height,
+            mwidth: width,
+            mheight: height,
+        }
+    }
+    // Keeps the alpha of images that have one unless it is fully opaque
+    pub fn from_dynamic_image(image: image::DynamicImage, file_path: String) -> MyImage {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+
+        if !image.color().has_alpha() {
+            return MyImage::new(image.into_rgb8().into_vec(), width, height, file_path);
+        }
+
+        let rgba = image.into_rgba8().into_vec();
+
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        let mut alpha = Vec::with_capacity(width * height);
+        for pixel in rgba.chunks_exact(4) {
+            rgb.extend_from_slice(&pixel[..3]);
+            alpha.push(pixel[3]);
+        }
+
+        let mut my_image = MyImage::new(rgb, width, height, file_path);
+        if alpha.iter().any(|&value| value != 0xFF) {
+            my_image.final_alpha = Some(alpha.clone());
+            my_image.original_alpha = Some(alpha);
+        }
+        my_image
+    }
+    pub fn image_to_ycbcr(&mut self, color_space: ColorSpace) {
+        self.image_converted = vec![vec![0i16; self.mheight * self.mwidth]; 3];
+        self.component_ranges = color_space.component_ranges();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index_ycbcr = y * self.mwidth + x;
+                let index_original = (y * self.width + x) * 3;
+
+                let r = self.original_image[index_original + 0] as f32;
+                let g = self.original_image[index_original + 1] as f32;
+                let b = self.original_image[index_original + 2] as f32;
+
+                let components = color_space.forward(r, g, b);
+                for ((plane, value), range) in self
+                    .image_converted
+                    .iter_mut()
+                    .zip(components)
+                    .zip(self.component_ranges)
+                {
+                    plane[index_ycbcr] = min_max_component(value + 0.5f32, range);
+                }
+            }
+        }
+    }
+    pub fn ycbcr_to_image(&mut self, color_space: ColorSpace) {
+        self.final_image = vec![0u8; self.height * self.width * 3];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index_ycbcr = y * self.mwidth + x;
+                let index_result = (y * self.width + x) * 3;
+
+                let rgb = color_space.inverse(
+                    self.image_converted[0][index_ycbcr] as f32,
+                    self.image_converted[1][index_ycbcr] as f32,
+                    self.image_converted[2][index_ycbcr] as f32,
+                );
+                for (result, value) in self.final_image[index_result..index_result + 3]
+                    .iter_mut()
+                    .zip(rgb)
+                {
+                    *result = min_max_color(value + 0.5f32);
+                }
+            }
+        }
+    }
+    pub fn image_to_rgb(&mut self) {
+        self.image_converted = vec![vec![0i16; self.mheight * self.mwidth]; 3];
+        self.component_ranges = [BYTE_RANGE; 3];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index_rgb = y * self.mwidth + x;
+                let index_original = (y * self.width + x) * 3;
+
+                self.image_converted[0][index_rgb] = self.original_image[index_original + 0] as i16;
+                self.image_converted[1][index_rgb] = self.original_image[index_original + 1] as i16;
+                self.image_converted[2][index_rgb] = self.original_image[index_original + 2] as i16;
+            }
+        }
+    }
+    pub fn rgb_to_image(&mut self) {
+        self.final_image = vec![0u8; self.height * self.width * 3];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index_rgb = y * self.mwidth + x;
+                let index_result = (y * self.width + x) * 3;
+
+                self.final_image[index_result + 0] = self.image_converted[0][index_rgb] as u8;
+                self.final_image[index_result + 1] = self.image_converted[1][index_rgb] as u8;
+                self.final_image[index_result + 2] = self.image_converted[2][index_rgb] as u8;
+            }
+        }
+    }
+    // Sends the subsampled components through their real resolution and
+    // back, the image keeps its size
+    pub fn sub_sampling(
+        &mut self,
+        use_color_transform: bool,
+        subsampling: Subsampling,
+        chroma_filters: ChromaFilters,
+    ) {
+        let factors = subsampling.factors();
+        if factors == (1, 1) {
+            return;
+        }
+
+        for i in usize::from(use_color_transform)..3 {
+            let (plane_width, plane_height) =
+                chroma::plane_size(self.width, self.height, factors.0, factors.1);
+
+            let plane = self.sub_sampled_plane(i, factors, chroma_filters);
+            let upsampled = chroma::upsample(
+                &plane,
+                plane_width,
+                plane_height,
+                factors,
+                self.width,
+                self.height,
+                chroma_filters,
+            );
+
+            for y in 0..self.height {
+                self.image_converted[i][y * self.mwidth..y * self.mwidth + self.width]
+                    .copy_from_slice(&upsampled[y * self.width..(y + 1) * self.width]);
+            }
+        }
+    }
+    // A component at its subsampled size, as stored
+    pub fn sub_sampled_plane(
+        &self,
+        component: usize,
+        factors: (usize, usize),
+        chroma_filters: ChromaFilters,
+    ) -> Vec<i16> {
+        let range = self.component_ranges[component];
+        let plane: Vec<f32> = self.image_converted[component]
+            .iter()
+            .map(|&value| value as f32)
+            .collect();
+
+        chroma::downsample(
+            &plane,
+            self.mwidth,
+            self.width,
+            self.height,
+            factors,
+            chroma_filters.downsample,
+            chroma_filters.siting,
+        )
+        .into_iter()
+        .map(|value| min_max_component(value + 0.5f32, range))
+        .collect()
+    }
+    pub fn fill_outbound(&mut self) {
+        for y in 0..self.mheight {
+            for x in self.width..self.mwidth {
+                let index_result = y * self.mwidth + x;
+                self.image_converted[0][index_result] = 128;
+                self.image_converted[1][index_result] = 128;
+                self.image_converted[2][index_result] = 128;
+            }
+        }
+        for y in self.height..self.mheight {
+            for x in 0..self.mwidth {
+                let index_result = y * self.mwidth + x;
+                self.image_converted[0][index_result] = 128;
+                self.image_converted[1][index_result] = 128;
+                self.image_converted[2][index_result] = 128;
+            }
+        }
+    }
+    pub fn apply_transform(
+        &mut self,
+        use_color_transform: bool,
+        subsampling: Subsampling,
+        chroma_filters: ChromaFilters,
+        color_space: ColorSpace,
+    ) {
+        if use_color_transform {
+            self.image_to_ycbcr(color_space);
+            self.fill_outbound();
+            self.sub_sampling(true, subsampling, chroma_filters);
+            self.ycbcr_to_image(color_space);
+        } else {
+            self.image_to_rgb();
+            self.fill_outbound();
+            self.sub_sampling(false, subsampling, chroma_filters);
+            self.rgb_to_image();
+        }
+        self.final_alpha = self.original_alpha.clone();
+    }
+    // Saves the result with its alpha when it has one
+    pub fn save_final_image(&self, path: &Path) -> image::ImageResult<()> {
+        match &self.final_alpha {
+            Some(alpha) => image::save_buffer(
+                path,
+                &pixels_with_alpha(&self.final_image, alpha),
+                self.width as u32,
+                self.height as u32,
+                image::ColorType::Rgba8,
+            ),
+            None => image::save_buffer(
+                path,
+                &self.final_image,
+                self.width as u32,
+                self.height as u32,
+                image::ColorType::Rgb8,
+            ),
+        }
+    }
+    pub fn round_up_size(&mut self, block_size: usize) {
+        let x = self.mwidth + (block_size - 1);
+        let y = self.mheight + (block_size - 1);
+        self.mwidth = x - (x % block_size);
+        self.mheight = y - (y % block_size);
+    }
+}
+
+// Interleaves RGB pixels with their alpha into RGBA pixels
+pub fn pixels_with_alpha(pixels: &[u8], alpha: &[u8]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(alpha.len() * 4);
+    for (pixel, &value) in pixels.chunks_exact(3).zip(alpha) {
+        rgba.extend_from_slice(pixel);
+        rgba.push(value);
+    }
+    rgba
+}
+
+pub const BYTE_RANGE: (i16, i16) = (0, 255);
+
+// Clamps a converted component to its range, rounding down like
+// min_max_color
+pub fn min_max_component(value: f32, range: (i16, i16)) -> i16 {
+    (value.floor() as i32).clamp(range.0 as i32, range.1 as i32) as i16
+}
+
+pub fn min_max_color(color: f32) -> u8 {
+    if color > 255.0f32 {
+        255
+    } else if color < 0.0f32 {
+        0
+    } else {
+        color as u8
+    }
+}
@@ -0,0 +1,246 @@
+use crate::quad_mind::QuadMindError;
+
+// QMI container: magic, version and then chunks of id, length, data and a
+// CRC-32 of id and data, PNG style. Chunks whose id starts with a lowercase
+// letter are optional and can be skipped by readers that do not know them.
+// Version 1 is the bincode QuadMindFile layout, it has no magic. Version 3
+// requires the decode settings chunk, version 2 kept them in the optional
+// encoder settings
+
+pub const QMI_MAGIC: &[u8; 8] = b"QMIFILE\n";
+pub const QMI_VERSION: u16 = 3;
+
+pub const CHUNK_HEADER: [u8; 4] = *b"HEAD";
+pub const CHUNK_DECODE_SETTINGS: [u8; 4] = *b"QCFG";
+pub const CHUNK_QUAD_NODES: [u8; 4] = *b"QNOD";
+pub const CHUNK_COEFFICIENTS: [u8; 4] = *b"QCOF";
+pub const CHUNK_Q_MATRICES: [u8; 4] = *b"QTAB";
+pub const CHUNK_ADAPTIVE_SCALES: [u8; 4] = *b"QSCL";
+pub const CHUNK_ENCODER_SETTINGS: [u8; 4] = *b"ecfg";
+pub const CHUNK_ALPHA: [u8; 4] = *b"alph";
+pub const CHUNK_END: [u8; 4] = *b"QEND";
+
+pub struct QmiChunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl QmiChunk {
+    pub fn new(id: [u8; 4], data: Vec<u8>) -> QmiChunk {
+        QmiChunk { id, data }
+    }
+    pub fn is_critical(&self) -> bool {
+        self.id[0].is_ascii_uppercase()
+    }
+}
+
+pub fn is_qmi_container(contents: &[u8]) -> bool {
+    contents.starts_with(QMI_MAGIC)
+}
+
+// The end chunk is added here, chunks must not contain it
+pub fn write_qmi(chunks: &[QmiChunk]) -> Vec<u8> {
+    let mut contents = Vec::new();
+    contents.extend_from_slice(QMI_MAGIC);
+    contents.extend_from_slice(&QMI_VERSION.to_le_bytes());
+
+    for chunk in chunks
+        .iter()
+        .chain([QmiChunk::new(CHUNK_END, Vec::new())].iter())
+    {
+        contents.extend_from_slice(&chunk.id);
+        contents.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+        contents.extend_from_slice(&chunk.data);
+        contents.extend_from_slice(&chunk_crc(chunk).to_le_bytes());
+    }
+    contents
+}
+
+// Returns the version and every chunk before the end chunk with its CRC
+// checked
+pub fn read_qmi(contents: &[u8]) -> Result<(u16, Vec<QmiChunk>), QuadMindError> {
+    let mut byte_reader = ByteReader::new(contents);
+
+    if byte_reader.read_bytes(QMI_MAGIC.len())? != QMI_MAGIC {
+        return Err(QuadMindError::Signature("Wrong QMI magic"));
+    }
+
+    let version = byte_reader.read_u16()?;
+    if version > QMI_VERSION {
+        return Err(QuadMindError::Version(version));
+    }
+
+    let mut chunks = Vec::new();
+    loop {
+        let mut id = [0u8; 4];
+        id.copy_from_slice(byte_reader.read_bytes(4)?);
+
+        let length = byte_reader.read_u32()? as usize;
+        let chunk = QmiChunk::new(id, byte_reader.read_bytes(length)?.to_vec());
+
+        if byte_reader.read_u32()? != chunk_crc(&chunk) {
+            return Err(QuadMindError::Checksum("Wrong QMI chunk CRC"));
+        }
+
+        if chunk.id == CHUNK_END {
+            return Ok((version, chunks));
+        }
+        chunks.push(chunk);
+    }
+}
+
+fn chunk_crc(chunk: &QmiChunk) -> u32 {
+    !crc32_update(crc32_update(u32::MAX, &chunk.id), &chunk.data)
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(u32::MAX, data)
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+// Little endian reader over chunk data that fails instead of panicking
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { data, position: 0 }
+    }
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], QuadMindError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(QuadMindError::Truncated("Unexpected end of QMI data"))?;
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+    pub fn read_u8(&mut self) -> Result<u8, QuadMindError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+    pub fn read_u16(&mut self) -> Result<u16, QuadMindError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+    pub fn read_u32(&mut self) -> Result<u32, QuadMindError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+    pub fn read_f32(&mut self) -> Result<f32, QuadMindError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Magic, version, then the id and length of the first chunk
+    const FIRST_DATA: usize = 8 + 2 + 4 + 4;
+
+    fn contents() -> Vec<u8> {
+        write_qmi(&[
+            QmiChunk::new(CHUNK_HEADER, vec![1, 2, 3, 4, 5]),
+            QmiChunk::new(CHUNK_ALPHA, Vec::new()),
+            QmiChunk::new(CHUNK_COEFFICIENTS, (0..=255).collect()),
+        ])
+    }
+
+    #[test]
+    fn round_trip() {
+        let (version, chunks) = read_qmi(&contents()).unwrap();
+
+        assert_eq!(version, QMI_VERSION);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].id == CHUNK_HEADER && chunks[0].data == [1, 2, 3, 4, 5]);
+        assert!(chunks[1].id == CHUNK_ALPHA && chunks[1].data.is_empty());
+        assert!(!chunks[1].is_critical());
+        assert!(chunks[2].id == CHUNK_COEFFICIENTS && chunks[2].data.len() == 256);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn flipped_crc_fails() {
+        let mut contents = contents();
+        contents[FIRST_DATA + 5] ^= 0x01;
+
+        assert!(matches!(
+            read_qmi(&contents),
+            Err(QuadMindError::Checksum(_))
+        ));
+    }
+
+    #[test]
+    fn flipped_data_fails() {
+        let mut contents = contents();
+        contents[FIRST_DATA + 2] ^= 0x80;
+
+        assert!(matches!(
+            read_qmi(&contents),
+            Err(QuadMindError::Checksum(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_chunk_fails() {
+        let contents = contents();
+
+        // Inside the coefficients, at the CRC of the end chunk and without it
+        for length in [
+            contents.len() - 100,
+            contents.len() - 2,
+            contents.len() - 12,
+        ] {
+            assert!(matches!(
+                read_qmi(&contents[..length]),
+                Err(QuadMindError::Truncated(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn oversized_length_fails() {
+        let mut contents = contents();
+        contents[FIRST_DATA - 4..FIRST_DATA].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            read_qmi(&contents),
+            Err(QuadMindError::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn newer_version_fails() {
+        let mut contents = contents();
+        contents[8..10].copy_from_slice(&(QMI_VERSION + 1).to_le_bytes());
+
+        assert!(matches!(
+            read_qmi(&contents),
+            Err(QuadMindError::Version(_))
+        ));
+    }
+}
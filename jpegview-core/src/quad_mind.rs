@@ -0,0 +1,2167 @@
+#![allow(clippy::needless_range_loop)]
+
+use crate::{
+    adaptive_quant,
+    alpha::{self, AlphaParameters},
+    chroma::{ChromaFilters, ChromaSiting, DownsampleFilter, Subsampling, UpsampleFilter},
+    coefficient_coder::{CoefficientDecoder, CoefficientEncoder},
+    color_space::{ColorRange, ColorSpace, ColorTransform, YcbcrMatrix},
+    jpeg::{self, CsfParameters, Jpeg, JpegSteps, QuantizerParameters},
+    my_image::{self, MyImage},
+    qmi::{self, ByteReader, QmiChunk},
+    quad_tree::{self, QuadNode, QuadNodeRef, QuadTree},
+    unwrap_arc_mutex, Vec2d, Vec3d,
+};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use miniz_oxide::inflate::TINFLStatus;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+pub fn render_quad_mind(
+    jpeg: &mut Jpeg,
+    my_image: &mut MyImage,
+    quad_tree: &QuadTree,
+    use_color_transform: bool,
+    use_threads: bool,
+    subsampling: Subsampling,
+) -> (Vec<QuadNodeRef>, Vec3d<i32>, usize) {
+    let square_size = if my_image.width > my_image.height {
+        my_image.width.next_power_of_two()
+    } else {
+        my_image.height.next_power_of_two()
+    };
+
+    my_image.final_image = my_image.original_image.to_vec();
+
+    let mut quad_root = QuadNode::new(my_image, 0, 0, square_size, square_size, 0);
+
+    let mut quad_node_list: Vec<QuadNodeRef> = Vec::new();
+    quad_tree.build(&mut quad_root, &mut quad_node_list, my_image);
+
+    my_image.mwidth = my_image.width;
+    my_image.mheight = my_image.height;
+
+    for quad in &quad_node_list {
+        let quad = quad.borrow();
+        if quad.box_right > my_image.mwidth {
+            my_image.mwidth = quad.box_right;
+        }
+        if quad.box_bottom > my_image.mheight {
+            my_image.mheight = quad.box_bottom;
+        }
+    }
+
+    jpeg.use_threads = use_threads;
+
+    my_image.round_up_size(quad_tree.max_size);
+
+    if use_color_transform {
+        my_image.image_to_ycbcr(jpeg.color_space);
+        my_image.fill_outbound();
+        my_image.sub_sampling(true, subsampling, jpeg.chroma_filters);
+    } else {
+        my_image.image_to_rgb();
+        my_image.fill_outbound();
+        my_image.sub_sampling(false, subsampling, jpeg.chroma_filters);
+    }
+
+    let table_size = (quad_tree.max_size as f32).log2().ceil() as usize;
+
+    let (dct_table, alpha_table) = if !jpeg.use_fast_dct {
+        let mut dct_table = Vec::with_capacity(table_size);
+        let mut alpha_table = Vec::with_capacity(table_size);
+
+        for i in 0..table_size {
+            let block_size = 1 << (i + 1);
+
+            dct_table.push(Arc::new(jpeg::generate_dct_table(block_size)));
+            alpha_table.push(Arc::new(jpeg::generate_alpha_table(block_size)));
+        }
+
+        (dct_table, alpha_table)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    let QuadMindQMatrices {
+        luma: q_matrix_luma,
+        chroma: q_matrix_chroma,
+        ..
+    } = QuadMindQMatrices::new(jpeg, quad_tree.max_size);
+
+    let q_matrix_luma = Arc::new(q_matrix_luma);
+    let q_matrix_chroma = Arc::new(q_matrix_chroma);
+
+    if jpeg.use_adaptive_quantization {
+        let activities: Vec<f32> = quad_node_list
+            .iter()
+            .map(|quad| {
+                let quad = quad.borrow();
+
+                let mut luma_block =
+                    Vec::with_capacity(quad.width_block_size * quad.width_block_size);
+                for y in 0..quad.width_block_size {
+                    let index = (quad.box_top + y) * my_image.mwidth + quad.box_left;
+                    luma_block.extend(
+                        my_image.image_converted[0][index..index + quad.width_block_size]
+                            .iter()
+                            .map(|&value| value as f32 - 128.0f32),
+                    );
+                }
+
+                adaptive_quant::block_activity(&luma_block, quad.width_block_size)
+            })
+            .collect();
+
+        let codes = adaptive_quant::scale_codes(&activities, jpeg.adaptive_strength);
+        for (quad, code) in quad_node_list.iter().zip(codes) {
+            quad.borrow_mut().adaptive_scale_code = code;
+        }
+    }
+
+    if jpeg.use_roi_mask() {
+        for quad in &quad_node_list {
+            let mut quad = quad.borrow_mut();
+            quad.adaptive_scale_code = jpeg.roi_scale_code(
+                quad.adaptive_scale_code,
+                quad.box_left,
+                quad.box_top,
+                quad.width_block_size,
+                my_image.width,
+                my_image.height,
+            );
+        }
+    }
+
+    let use_block_scales = jpeg.use_block_scales();
+
+    let node_q_matrices = |quad: &QuadNode| {
+        adaptive_quant::block_q_matrices(
+            &q_matrix_luma,
+            &q_matrix_chroma,
+            quad.width_block_size * quad.width_block_size,
+            Some(quad.adaptive_scale_code).filter(|_| use_block_scales),
+        )
+    };
+
+    let mut jpeg_steps = JpegSteps::new(jpeg, my_image.mwidth);
+
+    let (final_result_block, final_dct_zig_zag_block) = if jpeg.use_threads {
+        let mut zig_zag_table = Vec::with_capacity(table_size);
+
+        for i in 0..table_size {
+            let block_size = 1 << (i + 1);
+
+            zig_zag_table.push(Arc::new(generate_zig_zag_table(block_size)));
+        }
+
+        let mut image_block = Vec::with_capacity(quad_node_list.len());
+        let mut result_block = Vec::with_capacity(quad_node_list.len());
+        let mut jpeg_steps_list = Vec::with_capacity(quad_node_list.len());
+        let mut dct_zig_zag_block = Vec::with_capacity(quad_node_list.len());
+
+        for quad in &quad_node_list {
+            let quad = quad.borrow();
+
+            jpeg_steps_list.push(Arc::new({
+                let mut jpeg_steps = jpeg_steps.clone();
+
+                let table_index = (quad.width_block_size as f32).log2().ceil() as usize - 1;
+
+                if !jpeg.use_fast_dct {
+                    jpeg_steps.dct_table = Some(Arc::clone(&dct_table[table_index]));
+                    jpeg_steps.alpha_table = Some(Arc::clone(&alpha_table[table_index]));
+                    jpeg_steps.two_block_size = 2.0f32 / quad.width_block_size as f32;
+                }
+
+                jpeg_steps.block_size_index = table_index;
+                jpeg_steps.block_size = quad.width_block_size;
+
+                jpeg_steps
+            }));
+
+            let mut solo_image_block: Vec2d<f32> =
+                vec![vec![0.0f32; quad.width_block_size * quad.width_block_size]; 3];
+
+            for j in 0..3 {
+                for y in 0..quad.width_block_size {
+                    for x in 0..quad.width_block_size {
+                        let index_image_block = y * quad.width_block_size + x;
+                        let index_image_converted =
+                            (quad.box_top + y) * my_image.mwidth + (quad.box_left + x);
+
+                        solo_image_block[j][index_image_block] =
+                            my_image.image_converted[j][index_image_converted] as f32 - 128.0f32;
+                    }
+                }
+            }
+
+            image_block.push(Arc::new(solo_image_block));
+
+            result_block.push(Arc::new(Mutex::new(vec![
+                vec![
+                    0.0f32;
+                    quad.width_block_size
+                        * quad.width_block_size
+                ];
+                3
+            ])));
+
+            dct_zig_zag_block.push(Arc::new(Mutex::new(vec![
+                vec![
+                    0i32;
+                    quad.width_block_size
+                        * quad.width_block_size
+                ];
+                3
+            ])));
+        }
+
+        let cpu_threads = thread::available_parallelism().unwrap().get();
+        let pool = threadpool::ThreadPool::with_name(String::from("jpegview-worker"), cpu_threads);
+
+        for i in 0..quad_node_list.len() {
+            let quad = quad_node_list[i].borrow();
+
+            let quad_box_left = quad.box_left;
+            let table_index = (quad.width_block_size as f32).log2().ceil() as usize - 1;
+
+            let arc_jpeg_steps = Arc::clone(&jpeg_steps_list[i]);
+            let arc_image_block = Arc::clone(&image_block[i]);
+            let arc_result_block = Arc::clone(&result_block[i]);
+            let arc_zig_zag_table = Arc::clone(&zig_zag_table[table_index]);
+            let (arc_q_matrix_luma, arc_q_matrix_chroma) = node_q_matrices(&quad);
+            let arc_dct_zig_zag_block = Arc::clone(&dct_zig_zag_block[i]);
+
+            pool.execute(move || {
+                let arc_locked_result_block = &mut arc_result_block.lock().unwrap();
+                let arc_locked_dct_zig_zag_block = &mut arc_dct_zig_zag_block.lock().unwrap();
+                (arc_locked_result_block[0], arc_locked_dct_zig_zag_block[0]) = quad_mind_steps(
+                    quad_box_left,
+                    &arc_jpeg_steps,
+                    &arc_image_block[0],
+                    &arc_q_matrix_luma,
+                    &arc_zig_zag_table,
+                );
+                (arc_locked_result_block[1], arc_locked_dct_zig_zag_block[1]) = quad_mind_steps(
+                    quad_box_left,
+                    &arc_jpeg_steps,
+                    &arc_image_block[1],
+                    &arc_q_matrix_chroma,
+                    &arc_zig_zag_table,
+                );
+                (arc_locked_result_block[2], arc_locked_dct_zig_zag_block[2]) = quad_mind_steps(
+                    quad_box_left,
+                    &arc_jpeg_steps,
+                    &arc_image_block[2],
+                    &arc_q_matrix_chroma,
+                    &arc_zig_zag_table,
+                );
+            });
+        }
+        pool.join();
+
+        (
+            result_block.into_iter().map(unwrap_arc_mutex).collect(),
+            dct_zig_zag_block
+                .into_iter()
+                .map(unwrap_arc_mutex)
+                .collect(),
+        )
+    } else {
+        let mut zig_zag_table = Vec::with_capacity(table_size);
+
+        for i in 0..table_size {
+            let block_size = 1 << (i + 1);
+
+            zig_zag_table.push(generate_zig_zag_table(block_size));
+        }
+
+        let mut image_block = Vec::with_capacity(quad_node_list.len());
+        let mut result_block = Vec::with_capacity(quad_node_list.len());
+        let mut dct_zig_zag_block = Vec::with_capacity(quad_node_list.len());
+
+        for quad in &quad_node_list {
+            let quad = quad.borrow();
+
+            let mut solo_image_block: Vec2d<f32> =
+                vec![vec![0.0f32; quad.width_block_size * quad.width_block_size]; 3];
+
+            for j in 0..3 {
+                for y in 0..quad.width_block_size {
+                    for x in 0..quad.width_block_size {
+                        let index_image_block = y * quad.width_block_size + x;
+                        let index_image_converted =
+                            (quad.box_top + y) * my_image.mwidth + (quad.box_left + x);
+
+                        solo_image_block[j][index_image_block] =
+                            my_image.image_converted[j][index_image_converted] as f32 - 128.0f32;
+                    }
+                }
+            }
+
+            image_block.push(solo_image_block);
+
+            result_block.push(vec![
+                vec![
+                    0.0f32;
+                    quad.width_block_size * quad.width_block_size
+                ];
+                3
+            ]);
+
+            dct_zig_zag_block.push(vec![
+                vec![
+                    0i32;
+                    quad.width_block_size * quad.width_block_size
+                ];
+                3
+            ]);
+        }
+
+        for i in 0..quad_node_list.len() {
+            let quad = quad_node_list[i].borrow();
+
+            let table_index = (quad.width_block_size as f32).log2().ceil() as usize - 1;
+
+            if !jpeg.use_fast_dct {
+                jpeg_steps.dct_table = Some(Arc::clone(&dct_table[table_index]));
+                jpeg_steps.alpha_table = Some(Arc::clone(&alpha_table[table_index]));
+                jpeg_steps.two_block_size = 2.0f32 / quad.width_block_size as f32;
+            }
+
+            jpeg_steps.block_size_index = table_index;
+            jpeg_steps.block_size = quad.width_block_size;
+
+            let (q_matrix_luma, q_matrix_chroma) = node_q_matrices(&quad);
+
+            (result_block[i][0], dct_zig_zag_block[i][0]) = quad_mind_steps(
+                quad.box_left,
+                &jpeg_steps,
+                &image_block[i][0],
+                &q_matrix_luma,
+                &zig_zag_table[table_index],
+            );
+            (result_block[i][1], dct_zig_zag_block[i][1]) = quad_mind_steps(
+                quad.box_left,
+                &jpeg_steps,
+                &image_block[i][1],
+                &q_matrix_chroma,
+                &zig_zag_table[table_index],
+            );
+            (result_block[i][2], dct_zig_zag_block[i][2]) = quad_mind_steps(
+                quad.box_left,
+                &jpeg_steps,
+                &image_block[i][2],
+                &q_matrix_chroma,
+                &zig_zag_table[table_index],
+            );
+        }
+
+        (result_block, dct_zig_zag_block)
+    };
+
+    let mut result: Vec2d<i16> = vec![vec![0i16; my_image.mheight * my_image.mwidth]; 3];
+
+    for i in 0..quad_node_list.len() {
+        let quad = quad_node_list[i].borrow();
+
+        for j in 0..3 {
+            for y in 0..quad.width_block_size {
+                for x in 0..quad.width_block_size {
+                    let index_result_block = y * quad.width_block_size + x;
+                    let index_result = (quad.box_top + y) * my_image.mwidth + (quad.box_left + x);
+
+                    result[j][index_result] = my_image::min_max_component(
+                        final_result_block[i][j][index_result_block] + 128.0f32,
+                        my_image.component_ranges[j],
+                    );
+                }
+            }
+        }
+    }
+
+    my_image.image_converted = result;
+
+    if use_color_transform {
+        my_image.ycbcr_to_image(jpeg.color_space)
+    } else {
+        my_image.rgb_to_image()
+    }
+
+    if quad_tree.use_draw_line {
+        for quad in &quad_node_list {
+            let quad = quad.borrow();
+            let quad_boxr = if quad.box_right >= my_image.width {
+                my_image.width - 1
+            } else {
+                quad.box_right
+            };
+            let quad_boxb = if quad.box_bottom >= my_image.height {
+                my_image.height - 1
+            } else {
+                quad.box_bottom
+            };
+            quad_tree::draw_rect(
+                &mut my_image.final_image,
+                my_image.width,
+                quad.box_left,
+                quad.box_top,
+                quad_boxr,
+                quad_boxb,
+            );
+        }
+    }
+
+    if use_block_scales && jpeg.use_draw_adaptive_scale {
+        for quad in &quad_node_list {
+            let quad = quad.borrow();
+            adaptive_quant::draw_scale_overlay(
+                &mut my_image.final_image,
+                my_image.width,
+                my_image.height,
+                quad.box_left,
+                quad.box_top,
+                quad.width_block_size,
+                quad.adaptive_scale_code,
+            );
+        }
+    }
+
+    alpha::render_alpha(my_image, jpeg.alpha);
+
+    let file_size = encode_quad_mind(
+        &quad_node_list,
+        &final_dct_zig_zag_block,
+        my_image,
+        jpeg,
+        quad_tree,
+        use_color_transform,
+        use_threads,
+        subsampling,
+    )
+    .len();
+
+    (quad_node_list, final_dct_zig_zag_block, file_size)
+}
+
+fn quad_mind_steps(
+    start_x: usize,
+    jpeg_steps: &JpegSteps,
+    image_block: &[f32],
+    q_matrix: &[f32],
+    zig_zag_table: &[usize],
+) -> (Vec<f32>, Vec<i32>) {
+    let mut dct_matrix = jpeg_steps.dct_function(image_block);
+    jpeg_steps.quantize_function(start_x, q_matrix, &mut dct_matrix, false);
+    let dct_matrix_zig_zag = zig_zag_function(zig_zag_table, jpeg_steps.block_size, &dct_matrix);
+
+    if jpeg_steps.use_compression_rate {
+        dct_matrix = jpeg_steps.dct_function(image_block);
+        jpeg_steps.quantize_function(start_x, q_matrix, &mut dct_matrix, true);
+        jpeg_steps.de_quantize_function(start_x, q_matrix, &mut dct_matrix, true);
+    } else {
+        jpeg_steps.de_quantize_function(start_x, q_matrix, &mut dct_matrix, false);
+    };
+
+    (
+        jpeg_steps.inverse_dct_function(&dct_matrix),
+        dct_matrix_zig_zag,
+    )
+}
+
+fn quad_mind_steps_decompress_load(
+    jpeg_steps: &JpegSteps,
+    dct_matrix_zig_zag: &[i32],
+    q_matrix: &[f32],
+    zig_zag_table: &[usize],
+) -> Vec<f32> {
+    let mut dct_matrix =
+        un_zig_zag_function(zig_zag_table, jpeg_steps.block_size, dct_matrix_zig_zag);
+    jpeg_steps.de_quantize_function(0, q_matrix, &mut dct_matrix, false);
+    jpeg_steps.inverse_dct_function(&dct_matrix)
+}
+
+#[derive(Debug)]
+pub enum QuadMindError {
+    Io(io::Error),
+    Signature(&'static str),
+    Version(u16),
+    Checksum(&'static str),
+    Decompression(&'static str),
+    Truncated(&'static str),
+    Corrupt(&'static str),
+    Geometry(&'static str),
+    Limit(&'static str),
+}
+
+impl fmt::Display for QuadMindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuadMindError::Io(error) => write!(f, "File error: {}", error),
+            QuadMindError::Version(version) => write!(f, "Unsupported QMI version {}", version),
+            QuadMindError::Signature(message)
+            | QuadMindError::Checksum(message)
+            | QuadMindError::Decompression(message)
+            | QuadMindError::Truncated(message)
+            | QuadMindError::Corrupt(message)
+            | QuadMindError::Geometry(message)
+            | QuadMindError::Limit(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for QuadMindError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QuadMindError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for QuadMindError {
+    fn from(error: io::Error) -> QuadMindError {
+        QuadMindError::Io(error)
+    }
+}
+
+// Bounds for files from untrusted sources, checked before anything is
+// allocated from the values a file declares
+#[derive(Clone, Copy, Debug)]
+pub struct QuadMindLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_node_count: usize,
+    pub max_decompressed_size: usize,
+}
+
+impl QuadMindLimits {
+    pub fn new() -> QuadMindLimits {
+        QuadMindLimits {
+            max_width: 16384,
+            max_height: 16384,
+            max_node_count: 1 << 22,
+            max_decompressed_size: 1 << 28,
+        }
+    }
+}
+
+impl Default for QuadMindLimits {
+    fn default() -> QuadMindLimits {
+        QuadMindLimits::new()
+    }
+}
+
+#[repr(packed)]
+#[derive(Serialize, Deserialize)]
+pub struct QuadNodeJpeg {
+    x: u32,
+    y: u32,
+    block_size: u8,
+}
+
+impl QuadNodeJpeg {
+    pub fn new(x: u32, y: u32, block_size: u8) -> QuadNodeJpeg {
+        QuadNodeJpeg { x, y, block_size }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QuadMindData {
+    start_signature: String,
+    sha512: Vec<u8>,
+    data: Vec<u8>,
+    end_signature: String,
+}
+
+impl QuadMindData {
+    pub fn new(
+        start_signature: String,
+        sha512: Vec<u8>,
+        data: Vec<u8>,
+        end_signature: String,
+    ) -> QuadMindData {
+        QuadMindData {
+            start_signature,
+            sha512,
+            data,
+            end_signature,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QuadMindFile {
+    signature: String,
+    width: u32,
+    height: u32,
+    quality: f32,
+    use_color_transform: bool,
+    use_threads: bool,
+    use_fast_dct: bool,
+    use_gen_qtable: bool,
+    quad_mind_datas: Vec<QuadMindData>,
+}
+
+impl QuadMindFile {
+    pub fn new(
+        signature: String,
+        width: u32,
+        height: u32,
+        quality: f32,
+        use_color_transform: bool,
+        use_threads: bool,
+        use_fast_dct: bool,
+        use_gen_qtable: bool,
+        quad_mind_datas: Vec<QuadMindData>,
+    ) -> QuadMindFile {
+        QuadMindFile {
+            signature,
+            width,
+            height,
+            quality,
+            use_color_transform,
+            use_threads,
+            use_fast_dct,
+            use_gen_qtable,
+            quad_mind_datas,
+        }
+    }
+}
+
+impl QuadMindFile {
+    fn header(&self) -> QuadMindHeader {
+        QuadMindHeader {
+            width: self.width,
+            height: self.height,
+            quality: self.quality,
+            use_color_transform: self.use_color_transform,
+            use_threads: self.use_threads,
+            use_fast_dct: self.use_fast_dct,
+            use_gen_qtable: self.use_gen_qtable,
+        }
+    }
+}
+
+pub struct QuadMindHeader {
+    pub width: u32,
+    pub height: u32,
+    pub quality: f32,
+    pub use_color_transform: bool,
+    pub use_threads: bool,
+    pub use_fast_dct: bool,
+    pub use_gen_qtable: bool,
+}
+
+impl QuadMindHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let flags = (self.use_color_transform as u8)
+            | (self.use_threads as u8) << 1
+            | (self.use_fast_dct as u8) << 2
+            | (self.use_gen_qtable as u8) << 3;
+
+        let mut bytes = Vec::with_capacity(13);
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.quality.to_le_bytes());
+        bytes.push(flags);
+        bytes
+    }
+    // Fields added later go at the end, older headers are shorter
+    fn from_bytes(bytes: &[u8]) -> Result<QuadMindHeader, QuadMindError> {
+        let mut byte_reader = ByteReader::new(bytes);
+
+        let width = byte_reader.read_u32()?;
+        let height = byte_reader.read_u32()?;
+        let quality = byte_reader.read_f32()?;
+        let flags = byte_reader.read_u8()?;
+
+        Ok(QuadMindHeader {
+            width,
+            height,
+            quality,
+            use_color_transform: flags & 1 != 0,
+            use_threads: flags & 2 != 0,
+            use_fast_dct: flags & 4 != 0,
+            use_gen_qtable: flags & 8 != 0,
+        })
+    }
+}
+
+// Encoder settings that restore the UI. What decoding needs is repeated in
+// the decode settings, which win over the copy here
+pub struct QuadMindSettings {
+    pub subsampling: Subsampling,
+    pub block_size_index: usize,
+    pub quality_start: f32,
+    pub use_compression_rate: bool,
+    pub use_resampled_qtable: bool,
+    pub use_csf_qtable: bool,
+    pub csf: CsfParameters,
+    pub quantizer: QuantizerParameters,
+    pub use_adaptive_quantization: bool,
+    pub adaptive_strength: f32,
+    pub chroma_filters: ChromaFilters,
+    pub color_space: ColorSpace,
+    pub quad_tree: QuadTree,
+}
+
+impl QuadMindSettings {
+    pub fn new(jpeg: &Jpeg, quad_tree: &QuadTree, subsampling: Subsampling) -> QuadMindSettings {
+        QuadMindSettings {
+            subsampling,
+            block_size_index: jpeg.block_size_index,
+            quality_start: jpeg.quality_start,
+            use_compression_rate: jpeg.use_compression_rate,
+            use_resampled_qtable: jpeg.use_resampled_qtable,
+            use_csf_qtable: jpeg.use_csf_qtable,
+            csf: jpeg.csf,
+            quantizer: jpeg.quantizer,
+            use_adaptive_quantization: jpeg.use_adaptive_quantization,
+            adaptive_strength: jpeg.adaptive_strength,
+            chroma_filters: jpeg.chroma_filters,
+            color_space: jpeg.color_space,
+            quad_tree: QuadTree::new(
+                quad_tree.max_depth,
+                quad_tree.min_size,
+                quad_tree.max_size,
+                quad_tree.use_pow_2,
+                quad_tree.use_draw_line,
+                quad_tree.threshold_error,
+            ),
+        }
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        let flags = (self.use_compression_rate as u8)
+            | (self.quad_tree.use_pow_2 as u8) << 1
+            | (self.quad_tree.use_draw_line as u8) << 2
+            | (self.use_resampled_qtable as u8) << 3
+            | (self.use_csf_qtable as u8) << 4
+            | (self.use_adaptive_quantization as u8) << 5;
+
+        // Older readers only know the first six presets
+        let preset_index = self.subsampling.preset_index().unwrap_or(0xFF);
+
+        let mut bytes = Vec::with_capacity(51);
+        bytes.push(preset_index as u8);
+        bytes.push(self.block_size_index as u8);
+        bytes.extend_from_slice(&self.quality_start.to_le_bytes());
+        bytes.push(flags);
+        bytes.extend_from_slice(&self.quad_tree.max_depth.to_le_bytes());
+        bytes.extend_from_slice(&(self.quad_tree.min_size as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.quad_tree.max_size as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.quad_tree.threshold_error.to_le_bytes());
+        bytes.extend_from_slice(&self.csf.viewing_distance.to_le_bytes());
+        bytes.extend_from_slice(&self.csf.display_dpi.to_le_bytes());
+        bytes.extend_from_slice(&self.quantizer.dead_zone.to_le_bytes());
+        bytes.extend_from_slice(&self.quantizer.rounding_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.quantizer.reconstruction_bias.to_le_bytes());
+        bytes.extend_from_slice(&self.adaptive_strength.to_le_bytes());
+        bytes.push(self.chroma_filters.downsample as u8);
+        bytes.push(self.chroma_filters.upsample as u8);
+        bytes.push(self.subsampling.factor_x as u8);
+        bytes.push(self.subsampling.factor_y as u8);
+        bytes.push(self.chroma_filters.siting as u8);
+        bytes.push(self.color_space.matrix as u8);
+        bytes.push(self.color_space.range as u8);
+        bytes.push(self.color_space.transform as u8);
+        bytes
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<QuadMindSettings, QuadMindError> {
+        let mut byte_reader = ByteReader::new(bytes);
+
+        let preset_index = byte_reader.read_u8()? as usize;
+        let block_size_index = byte_reader.read_u8()? as usize;
+        let quality_start = byte_reader.read_f32()?;
+        let flags = byte_reader.read_u8()?;
+        let max_depth = byte_reader.read_u32()?;
+        let min_size = byte_reader.read_u16()? as usize;
+        let max_size = byte_reader.read_u16()? as usize;
+        let threshold_error = byte_reader.read_f32()?;
+
+        // Files written before the perceptual tables end here
+        let csf = if byte_reader.is_empty() {
+            CsfParameters::default()
+        } else {
+            CsfParameters::new(byte_reader.read_f32()?, byte_reader.read_f32()?)
+        };
+
+        // Files written before the quantizer options end here
+        let quantizer = if byte_reader.is_empty() {
+            QuantizerParameters::default()
+        } else {
+            QuantizerParameters::new(
+                byte_reader.read_f32()?,
+                byte_reader.read_f32()?,
+                byte_reader.read_f32()?,
+            )
+        };
+
+        // Files written before adaptive quantization end here
+        let adaptive_strength = if byte_reader.is_empty() {
+            1.0f32
+        } else {
+            byte_reader.read_f32()?
+        };
+
+        // Files written before the chroma filters end here
+        let (downsample, upsample) = if byte_reader.is_empty() {
+            (
+                DownsampleFilter::Box as usize,
+                UpsampleFilter::Fancy as usize,
+            )
+        } else {
+            (
+                byte_reader.read_u8()? as usize,
+                byte_reader.read_u8()? as usize,
+            )
+        };
+
+        // Files written before custom subsampling end here, they only have
+        // the preset
+        let (factors, siting) = if byte_reader.is_empty() {
+            (None, ChromaSiting::Centered as usize)
+        } else {
+            (
+                Some((
+                    byte_reader.read_u8()? as usize,
+                    byte_reader.read_u8()? as usize,
+                )),
+                byte_reader.read_u8()? as usize,
+            )
+        };
+
+        let chroma_filters = chroma_filters_from_indices(downsample, upsample, siting)?;
+
+        let subsampling = match factors {
+            Some((factor_x, factor_y)) => Some(Subsampling::new(factor_x, factor_y))
+                .filter(|subsampling| subsampling.is_valid()),
+            None => Subsampling::PRESETS[..6].get(preset_index).copied(),
+        };
+        let subsampling = match subsampling {
+            Some(subsampling) => subsampling,
+            None => return Err(QuadMindError::Corrupt("Invalid subsampling")),
+        };
+
+        // Files written before the color spaces end here
+        let (matrix, range) = if byte_reader.is_empty() {
+            (YcbcrMatrix::Bt601 as usize, ColorRange::Full as usize)
+        } else {
+            (
+                byte_reader.read_u8()? as usize,
+                byte_reader.read_u8()? as usize,
+            )
+        };
+
+        // Files written before the color transforms end here
+        let transform = if byte_reader.is_empty() {
+            ColorTransform::Ycbcr as usize
+        } else {
+            byte_reader.read_u8()? as usize
+        };
+
+        let color_space = color_space_from_indices(transform, matrix, range)?;
+
+        let is_block_size = |size: usize| size.is_power_of_two() && (2..=512).contains(&size);
+
+        if block_size_index > 8
+            || !(1.0f32..=100.0f32).contains(&quality_start)
+            || !is_block_size(min_size)
+            || !is_block_size(max_size)
+            || !threshold_error.is_finite()
+            || threshold_error < 0.0f32
+            || !csf.viewing_distance.is_finite()
+            || csf.viewing_distance <= 0.0f32
+            || !csf.display_dpi.is_finite()
+            || csf.display_dpi <= 0.0f32
+            || !(0.0f32..=2.0f32).contains(&quantizer.dead_zone)
+            || !(0.0f32..=1.0f32).contains(&quantizer.rounding_offset)
+            || !(0.0f32..=1.0f32).contains(&quantizer.reconstruction_bias)
+            || !(0.0f32..=4.0f32).contains(&adaptive_strength)
+        {
+            return Err(QuadMindError::Corrupt("Invalid encoder settings"));
+        }
+
+        Ok(QuadMindSettings {
+            subsampling,
+            block_size_index,
+            quality_start,
+            use_compression_rate: flags & 1 != 0,
+            use_resampled_qtable: flags & 8 != 0,
+            use_csf_qtable: flags & 16 != 0,
+            csf,
+            quantizer,
+            use_adaptive_quantization: flags & 32 != 0,
+            adaptive_strength,
+            chroma_filters,
+            color_space,
+            quad_tree: QuadTree::new(
+                max_depth,
+                min_size,
+                max_size,
+                flags & 2 != 0,
+                flags & 4 != 0,
+                threshold_error,
+            ),
+        })
+    }
+}
+
+// Everything besides the matrices that changes the decoded image. Files
+// written before it reconstruct at the middle with BT.601 full range
+#[derive(Clone, Copy)]
+pub struct QuadMindDecodeSettings {
+    pub subsampling: Subsampling,
+    pub chroma_filters: ChromaFilters,
+    pub color_space: ColorSpace,
+    pub reconstruction_bias: f32,
+}
+
+impl QuadMindDecodeSettings {
+    pub fn new(jpeg: &Jpeg, subsampling: Subsampling) -> QuadMindDecodeSettings {
+        QuadMindDecodeSettings {
+            subsampling,
+            chroma_filters: jpeg.chroma_filters,
+            color_space: jpeg.color_space,
+            reconstruction_bias: jpeg.quantizer.reconstruction_bias,
+        }
+    }
+    fn from_settings(settings: &QuadMindSettings) -> QuadMindDecodeSettings {
+        QuadMindDecodeSettings {
+            subsampling: settings.subsampling,
+            chroma_filters: settings.chroma_filters,
+            color_space: settings.color_space,
+            reconstruction_bias: settings.quantizer.reconstruction_bias,
+        }
+    }
+    fn apply(self, jpeg: &mut Jpeg) {
+        jpeg.chroma_filters = self.chroma_filters;
+        jpeg.color_space = self.color_space;
+        jpeg.quantizer.reconstruction_bias = self.reconstruction_bias;
+    }
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12);
+        bytes.push(self.subsampling.factor_x as u8);
+        bytes.push(self.subsampling.factor_y as u8);
+        bytes.push(self.chroma_filters.downsample as u8);
+        bytes.push(self.chroma_filters.upsample as u8);
+        bytes.push(self.chroma_filters.siting as u8);
+        bytes.push(self.color_space.transform as u8);
+        bytes.push(self.color_space.matrix as u8);
+        bytes.push(self.color_space.range as u8);
+        bytes.extend_from_slice(&self.reconstruction_bias.to_le_bytes());
+        bytes
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<QuadMindDecodeSettings, QuadMindError> {
+        let mut byte_reader = ByteReader::new(bytes);
+
+        let subsampling = Subsampling::new(
+            byte_reader.read_u8()? as usize,
+            byte_reader.read_u8()? as usize,
+        );
+        if !subsampling.is_valid() {
+            return Err(QuadMindError::Corrupt("Invalid subsampling"));
+        }
+
+        let chroma_filters = chroma_filters_from_indices(
+            byte_reader.read_u8()? as usize,
+            byte_reader.read_u8()? as usize,
+            byte_reader.read_u8()? as usize,
+        )?;
+        let color_space = color_space_from_indices(
+            byte_reader.read_u8()? as usize,
+            byte_reader.read_u8()? as usize,
+            byte_reader.read_u8()? as usize,
+        )?;
+
+        let reconstruction_bias = byte_reader.read_f32()?;
+        if !(0.0f32..=1.0f32).contains(&reconstruction_bias) {
+            return Err(QuadMindError::Corrupt("Invalid reconstruction bias"));
+        }
+
+        Ok(QuadMindDecodeSettings {
+            subsampling,
+            chroma_filters,
+            color_space,
+            reconstruction_bias,
+        })
+    }
+}
+
+impl Default for QuadMindDecodeSettings {
+    fn default() -> QuadMindDecodeSettings {
+        QuadMindDecodeSettings {
+            subsampling: Subsampling::default(),
+            chroma_filters: ChromaFilters::default(),
+            color_space: ColorSpace::default(),
+            reconstruction_bias: QuantizerParameters::default().reconstruction_bias,
+        }
+    }
+}
+
+fn chroma_filters_from_indices(
+    downsample: usize,
+    upsample: usize,
+    siting: usize,
+) -> Result<ChromaFilters, QuadMindError> {
+    match (
+        DownsampleFilter::ALL.get(downsample),
+        UpsampleFilter::ALL.get(upsample),
+        ChromaSiting::ALL.get(siting),
+    ) {
+        (Some(&downsample), Some(&upsample), Some(&siting)) => {
+            Ok(ChromaFilters::new(downsample, upsample, siting))
+        }
+        _ => Err(QuadMindError::Corrupt("Invalid chroma filters")),
+    }
+}
+
+fn color_space_from_indices(
+    transform: usize,
+    matrix: usize,
+    range: usize,
+) -> Result<ColorSpace, QuadMindError> {
+    match (
+        ColorTransform::ALL.get(transform),
+        YcbcrMatrix::ALL.get(matrix),
+        ColorRange::ALL.get(range),
+    ) {
+        (Some(&transform), Some(&matrix), Some(&range)) => {
+            Ok(ColorSpace::new(transform, matrix, range))
+        }
+        _ => Err(QuadMindError::Corrupt("Invalid color space")),
+    }
+}
+
+// The matrices used to quantize, blocks index them with their own size as
+// stride so they are stored as written instead of being regenerated
+pub struct QuadMindQMatrices {
+    pub size: usize,
+    pub luma: Vec<f32>,
+    pub chroma: Vec<f32>,
+}
+
+impl QuadMindQMatrices {
+    pub fn new(jpeg: &Jpeg, size: usize) -> QuadMindQMatrices {
+        // With compression rate the coefficients are stored against the base matrices
+        let (luma, chroma) = jpeg.q_matrices(size);
+
+        QuadMindQMatrices { size, luma, chroma }
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut values = Vec::with_capacity((self.luma.len() + self.chroma.len()) * 4);
+        for value in self.luma.iter().chain(&self.chroma) {
+            values.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut bytes = (self.size as u16).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(&values, 10));
+        bytes
+    }
+    fn from_bytes(
+        bytes: &[u8],
+        limits: &QuadMindLimits,
+    ) -> Result<QuadMindQMatrices, QuadMindError> {
+        let mut byte_reader = ByteReader::new(bytes);
+
+        let size = byte_reader.read_u16()? as usize;
+        if !size.is_power_of_two() || !(2..=512).contains(&size) {
+            return Err(QuadMindError::Corrupt("Invalid quantization matrix size"));
+        }
+
+        let values = inflate(
+            &bytes[2..],
+            limits,
+            "Could not decompress quantization matrices",
+        )?;
+        if values.len() != size * size * 8 {
+            return Err(QuadMindError::Corrupt(
+                "Quantization matrix length does not match its size",
+            ));
+        }
+
+        let mut value_reader = ByteReader::new(&values);
+        let mut q_matrices = Vec::with_capacity(size * size * 2);
+        for _ in 0..size * size * 2 {
+            let value = value_reader.read_f32()?;
+            if !value.is_finite() || value <= 0.0f32 {
+                return Err(QuadMindError::Corrupt("Invalid quantization matrix value"));
+            }
+            q_matrices.push(value);
+        }
+
+        let chroma = q_matrices.split_off(size * size);
+
+        Ok(QuadMindQMatrices {
+            size,
+            luma: q_matrices,
+            chroma,
+        })
+    }
+}
+
+// Coded alpha of images that have one, readers that skip its chunk show
+// the image opaque
+pub struct QuadMindAlpha {
+    pub alpha: AlphaParameters,
+    pub data: Vec<u8>,
+}
+
+impl QuadMindAlpha {
+    pub fn new(my_image: &MyImage, alpha: AlphaParameters) -> Option<QuadMindAlpha> {
+        my_image.original_alpha.as_ref().map(|plane| QuadMindAlpha {
+            alpha,
+            data: alpha::encode_alpha(plane, my_image.width, my_image.height, alpha),
+        })
+    }
+    pub fn plane(&self, width: usize, height: usize) -> Result<Vec<u8>, QuadMindError> {
+        alpha::decode_alpha(&self.data, width, height, self.alpha).map_err(QuadMindError::Corrupt)
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(5 + self.data.len());
+        bytes.push(self.alpha.use_lossless as u8);
+        bytes.extend_from_slice(&self.alpha.quality.to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<QuadMindAlpha, QuadMindError> {
+        let mut byte_reader = ByteReader::new(bytes);
+
+        let use_lossless = match byte_reader.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(QuadMindError::Corrupt("Invalid alpha mode")),
+        };
+        let quality = byte_reader.read_f32()?;
+        if !(1.0f32..=100.0f32).contains(&quality) {
+            return Err(QuadMindError::Corrupt("Invalid alpha quality"));
+        }
+
+        Ok(QuadMindAlpha {
+            alpha: AlphaParameters::new(use_lossless, quality),
+            data: bytes[5..].to_vec(),
+        })
+    }
+}
+
+// Everything read from a file, settings and matrices are missing in older
+// files, scale codes in files without adaptive quantization or a mask and
+// alpha in files of opaque images
+pub struct QuadMindContents {
+    pub header: QuadMindHeader,
+    pub decode_settings: QuadMindDecodeSettings,
+    pub settings: Option<QuadMindSettings>,
+    pub q_matrices: Option<QuadMindQMatrices>,
+    pub adaptive_scale_codes: Option<Vec<u8>>,
+    pub alpha: Option<QuadMindAlpha>,
+    pub alpha_plane: Option<Vec<u8>>,
+    pub quad_node_jpeg: Vec<QuadNodeJpeg>,
+    pub dct_zig_zag: Vec3d<i32>,
+}
+
+// What a decoded file restores in the viewer
+pub struct QuadMindConfig {
+    pub jpeg: Jpeg,
+    pub use_color_transform: bool,
+    pub settings: Option<QuadMindSettings>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn save_quad_mind(
+    path: &Path,
+    quad_node_list: &[QuadNodeRef],
+    quad_dct_zig_zag: &Vec3d<i32>,
+    my_image: &MyImage,
+    jpeg: &Jpeg,
+    quad_tree: &QuadTree,
+    use_color_transform: bool,
+    use_threads: bool,
+    subsampling: Subsampling,
+) -> Result<(), QuadMindError> {
+    let serialized_quad_mind_file = encode_quad_mind(
+        quad_node_list,
+        quad_dct_zig_zag,
+        my_image,
+        jpeg,
+        quad_tree,
+        use_color_transform,
+        use_threads,
+        subsampling,
+    );
+
+    let mut file = File::create(path)?;
+    file.write_all(&serialized_quad_mind_file)?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn encode_quad_mind(
+    quad_node_list: &[QuadNodeRef],
+    quad_dct_zig_zag: &Vec3d<i32>,
+    my_image: &MyImage,
+    jpeg: &Jpeg,
+    quad_tree: &QuadTree,
+    use_color_transform: bool,
+    use_threads: bool,
+    subsampling: Subsampling,
+) -> Vec<u8> {
+    let quad_mind_header = QuadMindHeader {
+        width: my_image.width as u32,
+        height: my_image.height as u32,
+        quality: jpeg.quality,
+        use_color_transform,
+        use_threads,
+        use_fast_dct: jpeg.use_fast_dct,
+        use_gen_qtable: jpeg.use_gen_qtable,
+    };
+
+    let quad_node_jpeg: Vec<QuadNodeJpeg> = quad_node_list
+        .iter()
+        .map(|quad| {
+            let quad = quad.borrow();
+            QuadNodeJpeg::new(
+                quad.box_left as u32,
+                quad.box_top as u32,
+                (quad.width_block_size as f32).log2().ceil() as u8,
+            )
+        })
+        .collect();
+
+    let adaptive_scale_codes: Option<Vec<u8>> = if jpeg.use_block_scales() {
+        Some(
+            quad_node_list
+                .iter()
+                .map(|quad| quad.borrow().adaptive_scale_code)
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    write_quad_mind(
+        &quad_mind_header,
+        &QuadMindDecodeSettings::new(jpeg, subsampling),
+        Some(&QuadMindSettings::new(jpeg, quad_tree, subsampling)),
+        Some(&QuadMindQMatrices::new(jpeg, quad_tree.max_size)),
+        adaptive_scale_codes.as_deref(),
+        QuadMindAlpha::new(my_image, jpeg.alpha).as_ref(),
+        &quad_node_jpeg,
+        quad_dct_zig_zag,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_quad_mind(
+    quad_mind_header: &QuadMindHeader,
+    decode_settings: &QuadMindDecodeSettings,
+    settings: Option<&QuadMindSettings>,
+    q_matrices: Option<&QuadMindQMatrices>,
+    adaptive_scale_codes: Option<&[u8]>,
+    alpha: Option<&QuadMindAlpha>,
+    quad_node_jpeg: &[QuadNodeJpeg],
+    dct_zig_zag: &Vec3d<i32>,
+) -> Vec<u8> {
+    let serialized_quad_node_jpeg =
+        bincode::serialize(quad_node_jpeg).expect("Could not serialize quad node jpeg");
+
+    let compressed_quad_node_jpeg =
+        miniz_oxide::deflate::compress_to_vec(&serialized_quad_node_jpeg, 10);
+
+    let mut coefficient_encoder = CoefficientEncoder::new();
+
+    for (quad, quad_dct_zig_zag) in quad_node_jpeg.iter().zip(dct_zig_zag) {
+        for (j, block) in quad_dct_zig_zag.iter().enumerate() {
+            coefficient_encoder.add_block(j, 1 << quad.block_size, block);
+        }
+    }
+
+    let mut chunks = vec![
+        QmiChunk::new(qmi::CHUNK_HEADER, quad_mind_header.to_bytes()),
+        QmiChunk::new(qmi::CHUNK_DECODE_SETTINGS, decode_settings.to_bytes()),
+    ];
+    if let Some(settings) = settings {
+        chunks.push(QmiChunk::new(
+            qmi::CHUNK_ENCODER_SETTINGS,
+            settings.to_bytes(),
+        ));
+    }
+    if let Some(q_matrices) = q_matrices {
+        chunks.push(QmiChunk::new(qmi::CHUNK_Q_MATRICES, q_matrices.to_bytes()));
+    }
+    if let Some(adaptive_scale_codes) = adaptive_scale_codes {
+        chunks.push(QmiChunk::new(
+            qmi::CHUNK_ADAPTIVE_SCALES,
+            miniz_oxide::deflate::compress_to_vec(adaptive_scale_codes, 10),
+        ));
+    }
+    chunks.push(QmiChunk::new(
+        qmi::CHUNK_QUAD_NODES,
+        compressed_quad_node_jpeg,
+    ));
+    chunks.push(QmiChunk::new(
+        qmi::CHUNK_COEFFICIENTS,
+        coefficient_encoder.finish(),
+    ));
+    if let Some(alpha) = alpha {
+        chunks.push(QmiChunk::new(qmi::CHUNK_ALPHA, alpha.to_bytes()));
+    }
+
+    qmi::write_qmi(&chunks)
+}
+
+pub fn load_quad_mind(path: &Path) -> Result<(MyImage, QuadMindConfig), QuadMindError> {
+    let mut contents: Vec<u8> = Vec::new();
+    let mut file = File::open(path)?;
+    file.read_to_end(&mut contents)?;
+
+    load_quad_mind_from_memory(
+        &contents,
+        path.to_string_lossy().to_string(),
+        &QuadMindLimits::new(),
+    )
+}
+
+pub fn load_quad_mind_from_memory(
+    contents: &[u8],
+    file_path: String,
+    limits: &QuadMindLimits,
+) -> Result<(MyImage, QuadMindConfig), QuadMindError> {
+    let quad_mind_contents = read_quad_mind_with_limits(contents, limits)?;
+
+    Ok(decode_quad_mind(quad_mind_contents, file_path))
+}
+
+pub fn read_quad_mind(contents: &[u8]) -> Result<QuadMindContents, QuadMindError> {
+    read_quad_mind_with_limits(contents, &QuadMindLimits::new())
+}
+
+pub fn read_quad_mind_with_limits(
+    contents: &[u8],
+    limits: &QuadMindLimits,
+) -> Result<QuadMindContents, QuadMindError> {
+    if !qmi::is_qmi_container(contents) {
+        return read_quad_mind_legacy(contents, limits);
+    }
+
+    let mut quad_mind_header = None;
+    let mut decode_settings = None;
+    let mut settings = None;
+    let mut q_matrices = None;
+    let mut adaptive_scale_codes = None;
+    let mut alpha = None;
+    let mut quad_node_jpeg = None;
+    let mut coefficients = None;
+
+    let (version, chunks) = qmi::read_qmi(contents)?;
+    for chunk in chunks {
+        match chunk.id {
+            qmi::CHUNK_HEADER => quad_mind_header = Some(QuadMindHeader::from_bytes(&chunk.data)?),
+            qmi::CHUNK_DECODE_SETTINGS => {
+                decode_settings = Some(QuadMindDecodeSettings::from_bytes(&chunk.data)?);
+            }
+            qmi::CHUNK_ENCODER_SETTINGS => {
+                settings = Some(QuadMindSettings::from_bytes(&chunk.data)?);
+            }
+            qmi::CHUNK_Q_MATRICES => {
+                q_matrices = Some(QuadMindQMatrices::from_bytes(&chunk.data, limits)?);
+            }
+            qmi::CHUNK_ADAPTIVE_SCALES => {
+                adaptive_scale_codes = Some(inflate(
+                    &chunk.data,
+                    limits,
+                    "Could not decompress adaptive scales",
+                )?);
+            }
+            qmi::CHUNK_QUAD_NODES => {
+                quad_node_jpeg = Some(read_quad_node_jpeg(&chunk.data, limits)?);
+            }
+            qmi::CHUNK_COEFFICIENTS => coefficients = Some(chunk.data),
+            qmi::CHUNK_ALPHA => alpha = Some(QuadMindAlpha::from_bytes(&chunk.data)?),
+            _ if chunk.is_critical() => {
+                return Err(QuadMindError::Corrupt("Unknown critical QMI chunk"))
+            }
+            _ => {}
+        }
+    }
+
+    let quad_mind_header =
+        quad_mind_header.ok_or(QuadMindError::Corrupt("Missing QMI header chunk"))?;
+    let quad_node_jpeg =
+        quad_node_jpeg.ok_or(QuadMindError::Corrupt("Missing QMI quad nodes chunk"))?;
+    let coefficients =
+        coefficients.ok_or(QuadMindError::Corrupt("Missing QMI coefficients chunk"))?;
+
+    let decode_settings = match (decode_settings, &settings) {
+        (Some(decode_settings), _) => decode_settings,
+        (None, _) if version >= 3 => {
+            return Err(QuadMindError::Corrupt("Missing QMI decode settings chunk"))
+        }
+        (None, Some(settings)) => QuadMindDecodeSettings::from_settings(settings),
+        (None, None) => QuadMindDecodeSettings::default(),
+    };
+    if let Some(settings) = &mut settings {
+        settings.subsampling = decode_settings.subsampling;
+        settings.chroma_filters = decode_settings.chroma_filters;
+        settings.color_space = decode_settings.color_space;
+        settings.quantizer.reconstruction_bias = decode_settings.reconstruction_bias;
+    }
+
+    validate_quad_mind(&quad_mind_header, &quad_node_jpeg, limits)?;
+
+    if let Some(q_matrices) = &q_matrices {
+        if quad_node_jpeg
+            .iter()
+            .any(|quad| 1 << quad.block_size > q_matrices.size)
+        {
+            return Err(QuadMindError::Geometry(
+                "Quad node larger than the quantization matrices",
+            ));
+        }
+    }
+
+    if let Some(adaptive_scale_codes) = &adaptive_scale_codes {
+        if adaptive_scale_codes.len() != quad_node_jpeg.len() {
+            return Err(QuadMindError::Geometry(
+                "Adaptive scale count does not match the quad nodes",
+            ));
+        }
+        if !adaptive_scale_codes
+            .iter()
+            .all(|&code| adaptive_quant::is_valid_code(code))
+        {
+            return Err(QuadMindError::Corrupt("Invalid adaptive scale"));
+        }
+    }
+
+    let dct_zig_zag = read_dct_zig_zag_coded(&coefficients, &quad_node_jpeg, limits)?;
+
+    let alpha_plane = match &alpha {
+        Some(alpha) => Some(alpha.plane(
+            quad_mind_header.width as usize,
+            quad_mind_header.height as usize,
+        )?),
+        None => None,
+    };
+
+    Ok(QuadMindContents {
+        header: quad_mind_header,
+        decode_settings,
+        settings,
+        q_matrices,
+        adaptive_scale_codes,
+        alpha,
+        alpha_plane,
+        quad_node_jpeg,
+        dct_zig_zag,
+    })
+}
+
+// Rewrites a file into the current chunk container without re-encoding, the
+// matrices older files were decoded with are stored with it
+pub fn migrate_quad_mind(contents: &[u8]) -> Result<Vec<u8>, QuadMindError> {
+    let quad_mind_contents = read_quad_mind(contents)?;
+
+    let q_matrices = match quad_mind_contents.q_matrices {
+        Some(q_matrices) => q_matrices,
+        None => legacy_q_matrices(
+            &quad_mind_contents.header,
+            &quad_mind_contents.quad_node_jpeg,
+        ),
+    };
+
+    Ok(write_quad_mind(
+        &quad_mind_contents.header,
+        &quad_mind_contents.decode_settings,
+        quad_mind_contents.settings.as_ref(),
+        Some(&q_matrices),
+        quad_mind_contents.adaptive_scale_codes.as_deref(),
+        quad_mind_contents.alpha.as_ref(),
+        &quad_mind_contents.quad_node_jpeg,
+        &quad_mind_contents.dct_zig_zag,
+    ))
+}
+
+// Files without matrices were decoded with matrices sized to the largest node
+fn legacy_q_matrices(
+    quad_mind_header: &QuadMindHeader,
+    quad_node_jpeg: &[QuadNodeJpeg],
+) -> QuadMindQMatrices {
+    let max_size = quad_node_jpeg
+        .iter()
+        .map(|quad| 1 << quad.block_size)
+        .max()
+        .unwrap_or(2);
+
+    let jpeg = Jpeg::new(
+        8,
+        quad_mind_header.quality,
+        1.0f32,
+        2,
+        quad_mind_header.use_gen_qtable,
+        quad_mind_header.use_threads,
+        quad_mind_header.use_fast_dct,
+        false,
+    );
+
+    QuadMindQMatrices::new(&jpeg, max_size)
+}
+
+fn read_quad_mind_legacy(
+    contents: &[u8],
+    limits: &QuadMindLimits,
+) -> Result<QuadMindContents, QuadMindError> {
+    let quad_mind_file: QuadMindFile = bincode::deserialize(contents)
+        .map_err(|_| QuadMindError::Signature("Not a quad mind file"))?;
+
+    if quad_mind_file.signature != "QUADMIND" {
+        return Err(QuadMindError::Signature("Wrong QUADMIND signature"));
+    }
+    if quad_mind_file.quad_mind_datas.len() < 2 {
+        return Err(QuadMindError::Corrupt("Missing quad mind data"));
+    }
+
+    let quad_node_jpeg_data = &quad_mind_file.quad_mind_datas[0];
+
+    if quad_node_jpeg_data.start_signature != "SQNJ" {
+        return Err(QuadMindError::Signature("Wrong QNJ start signature"));
+    }
+    if quad_node_jpeg_data.end_signature != "EQNJ" {
+        return Err(QuadMindError::Signature("Wrong QNJ end signature"));
+    }
+
+    let serialized_quad_node_jpeg = inflate(
+        &quad_node_jpeg_data.data,
+        limits,
+        "Could not decompress quad nodes",
+    )?;
+
+    let sha512_quad_node_jpeg_test = sha2::Sha512::digest(&serialized_quad_node_jpeg);
+
+    if quad_node_jpeg_data.sha512[..] != sha512_quad_node_jpeg_test[..] {
+        return Err(QuadMindError::Checksum("Wrong QNJ sha512 signature"));
+    }
+
+    let quad_node_jpeg = deserialize_quad_node_jpeg(&serialized_quad_node_jpeg)?;
+
+    let quad_mind_header = quad_mind_file.header();
+    validate_quad_mind(&quad_mind_header, &quad_node_jpeg, limits)?;
+
+    let dct_zig_zag_data = &quad_mind_file.quad_mind_datas[1];
+
+    // Files written before the coefficient coder store deflated i32s
+    let dct_zig_zag = match dct_zig_zag_data.start_signature.as_str() {
+        "SDCT" => load_dct_zig_zag_deflate(dct_zig_zag_data, &quad_node_jpeg, limits)?,
+        "SACC" => load_dct_zig_zag_coded(dct_zig_zag_data, &quad_node_jpeg, limits)?,
+        _ => return Err(QuadMindError::Signature("Wrong DCT start signature")),
+    };
+
+    Ok(QuadMindContents {
+        header: quad_mind_header,
+        decode_settings: QuadMindDecodeSettings::default(),
+        settings: None,
+        q_matrices: None,
+        adaptive_scale_codes: None,
+        alpha: None,
+        alpha_plane: None,
+        quad_node_jpeg,
+        dct_zig_zag,
+    })
+}
+
+fn load_dct_zig_zag_deflate(
+    dct_zig_zag_data: &QuadMindData,
+    quad_node_jpeg: &[QuadNodeJpeg],
+    limits: &QuadMindLimits,
+) -> Result<Vec3d<i32>, QuadMindError> {
+    if dct_zig_zag_data.end_signature != "EDCT" {
+        return Err(QuadMindError::Signature("Wrong DCT end signature"));
+    }
+
+    let serialized_dct_zig_zag = inflate(
+        &dct_zig_zag_data.data,
+        limits,
+        "Could not decompress dct zig zag",
+    )?;
+
+    let sha512_dct_zig_zag_test = sha2::Sha512::digest(&serialized_dct_zig_zag);
+
+    if dct_zig_zag_data.sha512[..] != sha512_dct_zig_zag_test[..] {
+        return Err(QuadMindError::Checksum("Wrong DCT sha512 signature"));
+    }
+
+    let quad_root_dct_zig_zag: Vec<i32> = bincode::deserialize(&serialized_dct_zig_zag)
+        .map_err(|_| QuadMindError::Corrupt("Could not deserialize dct zig zag"))?;
+
+    let dct_zig_zag_count: usize = quad_node_jpeg
+        .iter()
+        .map(|quad| (1usize << (quad.block_size * 2)) * 3)
+        .sum();
+
+    if quad_root_dct_zig_zag.len() != dct_zig_zag_count {
+        return Err(QuadMindError::Geometry(
+            "DCT coefficient count does not match the quad nodes",
+        ));
+    }
+
+    let mut dct_zig_zag: Vec3d<i32> = vec![vec![Vec::new(); 3]; quad_node_jpeg.len()];
+
+    let mut dzz_index = 0;
+    for i in 0..quad_node_jpeg.len() {
+        let quad = &quad_node_jpeg[i];
+        let quad_block_size = 1 << quad.block_size;
+        let quad_block_size_max = (quad_block_size * quad_block_size) as usize;
+
+        for j in 0..3 {
+            dct_zig_zag[i][j].resize(quad_block_size_max, 0);
+
+            for k in 0..quad_block_size_max {
+                dct_zig_zag[i][j][k] = quad_root_dct_zig_zag[dzz_index];
+                dzz_index += 1;
+            }
+        }
+    }
+
+    Ok(dct_zig_zag)
+}
+
+fn load_dct_zig_zag_coded(
+    dct_zig_zag_data: &QuadMindData,
+    quad_node_jpeg: &[QuadNodeJpeg],
+    limits: &QuadMindLimits,
+) -> Result<Vec3d<i32>, QuadMindError> {
+    if dct_zig_zag_data.end_signature != "EACC" {
+        return Err(QuadMindError::Signature("Wrong DCT end signature"));
+    }
+
+    let dct_zig_zag = read_dct_zig_zag_coded(&dct_zig_zag_data.data, quad_node_jpeg, limits)?;
+
+    let quad_root_dct_zig_zag: Vec<i32> = dct_zig_zag.iter().flatten().flatten().copied().collect();
+
+    let serialized_dct_zig_zag =
+        bincode::serialize(&quad_root_dct_zig_zag).expect("Could not serialize dct zig zag");
+
+    let sha512_dct_zig_zag_test = sha2::Sha512::digest(&serialized_dct_zig_zag);
+
+    if dct_zig_zag_data.sha512[..] != sha512_dct_zig_zag_test[..] {
+        return Err(QuadMindError::Checksum("Wrong DCT sha512 signature"));
+    }
+
+    Ok(dct_zig_zag)
+}
+
+fn read_quad_node_jpeg(
+    data: &[u8],
+    limits: &QuadMindLimits,
+) -> Result<Vec<QuadNodeJpeg>, QuadMindError> {
+    let serialized_quad_node_jpeg = inflate(data, limits, "Could not decompress quad nodes")?;
+
+    deserialize_quad_node_jpeg(&serialized_quad_node_jpeg)
+}
+
+fn inflate(
+    data: &[u8],
+    limits: &QuadMindLimits,
+    message: &'static str,
+) -> Result<Vec<u8>, QuadMindError> {
+    miniz_oxide::inflate::decompress_to_vec_with_limit(data, limits.max_decompressed_size).map_err(
+        |error| match error.status {
+            TINFLStatus::HasMoreOutput => {
+                QuadMindError::Limit("Decompressed data exceeds the size limit")
+            }
+            _ => QuadMindError::Decompression(message),
+        },
+    )
+}
+
+// Nodes written by the encoder start inside the image and do not overlap,
+// so they can not cover more than the padded image
+fn validate_quad_mind(
+    quad_mind_header: &QuadMindHeader,
+    quad_node_jpeg: &[QuadNodeJpeg],
+    limits: &QuadMindLimits,
+) -> Result<(), QuadMindError> {
+    let width = quad_mind_header.width;
+    let height = quad_mind_header.height;
+
+    if width == 0 || height == 0 {
+        return Err(QuadMindError::Geometry("Image has no pixels"));
+    }
+    if width > limits.max_width || height > limits.max_height {
+        return Err(QuadMindError::Limit("Image dimensions exceed the limit"));
+    }
+    if !(1.0f32..=100.0f32).contains(&quad_mind_header.quality) {
+        return Err(QuadMindError::Corrupt("Invalid quality"));
+    }
+
+    if quad_node_jpeg.is_empty() {
+        return Err(QuadMindError::Geometry("Image has no quad nodes"));
+    }
+    if quad_node_jpeg.len() > limits.max_node_count {
+        return Err(QuadMindError::Limit("Quad node count exceeds the limit"));
+    }
+
+    let mut area = 0u64;
+    let mut max_size = 0u64;
+    for quad in quad_node_jpeg {
+        if quad.x >= width || quad.y >= height {
+            return Err(QuadMindError::Geometry("Quad node outside the image"));
+        }
+
+        let block_size = 1u64 << quad.block_size;
+        area += block_size * block_size;
+        max_size = max_size.max(block_size);
+    }
+
+    let padded_area = (width as u64 + max_size * 2) * (height as u64 + max_size * 2);
+    if area > padded_area {
+        return Err(QuadMindError::Geometry(
+            "Quad nodes cover more than the image",
+        ));
+    }
+
+    Ok(())
+}
+
+fn deserialize_quad_node_jpeg(
+    serialized_quad_node_jpeg: &[u8],
+) -> Result<Vec<QuadNodeJpeg>, QuadMindError> {
+    let quad_node_jpeg: Vec<QuadNodeJpeg> = bincode::deserialize(serialized_quad_node_jpeg)
+        .map_err(|_| QuadMindError::Corrupt("Could not deserialize quad nodes"))?;
+
+    // Block sizes go from 2 to 512 like the jpeg block sizes
+    if quad_node_jpeg
+        .iter()
+        .any(|quad| quad.block_size < 1 || quad.block_size > 9)
+    {
+        return Err(QuadMindError::Geometry("Invalid quad block size"));
+    }
+
+    Ok(quad_node_jpeg)
+}
+
+fn read_dct_zig_zag_coded(
+    data: &[u8],
+    quad_node_jpeg: &[QuadNodeJpeg],
+    limits: &QuadMindLimits,
+) -> Result<Vec3d<i32>, QuadMindError> {
+    // Every coefficient of every node is allocated, whatever the data holds
+    let coefficient_count: u64 = quad_node_jpeg
+        .iter()
+        .map(|quad| (1u64 << (quad.block_size * 2)) * 3)
+        .sum();
+    if coefficient_count > limits.max_decompressed_size as u64 {
+        return Err(QuadMindError::Limit(
+            "Coefficient count exceeds the size limit",
+        ));
+    }
+
+    let mut coefficient_decoder = CoefficientDecoder::new(data);
+
+    let mut dct_zig_zag: Vec3d<i32> = Vec::with_capacity(quad_node_jpeg.len());
+
+    for quad in quad_node_jpeg {
+        let quad_block_size = 1 << quad.block_size;
+
+        let mut quad_dct_zig_zag = Vec::with_capacity(3);
+        for j in 0..3 {
+            let block = coefficient_decoder
+                .read_block(j, quad_block_size)
+                .map_err(|message| {
+                    if coefficient_decoder.is_overrun() {
+                        QuadMindError::Truncated(message)
+                    } else {
+                        QuadMindError::Corrupt(message)
+                    }
+                })?;
+            quad_dct_zig_zag.push(block);
+        }
+        dct_zig_zag.push(quad_dct_zig_zag);
+    }
+
+    Ok(dct_zig_zag)
+}
+
+// Expects input from read_quad_mind, which validates the geometry
+pub fn decode_quad_mind(
+    quad_mind_contents: QuadMindContents,
+    file_path: String,
+) -> (MyImage, QuadMindConfig) {
+    let QuadMindContents {
+        header: quad_mind_header,
+        decode_settings,
+        settings,
+        q_matrices,
+        adaptive_scale_codes,
+        alpha,
+        alpha_plane,
+        quad_node_jpeg,
+        dct_zig_zag,
+    } = quad_mind_contents;
+
+    let mut my_image = MyImage::new(
+        Vec::new(),
+        quad_mind_header.width as usize,
+        quad_mind_header.height as usize,
+        file_path,
+    );
+
+    let mut max_size = 0;
+
+    for quad in &quad_node_jpeg {
+        let block_size = (1 << quad.block_size) as usize;
+
+        let quad_box_right = quad.x as usize + block_size;
+        let quad_box_bottom = quad.y as usize + block_size;
+
+        if quad_box_right > my_image.mwidth {
+            my_image.mwidth = quad_box_right;
+        }
+        if quad_box_bottom > my_image.mheight {
+            my_image.mheight = quad_box_bottom;
+        }
+
+        if block_size > max_size {
+            max_size = block_size;
+        }
+    }
+
+    my_image.round_up_size(max_size);
+
+    let table_size = (max_size as f32).log2().ceil() as usize;
+
+    let (dct_table, alpha_table) = if !quad_mind_header.use_fast_dct {
+        let mut dct_table = Vec::with_capacity(table_size);
+        let mut alpha_table = Vec::with_capacity(table_size);
+
+        for i in 0..table_size {
+            let block_size = 1 << (i + 1);
+
+            dct_table.push(Arc::new(jpeg::generate_dct_table(block_size)));
+            alpha_table.push(Arc::new(jpeg::generate_alpha_table(block_size)));
+        }
+
+        (dct_table, alpha_table)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    let mut zig_zag_table = Vec::with_capacity(table_size);
+
+    for i in 0..table_size {
+        let block_size = 1 << (i + 1);
+
+        zig_zag_table.push(Arc::new(generate_zig_zag_table(block_size)));
+    }
+
+    let QuadMindQMatrices {
+        luma: q_matrix_luma,
+        chroma: q_matrix_chroma,
+        ..
+    } = match q_matrices {
+        Some(q_matrices) => q_matrices,
+        None => legacy_q_matrices(&quad_mind_header, &quad_node_jpeg),
+    };
+
+    let q_matrix_luma = Arc::new(q_matrix_luma);
+    let q_matrix_chroma = Arc::new(q_matrix_chroma);
+
+    let node_q_matrices = |i: usize| {
+        adaptive_quant::block_q_matrices(
+            &q_matrix_luma,
+            &q_matrix_chroma,
+            1 << (quad_node_jpeg[i].block_size * 2),
+            adaptive_scale_codes.as_ref().map(|codes| codes[i]),
+        )
+    };
+
+    let mut jpeg = match &settings {
+        Some(settings) => Jpeg::new(
+            1 << (settings.block_size_index + 1),
+            quad_mind_header.quality,
+            settings.quality_start,
+            settings.block_size_index,
+            quad_mind_header.use_gen_qtable,
+            quad_mind_header.use_threads,
+            quad_mind_header.use_fast_dct,
+            settings.use_compression_rate,
+        ),
+        None => Jpeg::new(
+            8,
+            quad_mind_header.quality,
+            1.0f32,
+            2,
+            quad_mind_header.use_gen_qtable,
+            quad_mind_header.use_threads,
+            quad_mind_header.use_fast_dct,
+            false,
+        ),
+    };
+
+    if let Some(settings) = &settings {
+        jpeg.use_resampled_qtable = settings.use_resampled_qtable;
+        jpeg.use_csf_qtable = settings.use_csf_qtable;
+        jpeg.csf = settings.csf;
+        jpeg.quantizer = settings.quantizer;
+        jpeg.adaptive_strength = settings.adaptive_strength;
+    }
+    decode_settings.apply(&mut jpeg);
+    if let Some(alpha) = &alpha {
+        jpeg.alpha = alpha.alpha;
+    }
+    if quad_mind_header.use_color_transform {
+        my_image.component_ranges = jpeg.color_space.component_ranges();
+    }
+    // Scales can also come from a mask, which is not stored
+    jpeg.use_adaptive_quantization = adaptive_scale_codes.is_some()
+        && settings
+            .as_ref()
+            .is_none_or(|settings| settings.use_adaptive_quantization);
+
+    let mut jpeg_steps = JpegSteps::new(&jpeg, my_image.mwidth);
+
+    let final_result_block = if jpeg.use_threads {
+        let mut zig_zag_table = Vec::with_capacity(table_size);
+
+        for i in 0..table_size {
+            let block_size = 1 << (i + 1);
+
+            zig_zag_table.push(Arc::new(generate_zig_zag_table(block_size)));
+        }
+
+        let mut result_block = Vec::with_capacity(quad_node_jpeg.len());
+        let mut jpeg_steps_list = Vec::with_capacity(quad_node_jpeg.len());
+
+        for quad in &quad_node_jpeg {
+            let block_size = (1 << quad.block_size) as usize;
+
+            jpeg_steps_list.push(Arc::new({
+                let mut jpeg_steps = jpeg_steps.clone();
+
+                let table_index = quad.block_size as usize - 1;
+
+                if !jpeg.use_fast_dct {
+                    jpeg_steps.dct_table = Some(Arc::clone(&dct_table[table_index]));
+                    jpeg_steps.alpha_table = Some(Arc::clone(&alpha_table[table_index]));
+                    jpeg_steps.two_block_size = 2.0f32 / block_size as f32;
+                }
+
+                jpeg_steps.block_size = block_size;
+                jpeg_steps.block_size_index = table_index;
+
+                jpeg_steps
+            }));
+
+            result_block.push(Arc::new(Mutex::new(vec![
+                vec![
+                    0.0f32;
+                    block_size * block_size
+                ];
+                3
+            ])));
+        }
+
+        let dct_zig_zag = Arc::new(dct_zig_zag);
+
+        let cpu_threads = thread::available_parallelism().unwrap().get();
+        let pool = threadpool::ThreadPool::with_name(String::from("jpegview-worker"), cpu_threads);
+
+        for i in 0..quad_node_jpeg.len() {
+            let table_index = quad_node_jpeg[i].block_size as usize - 1;
+
+            let arc_jpeg_steps = Arc::clone(&jpeg_steps_list[i]);
+            let arc_dct_zig_zag = Arc::clone(&dct_zig_zag);
+            let arc_result_block = Arc::clone(&result_block[i]);
+            let arc_zig_zag_table = Arc::clone(&zig_zag_table[table_index]);
+            let (arc_q_matrix_luma, arc_q_matrix_chroma) = node_q_matrices(i);
+
+            pool.execute(move || {
+                let arc_locked_result_block = &mut arc_result_block.lock().unwrap();
+                arc_locked_result_block[0] = quad_mind_steps_decompress_load(
+                    &arc_jpeg_steps,
+                    &arc_dct_zig_zag[i][0],
+                    &arc_q_matrix_luma,
+                    &arc_zig_zag_table,
+                );
+                arc_locked_result_block[1] = quad_mind_steps_decompress_load(
+                    &arc_jpeg_steps,
+                    &arc_dct_zig_zag[i][1],
+                    &arc_q_matrix_chroma,
+                    &arc_zig_zag_table,
+                );
+                arc_locked_result_block[2] = quad_mind_steps_decompress_load(
+                    &arc_jpeg_steps,
+                    &arc_dct_zig_zag[i][2],
+                    &arc_q_matrix_chroma,
+                    &arc_zig_zag_table,
+                );
+            });
+        }
+        pool.join();
+
+        result_block.into_iter().map(unwrap_arc_mutex).collect()
+    } else {
+        let mut zig_zag_table = Vec::with_capacity(table_size);
+
+        for i in 0..table_size {
+            let block_size = 1 << (i + 1);
+
+            zig_zag_table.push(generate_zig_zag_table(block_size));
+        }
+
+        let mut result_block = Vec::with_capacity(quad_node_jpeg.len());
+
+        for quad in &quad_node_jpeg {
+            let block_size = (1 << quad.block_size) as usize;
+
+            result_block.push(vec![vec![0.0f32; block_size * block_size]; 3]);
+        }
+
+        for i in 0..quad_node_jpeg.len() {
+            let block_size = (1 << quad_node_jpeg[i].block_size) as usize;
+
+            let table_index = quad_node_jpeg[i].block_size as usize - 1;
+
+            if !jpeg.use_fast_dct {
+                jpeg_steps.dct_table = Some(Arc::clone(&dct_table[table_index]));
+                jpeg_steps.alpha_table = Some(Arc::clone(&alpha_table[table_index]));
+                jpeg_steps.two_block_size = 2.0f32 / block_size as f32;
+            }
+
+            jpeg_steps.block_size = block_size;
+            jpeg_steps.block_size_index = table_index;
+
+            let (q_matrix_luma, q_matrix_chroma) = node_q_matrices(i);
+
+            result_block[i][0] = quad_mind_steps_decompress_load(
+                &jpeg_steps,
+                &dct_zig_zag[i][0],
+                &q_matrix_luma,
+                &zig_zag_table[table_index],
+            );
+            result_block[i][1] = quad_mind_steps_decompress_load(
+                &jpeg_steps,
+                &dct_zig_zag[i][1],
+                &q_matrix_chroma,
+                &zig_zag_table[table_index],
+            );
+            result_block[i][2] = quad_mind_steps_decompress_load(
+                &jpeg_steps,
+                &dct_zig_zag[i][2],
+                &q_matrix_chroma,
+                &zig_zag_table[table_index],
+            );
+        }
+
+        result_block
+    };
+
+    let mut result: Vec2d<i16> = vec![vec![0i16; my_image.mheight * my_image.mwidth]; 3];
+
+    for i in 0..quad_node_jpeg.len() {
+        let block_size = (1 << quad_node_jpeg[i].block_size) as usize;
+
+        for j in 0..3 {
+            for y in 0..block_size {
+                for x in 0..block_size {
+                    let index_result_block = y * block_size + x;
+                    let index_result = (quad_node_jpeg[i].y as usize + y) * my_image.mwidth
+                        + (quad_node_jpeg[i].x as usize + x);
+
+                    result[j][index_result] = my_image::min_max_component(
+                        final_result_block[i][j][index_result_block] + 128.0f32,
+                        my_image.component_ranges[j],
+                    );
+                }
+            }
+        }
+    }
+
+    my_image.image_converted = result;
+
+    if quad_mind_header.use_color_transform {
+        my_image.ycbcr_to_image(jpeg.color_space)
+    } else {
+        my_image.rgb_to_image()
+    }
+
+    my_image.original_image = my_image.final_image.to_vec();
+    my_image.original_alpha = alpha_plane.clone();
+    my_image.final_alpha = alpha_plane;
+
+    (
+        my_image,
+        QuadMindConfig {
+            jpeg,
+            use_color_transform: quad_mind_header.use_color_transform,
+            settings,
+        },
+    )
+}
+
+fn zig_zag_function(zig_zag_table: &[usize], block_size: usize, dct_matrix: &[f32]) -> Vec<i32> {
+    let mut dct_matrix_zig_zag: Vec<i32> = vec![0i32; block_size * block_size];
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let index = y * block_size + x;
+            dct_matrix_zig_zag[zig_zag_table[index]] = dct_matrix[index] as i32;
+        }
+    }
+
+    dct_matrix_zig_zag
+}
+
+fn un_zig_zag_function(
+    zig_zag_table: &[usize],
+    block_size: usize,
+    dct_matrix_zig_zag: &[i32],
+) -> Vec<f32> {
+    let mut dct_matrix: Vec<f32> = vec![0.0f32; block_size * block_size];
+    for y in 0..block_size {
+        for x in 0..block_size {
+            let index = y * block_size + x;
+            dct_matrix[index] = dct_matrix_zig_zag[zig_zag_table[index]] as f32;
+        }
+    }
+
+    dct_matrix
+}
+
+pub fn generate_zig_zag_table(block_size: usize) -> Vec<usize> {
+    let mut zig_zag_table = vec![0usize; block_size * block_size];
+
+    let mut n = 0;
+    let mut j: usize;
+    let mut index: usize;
+
+    for i in 0..(block_size * 2) {
+        if i < block_size {
+            j = 0;
+        } else {
+            j = i - block_size + 1;
+        }
+
+        while j <= i && j < block_size {
+            if (i & 1) == 1 {
+                index = j * (block_size - 1) + i;
+            } else {
+                index = (i - j) * block_size + j;
+            }
+
+            zig_zag_table[index] = n;
+            n += 1;
+            j += 1;
+        }
+    }
+
+    zig_zag_table
+}
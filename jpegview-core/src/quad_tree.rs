@@ -1,10 +1,20 @@
 #![allow(clippy::needless_range_loop)]
 
-use crate::{my_image::MyImage, Vec2d};
+use crate::{
+    adaptive_quant,
+    chroma::{ChromaFilters, Subsampling},
+    color_space::ColorSpace,
+    my_image::MyImage,
+    Vec2d,
+};
 use std::{cell::RefCell, rc::Rc};
 
 pub type QuadNodeRef = Rc<RefCell<QuadNode>>;
 
+// Every node costs a split flag and every leaf its color
+const QUAD_NODE_SPLIT_BITS: usize = 1;
+const QUAD_LEAF_COLOR_BITS: usize = 24;
+
 pub struct QuadNode {
     pub rgb: [u8; 3],
     pub error: f32,
@@ -18,6 +28,9 @@ pub struct QuadNode {
     pub width_block_size: usize,
     pub height_block_size: usize,
 
+    // Set by QuadMind when it uses adaptive quantization
+    pub adaptive_scale_code: u8,
+
     pub children_tl: Option<QuadNodeRef>,
     pub children_tr: Option<QuadNodeRef>,
     pub children_bl: Option<QuadNodeRef>,
@@ -74,6 +87,8 @@ impl QuadNode {
             width_block_size: box_right - box_left,
             height_block_size: box_bottom - box_top,
 
+            adaptive_scale_code: adaptive_quant::SCALE_CODE_NEUTRAL,
+
             children_tl: None,
             children_tr: None,
             children_bl: None,
@@ -177,11 +192,23 @@ impl QuadTree {
             self.build(&mut quad_node.children_br, quad_node_list, my_image);
         }
     }
-    pub fn render(&self, my_image: &mut MyImage, use_ycbcr: bool, subsampling_index: usize) {
+    pub fn render(
+        &self,
+        my_image: &mut MyImage,
+        use_color_transform: bool,
+        subsampling: Subsampling,
+        chroma_filters: ChromaFilters,
+        color_space: ColorSpace,
+    ) -> usize {
         my_image.mwidth = my_image.width;
         my_image.mheight = my_image.height;
 
-        my_image.apply_transform(use_ycbcr, subsampling_index);
+        my_image.apply_transform(
+            use_color_transform,
+            subsampling,
+            chroma_filters,
+            color_space,
+        );
 
         let mut quad_root = if self.use_pow_2 {
             let square_size = if my_image.width > my_image.height {
@@ -250,6 +277,24 @@ impl QuadTree {
                 );
             }
         }
+
+        let estimated_bits = count_nodes(&quad_root) * QUAD_NODE_SPLIT_BITS
+            + quad_node_list.len() * QUAD_LEAF_COLOR_BITS;
+
+        estimated_bits.div_ceil(8)
+    }
+}
+
+pub fn count_nodes(quad_node: &Option<QuadNodeRef>) -> usize {
+    match quad_node {
+        Some(quad_node) => {
+            let quad_node = quad_node.borrow();
+            1 + count_nodes(&quad_node.children_tl)
+                + count_nodes(&quad_node.children_tr)
+                + count_nodes(&quad_node.children_bl)
+                + count_nodes(&quad_node.children_br)
+        }
+        None => 0,
     }
 }
 
@@ -0,0 +1,213 @@
+use crate::{jpeg, jpeg_file};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+// Custom quantization tables replace the built in ones at any block size,
+// they are tiled or resampled like the 8x8 tables and scaled by the quality
+// like libjpeg, quality 50 keeps them unchanged
+
+pub const QUANT_TABLE_SIZES: [usize; 6] = [2, 4, 8, 16, 32, 64];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QuantTables {
+    pub size: usize,
+    pub luma: Vec<f32>,
+    pub chroma: Vec<f32>,
+}
+
+impl QuantTables {
+    // The built in tables at a size, a starting point for editing
+    pub fn new(size: usize, use_gen_qtable: bool) -> QuantTables {
+        QuantTables {
+            size,
+            luma: jpeg::generate_q_matrix(&jpeg::Q_MATRIX_LUMA_CONST, size, use_gen_qtable),
+            chroma: jpeg::generate_q_matrix(&jpeg::Q_MATRIX_CHROMA_CONST, size, use_gen_qtable),
+        }
+    }
+    pub fn resize(&self, size: usize) -> QuantTables {
+        QuantTables {
+            size,
+            luma: self.q_matrix(&self.luma, size, false),
+            chroma: self.q_matrix(&self.chroma, size, false),
+        }
+    }
+    pub fn q_matrix(
+        &self,
+        q_table: &[f32],
+        block_size: usize,
+        use_resampled_qtable: bool,
+    ) -> Vec<f32> {
+        if use_resampled_qtable {
+            return jpeg::resample_q_matrix(q_table, self.size, block_size);
+        }
+
+        let mut q_matrix: Vec<f32> = vec![0.0f32; block_size * block_size];
+        for y in 0..block_size {
+            for x in 0..block_size {
+                q_matrix[y * block_size + x] =
+                    q_table[(y % self.size) * self.size + (x % self.size)];
+            }
+        }
+        q_matrix
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !QUANT_TABLE_SIZES.contains(&self.size) {
+            return Err(format!(
+                "Unsupported quantization table size: {}",
+                self.size
+            ));
+        }
+        if self.luma.len() != self.size * self.size || self.chroma.len() != self.size * self.size {
+            return Err(String::from(
+                "Quantization table length does not match its size",
+            ));
+        }
+        if self
+            .luma
+            .iter()
+            .chain(&self.chroma)
+            .any(|value| !(1.0f32..=65535.0f32).contains(value))
+        {
+            return Err(String::from(
+                "Quantization table values must be between 1 and 65535",
+            ));
+        }
+        Ok(())
+    }
+
+    // Whitespace separated values, luma and then chroma, or a single table
+    // for both. Everything after a # is a comment
+    pub fn from_text(text: &str) -> Result<QuantTables, String> {
+        let mut values = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            for value in line.split(|c: char| c.is_whitespace() || c == ',') {
+                if value.is_empty() {
+                    continue;
+                }
+                values.push(
+                    value
+                        .parse::<f32>()
+                        .map_err(|_| format!("Invalid quantization table value: {}", value))?,
+                );
+            }
+        }
+
+        let size = QUANT_TABLE_SIZES
+            .iter()
+            .copied()
+            .find(|&size| values.len() == size * size || values.len() == size * size * 2)
+            .ok_or_else(|| {
+                format!(
+                    "{} values do not make one or two square quantization tables",
+                    values.len()
+                )
+            })?;
+
+        let chroma = if values.len() == size * size * 2 {
+            values.split_off(size * size)
+        } else {
+            values.clone()
+        };
+
+        let q_tables = QuantTables {
+            size,
+            luma: values,
+            chroma,
+        };
+
+        q_tables.validate()?;
+        Ok(q_tables)
+    }
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, q_table) in [("Luma", &self.luma), ("Chroma", &self.chroma)] {
+            text.push_str(&format!("# {} {}x{}\n", name, self.size, self.size));
+            for row in q_table.chunks(self.size) {
+                let row: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                text.push_str(&row.join(" "));
+                text.push('\n');
+            }
+        }
+        text
+    }
+    pub fn from_json(json: &str) -> Result<QuantTables, String> {
+        let q_tables: QuantTables = serde_json::from_str(json)
+            .map_err(|error| format!("Could not parse quantization tables: {}", error))?;
+
+        q_tables.validate()?;
+        Ok(q_tables)
+    }
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Could not serialize quantization tables")
+    }
+    // The DQT tables used by the first two components of a jpg file
+    pub fn from_jpeg(data: &[u8]) -> Result<QuantTables, String> {
+        let jpeg_file = jpeg_file::decode_jpeg(data)
+            .map_err(|error| format!("Could not decode jpeg: {}", error))?;
+
+        let q_table = |component: usize| {
+            jpeg_file
+                .components
+                .get(component)
+                .and_then(|component| jpeg_file.q_tables[component.q_table_index])
+                .map(|q_table| q_table.iter().map(|&value| value as f32).collect())
+        };
+
+        let luma: Vec<f32> = q_table(0).ok_or("Jpeg has no quantization table")?;
+        let chroma = q_table(1).unwrap_or_else(|| luma.clone());
+
+        let q_tables = QuantTables {
+            size: 8,
+            luma,
+            chroma,
+        };
+
+        q_tables.validate()?;
+        Ok(q_tables)
+    }
+
+    pub fn load(path: &Path) -> Result<QuantTables, String> {
+        let mut contents: Vec<u8> = Vec::new();
+        let mut file =
+            File::open(path).map_err(|error| format!("Could not open file: {}", error))?;
+        file.read_to_end(&mut contents)
+            .map_err(|error| format!("Could not read file: {}", error))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jpg") | Some("jpeg") => QuantTables::from_jpeg(&contents),
+            Some("json") => QuantTables::from_json(&String::from_utf8_lossy(&contents)),
+            _ => QuantTables::from_text(&String::from_utf8_lossy(&contents)),
+        }
+    }
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => self.to_json(),
+            Some("txt") => self.to_text(),
+            _ => {
+                return Err(String::from(
+                    "Quantization tables can only be saved as txt or json",
+                ))
+            }
+        };
+
+        let mut file =
+            File::create(path).map_err(|error| format!("Could not create file: {}", error))?;
+        file.write_all(contents.as_bytes())
+            .map_err(|error| format!("Could not write to file: {}", error))
+    }
+}
+
+// libjpeg scaling, 50 keeps the table and 100 makes every value 1
+pub fn quality_factor(quality: f32) -> f32 {
+    if quality >= 50.0f32 {
+        (200.0f32 - quality * 2.0f32) * 0.01f32
+    } else {
+        50.0f32 / quality
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    chroma::Subsampling,
+    jpeg::Jpeg,
+    metrics,
+    my_image::MyImage,
+    quad_mind,
+    quad_tree::{QuadNodeRef, QuadTree},
+    Vec3d,
+};
+
+const RATE_CONTROL_ITERATIONS: usize = 8;
+
+const QUALITY_MIN: f32 = 1.0f32;
+const QUALITY_MAX: f32 = 100.0f32;
+
+const THRESHOLD_ERROR_MIN: f32 = 0.0f32;
+const THRESHOLD_ERROR_MAX: f32 = 128.0f32;
+
+#[derive(Clone, Copy, Debug)]
+pub enum RateTarget {
+    Bytes(usize),
+    Psnr(f32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateControlResult {
+    pub quality: f32,
+    pub threshold_error: Option<f32>,
+
+    pub bytes: usize,
+    pub psnr: f32,
+
+    pub renders: usize,
+    pub target_met: bool,
+}
+
+impl RateTarget {
+    fn accepts(&self, bytes: usize, psnr: f32) -> bool {
+        match *self {
+            RateTarget::Bytes(target_bytes) => bytes <= target_bytes,
+            RateTarget::Psnr(target_psnr) => psnr >= target_psnr,
+        }
+    }
+    // A byte budget is met by low quality, a PSNR goal by high quality
+    fn accepts_low_quality(&self) -> bool {
+        matches!(*self, RateTarget::Bytes(_))
+    }
+}
+
+// Searches the quality, compression rate is turned off during the search
+// since it ignores it
+pub fn rate_control_jpeg(
+    jpeg: &mut Jpeg,
+    my_image: &mut MyImage,
+    target: RateTarget,
+    use_color_transform: bool,
+    use_threads: bool,
+    subsampling: Subsampling,
+) -> RateControlResult {
+    let use_compression_rate = std::mem::replace(&mut jpeg.use_compression_rate, false);
+
+    let mut renders = 0;
+    let mut render = |jpeg: &mut Jpeg, my_image: &mut MyImage, quality: f32| {
+        renders += 1;
+
+        jpeg.quality = quality;
+
+        my_image.mwidth = my_image.width;
+        my_image.mheight = my_image.height;
+
+        let bytes = jpeg.render(my_image, use_color_transform, use_threads, subsampling);
+        (bytes, image_psnr(my_image))
+    };
+
+    let quality = bisect(
+        QUALITY_MIN,
+        QUALITY_MAX,
+        target.accepts_low_quality(),
+        |quality| {
+            let (bytes, psnr) = render(jpeg, my_image, quality);
+            target.accepts(bytes, psnr)
+        },
+    );
+
+    let target_met = quality.is_some();
+    let quality = quality.unwrap_or_else(|| fallback_quality(target));
+
+    let (bytes, psnr) = render(jpeg, my_image, quality);
+    jpeg.use_compression_rate = use_compression_rate;
+
+    RateControlResult {
+        quality,
+        threshold_error: None,
+
+        bytes,
+        psnr,
+
+        renders,
+        target_met,
+    }
+}
+
+// Searches the quality first, when no quality meets the target the
+// QuadTree threshold error is searched with the closest quality. Compression
+// rate is off during the search like for jpeg
+pub fn rate_control_quad_mind(
+    jpeg: &mut Jpeg,
+    quad_tree: &mut QuadTree,
+    my_image: &mut MyImage,
+    target: RateTarget,
+    use_color_transform: bool,
+    use_threads: bool,
+    subsampling: Subsampling,
+) -> (RateControlResult, Vec<QuadNodeRef>, Vec3d<i32>) {
+    let use_compression_rate = std::mem::replace(&mut jpeg.use_compression_rate, false);
+
+    let mut renders = 0;
+    let mut render = |jpeg: &mut Jpeg, quad_tree: &QuadTree, my_image: &mut MyImage| {
+        renders += 1;
+
+        let (quad_node_list, quad_dct_zig_zag, bytes) = quad_mind::render_quad_mind(
+            jpeg,
+            my_image,
+            quad_tree,
+            use_color_transform,
+            use_threads,
+            subsampling,
+        );
+        (
+            quad_node_list,
+            quad_dct_zig_zag,
+            bytes,
+            image_psnr(my_image),
+        )
+    };
+
+    let mut quality = bisect(
+        QUALITY_MIN,
+        QUALITY_MAX,
+        target.accepts_low_quality(),
+        |quality| {
+            jpeg.quality = quality;
+            let (_, _, bytes, psnr) = render(jpeg, quad_tree, my_image);
+            target.accepts(bytes, psnr)
+        },
+    );
+
+    if quality.is_none() {
+        jpeg.quality = fallback_quality(target);
+
+        // A higher threshold error merges more quads and costs less bytes
+        let threshold_error = bisect(
+            THRESHOLD_ERROR_MIN,
+            THRESHOLD_ERROR_MAX,
+            !target.accepts_low_quality(),
+            |threshold_error| {
+                quad_tree.threshold_error = threshold_error;
+                let (_, _, bytes, psnr) = render(jpeg, quad_tree, my_image);
+                target.accepts(bytes, psnr)
+            },
+        );
+
+        if threshold_error.is_some() {
+            quality = Some(jpeg.quality);
+        }
+
+        quad_tree.threshold_error = threshold_error.unwrap_or(match target {
+            RateTarget::Bytes(_) => THRESHOLD_ERROR_MAX,
+            RateTarget::Psnr(_) => THRESHOLD_ERROR_MIN,
+        });
+    }
+
+    let target_met = quality.is_some();
+    jpeg.quality = quality.unwrap_or_else(|| fallback_quality(target));
+
+    let (quad_node_list, quad_dct_zig_zag, bytes, psnr) = render(jpeg, quad_tree, my_image);
+    jpeg.use_compression_rate = use_compression_rate;
+
+    (
+        RateControlResult {
+            quality: jpeg.quality,
+            threshold_error: Some(quad_tree.threshold_error),
+
+            bytes,
+            psnr,
+
+            renders,
+            target_met,
+        },
+        quad_node_list,
+        quad_dct_zig_zag,
+    )
+}
+
+fn fallback_quality(target: RateTarget) -> f32 {
+    if target.accepts_low_quality() {
+        QUALITY_MIN
+    } else {
+        QUALITY_MAX
+    }
+}
+
+fn image_psnr(my_image: &MyImage) -> f32 {
+    metrics::psnr(metrics::mean_squared_error(
+        &my_image.original_image,
+        &my_image.final_image,
+    ))
+}
+
+// Finds the accepted value closest to the rejected side, accept must be
+// monotonic with accepted values all below (accept_low) or all above it
+fn bisect<F>(mut low: f32, mut high: f32, accept_low: bool, mut accept: F) -> Option<f32>
+where
+    F: FnMut(f32) -> bool,
+{
+    let (best_end, worst_end) = if accept_low { (high, low) } else { (low, high) };
+
+    if accept(best_end) {
+        return Some(best_end);
+    }
+    if !accept(worst_end) {
+        return None;
+    }
+
+    let mut best = worst_end;
+    for _ in 0..RATE_CONTROL_ITERATIONS {
+        let middle = (low + high) * 0.5f32;
+        let accepted = accept(middle);
+
+        if accepted {
+            best = middle;
+        }
+        if accepted == accept_low {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some(best)
+}
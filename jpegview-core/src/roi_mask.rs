@@ -0,0 +1,127 @@
+use std::path::Path;
+
+// A grayscale region of interest over the image, white keeps the quality and
+// black drops it to the start quality. The mask is stretched over the image
+// so it can be loaded at any size
+#[derive(Clone)]
+pub struct RoiMask {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<u8>,
+}
+
+impl RoiMask {
+    // An empty mask compresses the whole image at the start quality
+    pub fn new(width: usize, height: usize) -> RoiMask {
+        RoiMask {
+            width,
+            height,
+            values: vec![0u8; width * height],
+        }
+    }
+    pub fn load(path: &Path) -> Result<RoiMask, String> {
+        let image = image::io::Reader::open(path)
+            .map_err(|error| format!("Could not open mask: {}", error))?
+            .decode()
+            .map_err(|error| format!("Could not decode mask: {}", error))?;
+
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+
+        if width == 0 || height == 0 {
+            return Err(String::from("Mask is empty"));
+        }
+
+        Ok(RoiMask {
+            width,
+            height,
+            values: image.into_luma8().into_vec(),
+        })
+    }
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        image::GrayImage::from_raw(self.width as u32, self.height as u32, self.values.clone())
+            .expect("Mask size does not match its values")
+            .save(path)
+            .map_err(|error| format!("Could not save mask: {}", error))
+    }
+
+    // Mean of the mask over the part of a block inside the image, 0 to 1.
+    // Blocks in the padding use the nearest edge
+    pub fn block_weight(
+        &self,
+        box_left: usize,
+        box_top: usize,
+        block_size: usize,
+        width: usize,
+        height: usize,
+    ) -> f32 {
+        let left = box_left.min(width - 1);
+        let top = box_top.min(height - 1);
+        let right = (box_left + block_size).clamp(left + 1, width);
+        let bottom = (box_top + block_size).clamp(top + 1, height);
+
+        let mut sum = 0u64;
+        for y in top..bottom {
+            let mask_y = y * self.height / height;
+            for x in left..right {
+                let mask_x = x * self.width / width;
+                sum += self.values[mask_y * self.width + mask_x] as u64;
+            }
+        }
+
+        sum as f32 / ((right - left) * (bottom - top)) as f32 / 255.0f32
+    }
+
+    // Fills a circle given in image pixels
+    pub fn paint(
+        &mut self,
+        center_x: f32,
+        center_y: f32,
+        radius: f32,
+        value: u8,
+        width: usize,
+        height: usize,
+    ) {
+        let scale_x = self.width as f32 / width as f32;
+        let scale_y = self.height as f32 / height as f32;
+
+        let mask_x = center_x * scale_x;
+        let mask_y = center_y * scale_y;
+        let radius_x = (radius * scale_x).max(0.5f32);
+        let radius_y = (radius * scale_y).max(0.5f32);
+
+        let left = (mask_x - radius_x).floor().max(0.0f32) as usize;
+        let top = (mask_y - radius_y).floor().max(0.0f32) as usize;
+        let right = ((mask_x + radius_x).ceil().max(0.0f32) as usize).min(self.width);
+        let bottom = ((mask_y + radius_y).ceil().max(0.0f32) as usize).min(self.height);
+
+        for y in top..bottom {
+            for x in left..right {
+                let dx = (x as f32 + 0.5f32 - mask_x) / radius_x;
+                let dy = (y as f32 + 0.5f32 - mask_y) / radius_y;
+                if dx * dx + dy * dy <= 1.0f32 {
+                    self.values[y * self.width + x] = value;
+                }
+            }
+        }
+    }
+
+    // Darkens the image where the mask is black to show it while painting
+    pub fn draw_overlay(&self, image: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let mut result = image.to_vec();
+        for y in 0..height {
+            let mask_y = y * self.height / height;
+            for x in 0..width {
+                let mask_x = x * self.width / width;
+                let weight = self.values[mask_y * self.width + mask_x] as f32 / 255.0f32;
+                let brightness = 0.3f32 + weight * 0.7f32;
+
+                let index = (y * width + x) * 3;
+                for value in &mut result[index..index + 3] {
+                    *value = (*value as f32 * brightness).round() as u8;
+                }
+            }
+        }
+        result
+    }
+}
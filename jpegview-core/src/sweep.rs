@@ -0,0 +1,293 @@
+use crate::{
+    bitrate::SizeEstimate, chroma::Subsampling, jpeg::Jpeg, metrics::Metrics, my_image::MyImage,
+    quad_mind, quad_tree::QuadTree,
+};
+use std::{fs::File, io::Write, path::Path};
+
+#[derive(Clone)]
+pub struct Sweep {
+    pub quality_start: f32,
+    pub quality_end: f32,
+    pub quality_step: f32,
+
+    pub block_size_indices: Vec<usize>,
+    // Indices of the subsampling presets
+    pub subsampling_indices: Vec<usize>,
+
+    pub use_jpeg: bool,
+    pub use_quad_mind: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct SweepPoint {
+    pub mode: &'static str,
+    pub quality: f32,
+    pub block_size: usize,
+    pub subsampling_index: usize,
+
+    pub bytes: usize,
+    pub bpp: f32,
+
+    pub psnr: f32,
+    pub psnr_y: f32,
+    pub ssim: f32,
+    pub ms_ssim: f32,
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            quality_start: 10.0f32,
+            quality_end: 100.0f32,
+            quality_step: 10.0f32,
+
+            block_size_indices: vec![2],
+            subsampling_indices: vec![0],
+
+            use_jpeg: true,
+            use_quad_mind: false,
+        }
+    }
+    pub fn qualities(&self) -> Vec<f32> {
+        let mut qualities = Vec::new();
+        let mut quality = self.quality_start;
+        while quality <= self.quality_end + 0.001f32 {
+            qualities.push(quality.min(100.0f32));
+            if self.quality_step <= 0.0f32 {
+                break;
+            }
+            quality += self.quality_step;
+        }
+        qualities
+    }
+    pub fn point_count(&self) -> usize {
+        let jpeg_count = if self.use_jpeg {
+            self.block_size_indices.len()
+        } else {
+            0
+        };
+        let quad_mind_count = if self.use_quad_mind {
+            self.quad_mind_subsampling_count()
+        } else {
+            0
+        };
+
+        self.qualities().len() * (self.subsampling_indices.len() * jpeg_count + quad_mind_count)
+    }
+    // QuadMind codes full resolution chroma, it only runs at 4:4:4
+    fn quad_mind_subsampling_count(&self) -> usize {
+        self.subsampling_indices
+            .iter()
+            .filter(|&&index| Subsampling::PRESETS[index] == Subsampling::default())
+            .count()
+    }
+    // Quality, block size and subsampling are taken from the sweep,
+    // every other setting from jpeg and quad_tree
+    #[allow(clippy::too_many_arguments)]
+    pub fn run<F>(
+        &self,
+        jpeg: &Jpeg,
+        quad_tree: &QuadTree,
+        original_image: &[u8],
+        original_alpha: Option<&[u8]>,
+        width: usize,
+        height: usize,
+        use_color_transform: bool,
+        use_threads: bool,
+        mut on_point: F,
+    ) where
+        F: FnMut(SweepPoint),
+    {
+        let mut my_image = MyImage::new(
+            original_image.to_vec(),
+            width,
+            height,
+            String::from("sweep"),
+        );
+        my_image.original_alpha = original_alpha.map(|alpha| alpha.to_vec());
+
+        for &subsampling_index in &self.subsampling_indices {
+            let subsampling = Subsampling::PRESETS[subsampling_index];
+
+            for quality in self.qualities() {
+                if self.use_jpeg {
+                    for &block_size_index in &self.block_size_indices {
+                        let block_size = 1 << (block_size_index + 1);
+
+                        let mut jpeg = point_jpeg(jpeg, quality, use_threads);
+                        jpeg.block_size = block_size;
+                        jpeg.block_size_index = block_size_index;
+
+                        // Padding of a previous bigger block size must not carry over
+                        my_image.mwidth = width;
+                        my_image.mheight = height;
+
+                        let bytes = jpeg.render(
+                            &mut my_image,
+                            use_color_transform,
+                            use_threads,
+                            subsampling,
+                        );
+
+                        on_point(SweepPoint::new(
+                            "jpeg",
+                            quality,
+                            block_size,
+                            subsampling_index,
+                            bytes,
+                            &my_image,
+                        ));
+                    }
+                }
+
+                if self.use_quad_mind && subsampling == Subsampling::default() {
+                    let mut jpeg = point_jpeg(jpeg, quality, use_threads);
+
+                    let (_, _, bytes) = quad_mind::render_quad_mind(
+                        &mut jpeg,
+                        &mut my_image,
+                        quad_tree,
+                        use_color_transform,
+                        use_threads,
+                        subsampling,
+                    );
+
+                    on_point(SweepPoint::new(
+                        "quadmind",
+                        quality,
+                        quad_tree.max_size,
+                        subsampling_index,
+                        bytes,
+                        &my_image,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Default for Sweep {
+    fn default() -> Sweep {
+        Sweep::new()
+    }
+}
+
+// The settings of a point, compression rate would replace its quality and
+// the adaptive scales would be drawn over the image it measures
+fn point_jpeg(jpeg: &Jpeg, quality: f32, use_threads: bool) -> Jpeg {
+    let mut jpeg = jpeg.clone();
+    jpeg.quality = quality;
+    jpeg.use_threads = use_threads;
+    jpeg.use_compression_rate = false;
+    jpeg.use_draw_adaptive_scale = false;
+    jpeg
+}
+
+impl SweepPoint {
+    pub fn new(
+        mode: &'static str,
+        quality: f32,
+        block_size: usize,
+        subsampling_index: usize,
+        bytes: usize,
+        my_image: &MyImage,
+    ) -> SweepPoint {
+        let size_estimate = SizeEstimate::new(bytes, my_image.width, my_image.height);
+        let metrics = Metrics::new(my_image);
+
+        SweepPoint {
+            mode,
+            quality,
+            block_size,
+            subsampling_index,
+
+            bytes,
+            bpp: size_estimate.bpp,
+
+            psnr: metrics.psnr,
+            psnr_y: metrics.y.psnr,
+            ssim: metrics.y.ssim,
+            ms_ssim: metrics.y.ms_ssim,
+        }
+    }
+    // Points with the same series name belong to the same RD curve
+    pub fn series(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.mode,
+            self.block_size,
+            Subsampling::PRESETS[self.subsampling_index].name()
+        )
+    }
+}
+
+const CSV_HEADER: &str =
+    "mode,quality,block_size,subsampling,bytes,bpp,psnr,psnr_y,ssim_y,ms_ssim_y";
+
+pub fn to_csv(points: &[SweepPoint]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+
+    for point in points {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{:.5},{:.5},{:.5},{:.6},{:.6}\n",
+            point.mode,
+            point.quality,
+            point.block_size,
+            Subsampling::PRESETS[point.subsampling_index].name(),
+            point.bytes,
+            point.bpp,
+            point.psnr,
+            point.psnr_y,
+            point.ssim,
+            point.ms_ssim
+        ));
+    }
+    csv
+}
+
+pub fn to_json(points: &[SweepPoint]) -> String {
+    let records: Vec<String> = points
+        .iter()
+        .map(|point| {
+            format!(
+                "  {{\"mode\": \"{}\", \"quality\": {}, \"block_size\": {}, \"subsampling\": \"{}\", \
+                 \"bytes\": {}, \"bpp\": {}, \"psnr\": {}, \"psnr_y\": {}, \"ssim_y\": {}, \"ms_ssim_y\": {}}}",
+                point.mode,
+                point.quality,
+                point.block_size,
+                Subsampling::PRESETS[point.subsampling_index].name(),
+                point.bytes,
+                json_number(point.bpp),
+                json_number(point.psnr),
+                json_number(point.psnr_y),
+                json_number(point.ssim),
+                json_number(point.ms_ssim)
+            )
+        })
+        .collect();
+
+    format!("[\n{}\n]\n", records.join(",\n"))
+}
+
+// JSON has no infinity, a lossless point has an infinite PSNR
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+pub fn save_sweep(path: &Path, points: &[SweepPoint]) -> Result<(), String> {
+    let contents = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => to_csv(points),
+        Some("json") => to_json(points),
+        _ => return Err(String::from("Sweep can only be saved as csv or json")),
+    };
+
+    let mut file =
+        File::create(path).map_err(|error| format!("Could not create file: {}", error))?;
+    file.write_all(contents.as_bytes())
+        .map_err(|error| format!("Could not write to file: {}", error))
+}
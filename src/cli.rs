@@ -0,0 +1,792 @@
+use crate::imgui_layout::{BLOCK_SIZE_ITEMS, DOWNSAMPLE_FILTER_ITEMS, UPSAMPLE_FILTER_ITEMS};
+use jpegview_core::{
+    bitrate::SizeEstimate,
+    chroma::{ChromaFilters, ChromaSiting, DownsampleFilter, Subsampling, UpsampleFilter},
+    color_space::{ColorRange, ColorSpace, ColorTransform, YcbcrMatrix},
+    jfif,
+    jpeg::Jpeg,
+    metrics::{ChannelMetrics, Metrics},
+    quad_mind,
+    quad_tree::QuadTree,
+    quant_table::QuantTables,
+    rate_control::{self, RateControlResult, RateTarget},
+    roi_mask::RoiMask,
+    sweep::{self, Sweep},
+};
+use std::{fs, path::Path, thread, time::Instant};
+
+pub const USAGE: &str = "Usage:
+    jpegview-rust
+    jpegview-rust compress [options] <input> <output>
+    jpegview-rust metrics <reference> <image>
+    jpegview-rust sweep [options] <input> <output.csv|output.json>
+    jpegview-rust migrate <input.qmi> <output.qmi>
+
+Options:
+    --mode <jpeg|quadtree|quadmind|none>  Compression mode (default: jpeg)
+    --quality <1-100>                     Quality factor (default: 90)
+    --quality-start <1-100>               Quality start for compression rate (default: 1)
+    --block-size <2-512>                  Jpeg block size, power of 2 (default: 8)
+    --subsampling <J:a:b|HxV>             Chroma subsampling, factors up to 4, jpeg and none modes (default: 4:4:4)
+    --downsample <box|triangle|lanczos>   Chroma downsampling filter (default: box)
+    --upsample <nearest|bilinear|fancy>   Chroma upsampling filter (default: fancy)
+    --siting <centered|cosited>           Chroma sample position (default: centered)
+    --transform <name>                    Color transform: ycbcr, ycocg, ycocg-r, rct, ict, cielab
+                                          or oklab (default: ycbcr)
+    --matrix <bt601|bt709|bt2020>         YCbCr matrix (default: bt601)
+    --range <full|limited>                YCbCr range (default: full)
+    --rgb                                 Use RGB colors instead of a color transform
+    --alpha-quality <1-100>               Compress alpha lossy with its own quality (default: lossless)
+    --gen-qtable                          Use generated quantization table
+    --resample-qtable                     Resample quantization tables to the block size instead of tiling
+    --qtables <file>                      Custom quantization tables (.txt, .json or a .jpg's DQT)
+    --csf-qtable                          Use perceptual quantization tables from a contrast sensitivity function
+    --viewing-distance <cm>               Viewing distance for --csf-qtable (default: 50)
+    --display-dpi <dpi>                   Display DPI for --csf-qtable (default: 96)
+    --rdo-lambda <n>                      Rate-distortion optimized quantization with a lambda
+    --dead-zone <0-2>                     Coefficients below it in steps become zero (default: 0.5)
+    --rounding-offset <0-0.5>             Added before truncating, 0.5 rounds to nearest (default: 0.5)
+    --reconstruction-bias <0-0.5>         Steps dequantized values move towards zero (default: 0)
+    --adaptive-quant <0-4>                Scale every block by its activity with a strength
+    --draw-adaptive-scale                 Tint blocks quantized coarser red and finer blue
+    --roi-mask <file>                     Grayscale mask, black blocks drop to the quality start
+    --compression-rate                    Show compression rate
+    --slow-dct                            Do not use fast DCT algorithm
+    --no-threads                          Do not use multi-threading
+    --optimize-huffman                    Use optimized Huffman tables for .jpg output
+    --metrics                             Print PSNR, SSIM and MS-SSIM of the result
+    --target-size <bytes>                 Search the quality meeting a size (jpeg, quadmind)
+    --target-psnr <dB>                    Search the quality meeting a PSNR (jpeg, quadmind)
+    --max-depth <n>                       QuadTree max depth (default: 50)
+    --threshold-error <n>                 QuadTree error threshold (default: 10)
+    --min-size <2-512>                    QuadTree min quad size (default: 4)
+    --max-size <2-512>                    QuadTree max quad size (default: 64)
+    --pow-2                               Use QuadTree quad size power of 2
+    --draw-line                           Draw QuadTree quadrant lines
+
+Sweep options (compress options also apply):
+    --qualities <start:end:step>          Quality range (default: 10:100:10)
+    --block-sizes <n,n,...>               Jpeg block sizes (default: 8)
+    --subsamplings <J:a:b,...>            Chroma subsampling presets (default: 4:4:4)
+    --quadmind                            Also sweep QuadMind, at 4:4:4 only
+    --no-jpeg                             Do not sweep Jpeg
+
+Output format follows the extension, jpeg mode writes baseline .jpg files
+with 8x8 blocks and quadmind mode can also write .qmi files.";
+
+#[derive(PartialEq)]
+pub enum CompressMode {
+    Jpeg,
+    QuadTree,
+    QuadMind,
+    None,
+}
+
+pub struct CompressArgs {
+    pub mode: CompressMode,
+
+    pub input: String,
+    pub output: String,
+
+    pub jpeg: Jpeg,
+    pub quad_tree: QuadTree,
+
+    pub use_color_transform: bool,
+    pub use_threads: bool,
+    pub use_optimized_huffman: bool,
+    pub use_metrics: bool,
+    pub subsampling: Subsampling,
+
+    pub rate_target: Option<RateTarget>,
+}
+
+impl CompressArgs {
+    pub fn parse(args: &[String]) -> Result<CompressArgs, String> {
+        let use_threads = match thread::available_parallelism() {
+            Ok(count) => count.get() > 1,
+            Err(_) => false,
+        };
+
+        let mut compress_args = CompressArgs {
+            mode: CompressMode::Jpeg,
+
+            input: String::new(),
+            output: String::new(),
+
+            jpeg: Jpeg::new(8, 90.0f32, 1.0f32, 2, false, use_threads, true, false),
+            quad_tree: QuadTree::new(50, 4, 64, false, false, 10.0f32),
+
+            use_color_transform: true,
+            use_threads,
+            use_optimized_huffman: false,
+            use_metrics: false,
+            subsampling: Subsampling::default(),
+
+            rate_target: None,
+        };
+
+        let mut paths = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--mode" => {
+                    compress_args.mode = match value()?.as_str() {
+                        "jpeg" => CompressMode::Jpeg,
+                        "quadtree" => CompressMode::QuadTree,
+                        "quadmind" => CompressMode::QuadMind,
+                        "none" => CompressMode::None,
+                        mode => return Err(format!("Unknown mode: {}", mode)),
+                    }
+                }
+                "--quality" => compress_args.jpeg.quality = parse_quality(value()?)?,
+                "--quality-start" => compress_args.jpeg.quality_start = parse_quality(value()?)?,
+                "--block-size" => {
+                    compress_args.jpeg.block_size_index = parse_block_size_index(value()?)?;
+                    compress_args.jpeg.block_size = 1 << (compress_args.jpeg.block_size_index + 1);
+                }
+                "--subsampling" => compress_args.subsampling = Subsampling::parse(value()?)?,
+                "--downsample" => {
+                    compress_args.jpeg.chroma_filters.downsample = DownsampleFilter::ALL
+                        [parse_filter_index("downsampling", &DOWNSAMPLE_FILTER_ITEMS, value()?)?]
+                }
+                "--upsample" => {
+                    compress_args.jpeg.chroma_filters.upsample = UpsampleFilter::ALL
+                        [parse_filter_index("upsampling", &UPSAMPLE_FILTER_ITEMS, value()?)?]
+                }
+                "--siting" => {
+                    compress_args.jpeg.chroma_filters.siting = match value()?.as_str() {
+                        "centered" => ChromaSiting::Centered,
+                        "cosited" => ChromaSiting::Cosited,
+                        siting => return Err(format!("Unknown chroma siting: {}", siting)),
+                    }
+                }
+                "--transform" => {
+                    compress_args.jpeg.color_space.transform = match value()?.as_str() {
+                        "ycbcr" => ColorTransform::Ycbcr,
+                        "ycocg" => ColorTransform::Ycocg,
+                        "ycocg-r" => ColorTransform::YcocgR,
+                        "rct" => ColorTransform::Rct,
+                        "ict" => ColorTransform::Ict,
+                        "cielab" => ColorTransform::Cielab,
+                        "oklab" => ColorTransform::Oklab,
+                        transform => return Err(format!("Unknown transform: {}", transform)),
+                    }
+                }
+                "--matrix" => {
+                    compress_args.jpeg.color_space.matrix = match value()?.as_str() {
+                        "bt601" => YcbcrMatrix::Bt601,
+                        "bt709" => YcbcrMatrix::Bt709,
+                        "bt2020" => YcbcrMatrix::Bt2020,
+                        matrix => return Err(format!("Unknown matrix: {}", matrix)),
+                    }
+                }
+                "--range" => {
+                    compress_args.jpeg.color_space.range = match value()?.as_str() {
+                        "full" => ColorRange::Full,
+                        "limited" => ColorRange::Limited,
+                        range => return Err(format!("Unknown range: {}", range)),
+                    }
+                }
+                "--rgb" => compress_args.use_color_transform = false,
+                "--alpha-quality" => {
+                    compress_args.jpeg.alpha.quality = parse_quality(value()?)?;
+                    compress_args.jpeg.alpha.use_lossless = false;
+                }
+                "--gen-qtable" => compress_args.jpeg.use_gen_qtable = true,
+                "--resample-qtable" => compress_args.jpeg.use_resampled_qtable = true,
+                "--qtables" => {
+                    compress_args.jpeg.q_tables = Some(QuantTables::load(Path::new(value()?))?)
+                }
+                "--csf-qtable" => compress_args.jpeg.use_csf_qtable = true,
+                "--viewing-distance" => {
+                    compress_args.jpeg.csf.viewing_distance =
+                        parse_positive("viewing distance", value()?)?
+                }
+                "--display-dpi" => {
+                    compress_args.jpeg.csf.display_dpi = parse_positive("display DPI", value()?)?
+                }
+                "--rdo-lambda" => {
+                    let rdo_lambda = value()?;
+                    compress_args.jpeg.rdo_lambda = rdo_lambda
+                        .parse()
+                        .ok()
+                        .filter(|lambda: &f32| lambda.is_finite() && *lambda >= 0.0f32)
+                        .ok_or_else(|| format!("Invalid RDO lambda: {}", rdo_lambda))?;
+                    compress_args.jpeg.use_rdo_quantization = true;
+                }
+                "--dead-zone" => {
+                    compress_args.jpeg.quantizer.dead_zone =
+                        parse_range("dead zone", value()?, 0.0f32, 2.0f32)?
+                }
+                "--rounding-offset" => {
+                    compress_args.jpeg.quantizer.rounding_offset =
+                        parse_range("rounding offset", value()?, 0.0f32, 0.5f32)?
+                }
+                "--reconstruction-bias" => {
+                    compress_args.jpeg.quantizer.reconstruction_bias =
+                        parse_range("reconstruction bias", value()?, 0.0f32, 0.5f32)?
+                }
+                "--adaptive-quant" => {
+                    compress_args.jpeg.adaptive_strength =
+                        parse_range("adaptive strength", value()?, 0.0f32, 4.0f32)?;
+                    compress_args.jpeg.use_adaptive_quantization = true;
+                }
+                "--draw-adaptive-scale" => compress_args.jpeg.use_draw_adaptive_scale = true,
+                "--roi-mask" => {
+                    compress_args.jpeg.roi_mask = Some(RoiMask::load(Path::new(value()?))?)
+                }
+                "--compression-rate" => compress_args.jpeg.use_compression_rate = true,
+                "--slow-dct" => compress_args.jpeg.use_fast_dct = false,
+                "--no-threads" => compress_args.use_threads = false,
+                "--optimize-huffman" => compress_args.use_optimized_huffman = true,
+                "--metrics" => compress_args.use_metrics = true,
+                "--target-size" => {
+                    let target_size = value()?;
+                    let bytes = target_size
+                        .parse()
+                        .ok()
+                        .filter(|&bytes| bytes > 0)
+                        .ok_or_else(|| format!("Invalid target size: {}", target_size))?;
+                    compress_args.rate_target = Some(RateTarget::Bytes(bytes));
+                }
+                "--target-psnr" => {
+                    let target_psnr = value()?;
+                    let psnr = target_psnr
+                        .parse()
+                        .ok()
+                        .filter(|psnr: &f32| psnr.is_finite())
+                        .ok_or_else(|| format!("Invalid target PSNR: {}", target_psnr))?;
+                    compress_args.rate_target = Some(RateTarget::Psnr(psnr));
+                }
+                "--max-depth" => {
+                    let max_depth = value()?;
+                    compress_args.quad_tree.max_depth = max_depth
+                        .parse()
+                        .map_err(|_| format!("Invalid max depth: {}", max_depth))?;
+                }
+                "--threshold-error" => {
+                    let threshold_error = value()?;
+                    compress_args.quad_tree.threshold_error = threshold_error
+                        .parse()
+                        .map_err(|_| format!("Invalid threshold error: {}", threshold_error))?;
+                }
+                "--min-size" => {
+                    compress_args.quad_tree.min_size = 1 << (parse_block_size_index(value()?)? + 1)
+                }
+                "--max-size" => {
+                    compress_args.quad_tree.max_size = 1 << (parse_block_size_index(value()?)? + 1)
+                }
+                "--pow-2" => compress_args.quad_tree.use_pow_2 = true,
+                "--draw-line" => compress_args.quad_tree.use_draw_line = true,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+                _ => paths.push(arg.to_string()),
+            }
+        }
+
+        if paths.len() != 2 {
+            return Err(String::from("Expected an input and an output path"));
+        }
+
+        if compress_args.rate_target.is_some()
+            && compress_args.mode != CompressMode::Jpeg
+            && compress_args.mode != CompressMode::QuadMind
+        {
+            return Err(String::from(
+                "Rate control only works in jpeg and quadmind modes",
+            ));
+        }
+        // QuadTree nodes are coded at full resolution whatever the subsampling
+        if (compress_args.mode == CompressMode::QuadTree
+            || compress_args.mode == CompressMode::QuadMind)
+            && compress_args.subsampling != Subsampling::default()
+        {
+            return Err(String::from(
+                "Subsampling only works in jpeg and none modes",
+            ));
+        }
+        // The rendered quality would not be the one saved
+        if compress_args.rate_target.is_some() && compress_args.jpeg.use_compression_rate {
+            return Err(String::from(
+                "Rate control can not be used with --compression-rate",
+            ));
+        }
+
+        compress_args.output = paths.pop().unwrap();
+        compress_args.input = paths.pop().unwrap();
+
+        Ok(compress_args)
+    }
+}
+
+pub struct SweepArgs {
+    pub sweep: Sweep,
+    pub compress_args: CompressArgs,
+}
+
+impl SweepArgs {
+    pub fn parse(args: &[String]) -> Result<SweepArgs, String> {
+        let mut sweep = Sweep::new();
+
+        let mut compress_args = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "--qualities" => {
+                    let qualities = value()?;
+                    let range = qualities
+                        .split(':')
+                        .map(parse_quality)
+                        .collect::<Result<Vec<f32>, String>>()?;
+                    if range.len() != 3 {
+                        return Err(format!("Invalid qualities: {}", qualities));
+                    }
+                    sweep.quality_start = range[0];
+                    sweep.quality_end = range[1];
+                    sweep.quality_step = range[2];
+                }
+                "--block-sizes" => {
+                    sweep.block_size_indices = value()?
+                        .split(',')
+                        .map(parse_block_size_index)
+                        .collect::<Result<_, _>>()?
+                }
+                "--subsamplings" => {
+                    sweep.subsampling_indices = value()?
+                        .split(',')
+                        .map(parse_subsampling_index)
+                        .collect::<Result<_, _>>()?
+                }
+                "--quadmind" => sweep.use_quad_mind = true,
+                "--no-jpeg" => sweep.use_jpeg = false,
+                _ => compress_args.push(arg.to_string()),
+            }
+        }
+
+        Ok(SweepArgs {
+            sweep,
+            compress_args: CompressArgs::parse(&compress_args)?,
+        })
+    }
+}
+
+pub fn run(args: &[String]) -> i32 {
+    match args[0].as_str() {
+        "compress" => match CompressArgs::parse(&args[1..]) {
+            Ok(compress_args) => match compress(compress_args) {
+                Ok(()) => 0,
+                Err(error) => {
+                    eprintln!("Error: {}", error);
+                    1
+                }
+            },
+            Err(error) => {
+                eprintln!("Error: {}\n\n{}", error, USAGE);
+                2
+            }
+        },
+        "sweep" => match SweepArgs::parse(&args[1..]) {
+            Ok(sweep_args) => match run_sweep(sweep_args) {
+                Ok(()) => 0,
+                Err(error) => {
+                    eprintln!("Error: {}", error);
+                    1
+                }
+            },
+            Err(error) => {
+                eprintln!("Error: {}\n\n{}", error, USAGE);
+                2
+            }
+        },
+        "metrics" if args.len() == 3 => match metrics(&args[1], &args[2]) {
+            Ok(()) => 0,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                1
+            }
+        },
+        "migrate" if args.len() == 3 => match migrate(&args[1], &args[2]) {
+            Ok(()) => 0,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                1
+            }
+        },
+        "migrate" => {
+            eprintln!("Error: Expected an input and an output path\n\n{}", USAGE);
+            2
+        }
+        "metrics" => {
+            eprintln!("Error: Expected a reference and an image path\n\n{}", USAGE);
+            2
+        }
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            0
+        }
+        command => {
+            eprintln!("Error: Unknown command: {}\n\n{}", command, USAGE);
+            2
+        }
+    }
+}
+
+pub fn compress(mut compress_args: CompressArgs) -> Result<(), String> {
+    let input_path = Path::new(&compress_args.input);
+    let output_path = Path::new(&compress_args.output);
+
+    let (mut my_image, _) = jpegview_core::load_image(
+        input_path,
+        compress_args.use_color_transform,
+        compress_args.subsampling,
+        compress_args.jpeg.chroma_filters,
+        compress_args.jpeg.color_space,
+    )
+    .map_err(|error| format!("Could not load {}: {}", compress_args.input, error))?;
+
+    let start_time = Instant::now();
+
+    let mut quad_mind_result = None;
+    let mut rate_control_result = None;
+
+    let estimated_bytes = match compress_args.mode {
+        CompressMode::Jpeg => match compress_args.rate_target {
+            Some(rate_target) => {
+                let result = rate_control::rate_control_jpeg(
+                    &mut compress_args.jpeg,
+                    &mut my_image,
+                    rate_target,
+                    compress_args.use_color_transform,
+                    compress_args.use_threads,
+                    compress_args.subsampling,
+                );
+                rate_control_result = Some(result);
+                Some(result.bytes)
+            }
+            None => Some(compress_args.jpeg.render(
+                &mut my_image,
+                compress_args.use_color_transform,
+                compress_args.use_threads,
+                compress_args.subsampling,
+            )),
+        },
+        CompressMode::QuadTree => Some(compress_args.quad_tree.render(
+            &mut my_image,
+            compress_args.use_color_transform,
+            compress_args.subsampling,
+            compress_args.jpeg.chroma_filters,
+            compress_args.jpeg.color_space,
+        )),
+        CompressMode::QuadMind => match compress_args.rate_target {
+            Some(rate_target) => {
+                let (result, quad_node_list, quad_dct_zig_zag) =
+                    rate_control::rate_control_quad_mind(
+                        &mut compress_args.jpeg,
+                        &mut compress_args.quad_tree,
+                        &mut my_image,
+                        rate_target,
+                        compress_args.use_color_transform,
+                        compress_args.use_threads,
+                        compress_args.subsampling,
+                    );
+                quad_mind_result = Some((quad_node_list, quad_dct_zig_zag));
+                rate_control_result = Some(result);
+                Some(result.bytes)
+            }
+            None => {
+                let (quad_node_list, quad_dct_zig_zag, file_size) = quad_mind::render_quad_mind(
+                    &mut compress_args.jpeg,
+                    &mut my_image,
+                    &compress_args.quad_tree,
+                    compress_args.use_color_transform,
+                    compress_args.use_threads,
+                    compress_args.subsampling,
+                );
+                quad_mind_result = Some((quad_node_list, quad_dct_zig_zag));
+                Some(file_size)
+            }
+        },
+        CompressMode::None => {
+            my_image.apply_transform(
+                compress_args.use_color_transform,
+                compress_args.subsampling,
+                compress_args.jpeg.chroma_filters,
+                compress_args.jpeg.color_space,
+            );
+            None
+        }
+    };
+
+    let elapsed_time = start_time.elapsed();
+
+    let ext = output_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+
+    let mut file_size = None;
+
+    if ext == "jpg" || ext == "jpeg" {
+        if compress_args.mode != CompressMode::Jpeg {
+            return Err(String::from("Only jpeg mode can be saved as jpg"));
+        }
+
+        file_size = Some(jfif::save_jfif(
+            output_path,
+            &my_image,
+            &compress_args.jpeg,
+            compress_args.use_color_transform,
+            compress_args.subsampling,
+            compress_args.use_optimized_huffman,
+        )?);
+    } else if ext == "qmi" {
+        let (quad_node_list, quad_dct_zig_zag) = quad_mind_result
+            .ok_or_else(|| String::from("Only quadmind mode can be saved as qmi"))?;
+
+        quad_mind::save_quad_mind(
+            output_path,
+            &quad_node_list,
+            &quad_dct_zig_zag,
+            &my_image,
+            &compress_args.jpeg,
+            &compress_args.quad_tree,
+            compress_args.use_color_transform,
+            compress_args.use_threads,
+            compress_args.subsampling,
+        )
+        .map_err(|error| error.to_string())?;
+    } else {
+        my_image
+            .save_final_image(output_path)
+            .map_err(|error| format!("Could not save image: {}", error))?;
+    }
+
+    println!(
+        "{} -> {} ({}x{}) in {:.3} ms",
+        compress_args.input,
+        compress_args.output,
+        my_image.width,
+        my_image.height,
+        elapsed_time.as_secs_f32() * 1000.0f32
+    );
+
+    if let Some(estimated_bytes) = estimated_bytes {
+        let size_estimate = SizeEstimate::new(estimated_bytes, my_image.width, my_image.height);
+        println!(
+            "Estimated Size: {} bytes / {:.3} bpp / Ratio {:.2}:1",
+            size_estimate.bytes, size_estimate.bpp, size_estimate.ratio
+        );
+    }
+
+    if let Some(file_size) = file_size {
+        println!("Jpeg File Size: {} bytes", file_size);
+    }
+
+    if let Some(rate_control_result) = rate_control_result {
+        print_rate_control(&rate_control_result);
+    }
+
+    if compress_args.use_metrics {
+        print_metrics(&Metrics::new(&my_image));
+
+        if compress_args.use_color_transform {
+            let round_trip_error = compress_args.jpeg.color_space.round_trip_error();
+            println!(
+                "Color Round Trip: Max Error {} / Mean Error {:.4}",
+                round_trip_error.max, round_trip_error.mean
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn metrics(reference: &str, image: &str) -> Result<(), String> {
+    let load = |path: &str| {
+        jpegview_core::load_image(
+            Path::new(path),
+            true,
+            Subsampling::default(),
+            ChromaFilters::default(),
+            ColorSpace::default(),
+        )
+        .map(|(my_image, _)| my_image)
+        .map_err(|error| format!("Could not load {}: {}", path, error))
+    };
+
+    let reference_image = load(reference)?;
+    let my_image = load(image)?;
+
+    if reference_image.width != my_image.width || reference_image.height != my_image.height {
+        return Err(format!(
+            "Image sizes differ: {}x{} and {}x{}",
+            reference_image.width, reference_image.height, my_image.width, my_image.height
+        ));
+    }
+
+    print_metrics(&Metrics::compare(
+        &reference_image.original_image,
+        &my_image.original_image,
+        my_image.width,
+        my_image.height,
+    ));
+
+    Ok(())
+}
+
+pub fn migrate(input: &str, output: &str) -> Result<(), String> {
+    let contents =
+        fs::read(input).map_err(|error| format!("Could not read {}: {}", input, error))?;
+
+    let migrated = quad_mind::migrate_quad_mind(&contents)
+        .map_err(|error| format!("Could not migrate {}: {}", input, error))?;
+
+    fs::write(output, &migrated)
+        .map_err(|error| format!("Could not write {}: {}", output, error))?;
+
+    println!(
+        "{} ({} bytes) -> {} ({} bytes)",
+        input,
+        contents.len(),
+        output,
+        migrated.len()
+    );
+
+    Ok(())
+}
+
+fn print_metrics(metrics: &Metrics) {
+    println!("RGB: PSNR {:.3} dB / MSE {:.3}", metrics.psnr, metrics.mse);
+
+    let channel = |name: &str, channel: &ChannelMetrics| {
+        println!(
+            "{}: PSNR {:.3} dB / MSE {:.3} / SSIM {:.5} / MS-SSIM {:.5}",
+            name, channel.psnr, channel.mse, channel.ssim, channel.ms_ssim
+        );
+    };
+
+    channel("Y", &metrics.y);
+    channel("Cb", &metrics.cb);
+    channel("Cr", &metrics.cr);
+    if let Some(alpha) = &metrics.alpha {
+        channel("Alpha", alpha);
+    }
+}
+
+fn print_rate_control(rate_control_result: &RateControlResult) {
+    print!("Rate Control: Quality {:.2}", rate_control_result.quality);
+    if let Some(threshold_error) = rate_control_result.threshold_error {
+        print!(" / Threshold Error {:.2}", threshold_error);
+    }
+    println!(
+        " / PSNR {:.3} dB / {} Renders / {}",
+        rate_control_result.psnr,
+        rate_control_result.renders,
+        if rate_control_result.target_met {
+            "Target Met"
+        } else {
+            "Target Not Met"
+        }
+    );
+}
+
+pub fn run_sweep(sweep_args: SweepArgs) -> Result<(), String> {
+    let compress_args = sweep_args.compress_args;
+    let output_path = Path::new(&compress_args.output);
+
+    match output_path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") | Some("json") => {}
+        _ => return Err(String::from("Sweep can only be saved as csv or json")),
+    }
+
+    let (my_image, _) = jpegview_core::load_image(
+        Path::new(&compress_args.input),
+        compress_args.use_color_transform,
+        compress_args.subsampling,
+        compress_args.jpeg.chroma_filters,
+        compress_args.jpeg.color_space,
+    )
+    .map_err(|error| format!("Could not load {}: {}", compress_args.input, error))?;
+
+    let point_count = sweep_args.sweep.point_count();
+    let mut points = Vec::with_capacity(point_count);
+
+    sweep_args.sweep.run(
+        &compress_args.jpeg,
+        &compress_args.quad_tree,
+        &my_image.original_image,
+        my_image.original_alpha.as_deref(),
+        my_image.width,
+        my_image.height,
+        compress_args.use_color_transform,
+        compress_args.use_threads,
+        |point| {
+            println!(
+                "[{}/{}] {} q{} -> {} bytes / {:.3} bpp / PSNR {:.3} dB / SSIM {:.5}",
+                points.len() + 1,
+                point_count,
+                point.series(),
+                point.quality,
+                point.bytes,
+                point.bpp,
+                point.psnr,
+                point.ssim
+            );
+            points.push(point);
+        },
+    );
+
+    sweep::save_sweep(output_path, &points)
+}
+
+fn parse_quality(quality: &str) -> Result<f32, String> {
+    match quality.parse::<f32>() {
+        Ok(quality) if (1.0f32..=100.0f32).contains(&quality) => Ok(quality),
+        _ => Err(format!("Invalid quality: {}", quality)),
+    }
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(parsed) if parsed.is_finite() && parsed > 0.0f32 => Ok(parsed),
+        _ => Err(format!("Invalid {}: {}", name, value)),
+    }
+}
+
+fn parse_range(name: &str, value: &str, min: f32, max: f32) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(parsed) if (min..=max).contains(&parsed) => Ok(parsed),
+        _ => Err(format!("Invalid {}: {}", name, value)),
+    }
+}
+
+fn parse_subsampling_index(subsampling: &str) -> Result<usize, String> {
+    Subsampling::parse(subsampling)?
+        .preset_index()
+        .ok_or_else(|| format!("Sweeps only take the preset subsamplings: {}", subsampling))
+}
+
+fn parse_filter_index(name: &str, items: &[&str], value: &str) -> Result<usize, String> {
+    items
+        .iter()
+        .position(|item| item.eq_ignore_ascii_case(value))
+        .ok_or_else(|| format!("Unknown {} filter: {}", name, value))
+}
+
+fn parse_block_size_index(block_size: &str) -> Result<usize, String> {
+    BLOCK_SIZE_ITEMS
+        .iter()
+        .position(|item| *item == block_size)
+        .ok_or_else(|| format!("Invalid block size: {}", block_size))
+}
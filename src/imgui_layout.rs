@@ -1,4 +1,17 @@
-use crate::{jpeg::Jpeg, my_image::MyImage, quad_tree::QuadTree};
+use jpegview_core::{
+    bitrate::SizeEstimate,
+    chroma::{ChromaFilters, ChromaSiting, DownsampleFilter, Subsampling, UpsampleFilter},
+    color_space::{ColorRange, ColorSpace, ColorTransform, RoundTripError, YcbcrMatrix},
+    jpeg::Jpeg,
+    jpeg_file::JpegFile,
+    metrics::{ChannelMetrics, Metrics},
+    my_image::MyImage,
+    quad_tree::QuadTree,
+    quant_table::{QuantTables, QUANT_TABLE_SIZES},
+    rate_control::RateControlResult,
+    roi_mask::RoiMask,
+    sweep::{Sweep, SweepPoint},
+};
 
 pub fn jpeg(
     ui: &imgui::Ui,
@@ -6,6 +19,7 @@ pub fn jpeg(
     jpeg: &mut Jpeg,
     use_jpeg: &mut bool,
     use_threads: &mut bool,
+    use_optimized_huffman: &mut bool,
     threads_available: bool,
 ) {
     ui.align_text_to_frame_padding();
@@ -30,8 +44,131 @@ pub fn jpeg(
             jpeg.block_size = 1 << (jpeg.block_size_index + 1);
         }
 
+        let mut q_matrix_index = if jpeg.use_gen_qtable {
+            2
+        } else if jpeg.use_csf_qtable {
+            3
+        } else {
+            usize::from(jpeg.use_resampled_qtable)
+        };
+
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Quantization Table:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        if ui.combo_simple_string("##q_matrix", &mut q_matrix_index, &Q_MATRIX_ITEMS) {
+            jpeg.use_gen_qtable = q_matrix_index == 2;
+            jpeg.use_resampled_qtable = q_matrix_index == 1;
+            jpeg.use_csf_qtable = q_matrix_index == 3;
+        }
+
+        if jpeg.use_csf_qtable {
+            indent_block(ui, || {
+                ui.align_text_to_frame_padding();
+                ui.bullet_text("Viewing Distance (cm):");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                ui.slider(
+                    "##viewing_distance",
+                    10.0f32,
+                    300.0f32,
+                    &mut jpeg.csf.viewing_distance,
+                );
+
+                ui.align_text_to_frame_padding();
+                ui.bullet_text("Display DPI:");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                ui.slider(
+                    "##display_dpi",
+                    50.0f32,
+                    600.0f32,
+                    &mut jpeg.csf.display_dpi,
+                );
+            });
+        }
+
         ui.align_text_to_frame_padding();
-        ui.checkbox("Use Generated Quantization Table", &mut jpeg.use_gen_qtable);
+        ui.checkbox("Use RDO Quantization", &mut jpeg.use_rdo_quantization);
+
+        ui.disabled(!jpeg.use_rdo_quantization, || {
+            indent_block(ui, || {
+                ui.align_text_to_frame_padding();
+                ui.bullet_text("Lambda:");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                ui.slider_config("##rdo_lambda", 0.0f32, 2000.0f32)
+                    .flags(imgui::SliderFlags::LOGARITHMIC)
+                    .build(&mut jpeg.rdo_lambda);
+            });
+        });
+
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Dead Zone:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        ui.slider("##dead_zone", 0.0f32, 2.0f32, &mut jpeg.quantizer.dead_zone);
+
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Rounding Offset:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        ui.slider(
+            "##rounding_offset",
+            0.0f32,
+            0.5f32,
+            &mut jpeg.quantizer.rounding_offset,
+        );
+
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Reconstruction Bias:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        ui.slider(
+            "##reconstruction_bias",
+            0.0f32,
+            0.5f32,
+            &mut jpeg.quantizer.reconstruction_bias,
+        );
+
+        ui.align_text_to_frame_padding();
+        ui.checkbox(
+            "Use Adaptive Quantization",
+            &mut jpeg.use_adaptive_quantization,
+        );
+
+        ui.disabled(!jpeg.use_adaptive_quantization, || {
+            indent_block(ui, || {
+                ui.align_text_to_frame_padding();
+                ui.bullet_text("Strength:");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                ui.slider(
+                    "##adaptive_strength",
+                    0.0f32,
+                    4.0f32,
+                    &mut jpeg.adaptive_strength,
+                );
+            });
+        });
+
+        ui.disabled(!jpeg.use_block_scales(), || {
+            ui.align_text_to_frame_padding();
+            ui.checkbox("Show Block Scales", &mut jpeg.use_draw_adaptive_scale);
+        });
+
+        ui.align_text_to_frame_padding();
+        ui.checkbox("Use Lossless Alpha", &mut jpeg.alpha.use_lossless);
+
+        ui.disabled(jpeg.alpha.use_lossless, || {
+            indent_block(ui, || {
+                ui.align_text_to_frame_padding();
+                ui.bullet_text("Alpha Quality:");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                ui.slider("##alpha_quality", 1.0f32, 100.0f32, &mut jpeg.alpha.quality);
+            });
+        });
 
         ui.align_text_to_frame_padding();
         ui.checkbox("Show Compression Rate", &mut jpeg.use_compression_rate);
@@ -47,6 +184,9 @@ pub fn jpeg(
         ui.align_text_to_frame_padding();
         ui.checkbox("Use Fast DCT Algorithm", &mut jpeg.use_fast_dct);
 
+        ui.align_text_to_frame_padding();
+        ui.checkbox("Use Optimized Huffman Tables", use_optimized_huffman);
+
         ui.disabled(!threads_available, || {
             ui.align_text_to_frame_padding();
             ui.checkbox("Use Multi-Threading", use_threads);
@@ -54,15 +194,269 @@ pub fn jpeg(
     });
 }
 
-pub fn ycbcr(ui: &imgui::Ui, column: f32, use_ycbcr: &mut bool, subsampling_index: &mut usize) {
+#[allow(clippy::too_many_arguments)]
+pub fn color_transform(
+    ui: &imgui::Ui,
+    column: f32,
+    use_color_transform: &mut bool,
+    subsampling: &mut Subsampling,
+    chroma_filters: &mut ChromaFilters,
+    color_space: &mut ColorSpace,
+    round_trip_error: &RoundTripError,
+    use_quad_tree: bool,
+) {
     ui.align_text_to_frame_padding();
-    ui.checkbox("Use YCbCr Colors", use_ycbcr);
+    ui.checkbox("Use Color Transform", use_color_transform);
 
-    ui.align_text_to_frame_padding();
-    ui.bullet_text("Chroma Subsampling:");
-    ui.same_line();
-    ui.set_next_item_width(column - ui.cursor_pos()[0]);
-    ui.combo_simple_string("##subsampling", subsampling_index, &SUBSAMPLING_ITEMS);
+    ui.disabled(!*use_color_transform, || {
+        indent_block(ui, || {
+            let mut transform_index = ColorTransform::ALL
+                .iter()
+                .position(|&transform| transform == color_space.transform)
+                .unwrap_or(0);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Transform:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            if ui.combo_simple_string("##color_transform", &mut transform_index, &TRANSFORM_ITEMS) {
+                color_space.transform = ColorTransform::ALL[transform_index];
+            }
+
+            let is_ycbcr = color_space.transform == ColorTransform::Ycbcr;
+
+            let mut matrix_index = YcbcrMatrix::ALL
+                .iter()
+                .position(|&matrix| matrix == color_space.matrix)
+                .unwrap_or(0);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Matrix:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.disabled(!is_ycbcr, || {
+                if ui.combo_simple_string("##ycbcr_matrix", &mut matrix_index, &MATRIX_ITEMS) {
+                    color_space.matrix = YcbcrMatrix::ALL[matrix_index];
+                }
+            });
+
+            let mut range_index = ColorRange::ALL
+                .iter()
+                .position(|&range| range == color_space.range)
+                .unwrap_or(0);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Range:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.disabled(!is_ycbcr, || {
+                if ui.combo_simple_string("##ycbcr_range", &mut range_index, &RANGE_ITEMS) {
+                    color_space.range = ColorRange::ALL[range_index];
+                }
+            });
+
+            ui.bullet_text(format!(
+                "Round Trip Error: Max {} / Mean {:.4}",
+                round_trip_error.max, round_trip_error.mean
+            ));
+        });
+    });
+
+    // Nodes are coded at full resolution, a subsampled size would not be
+    // smaller
+    if use_quad_tree {
+        *subsampling = Subsampling::default();
+        ui.text_disabled("QuadTree codes full resolution chroma");
+    }
+
+    ui.disabled(use_quad_tree, || {
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Chroma Subsampling:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        // The factors show as custom when no preset has them
+        let mut subsampling_index = subsampling
+            .preset_index()
+            .unwrap_or(Subsampling::PRESETS.len());
+        if ui.combo_simple_string("##subsampling", &mut subsampling_index, &SUBSAMPLING_ITEMS) {
+            if let Some(&preset) = Subsampling::PRESETS.get(subsampling_index) {
+                *subsampling = preset;
+            }
+        }
+
+        indent_block(ui, || {
+            let mut factor_x = subsampling.factor_x as u32;
+            let mut factor_y = subsampling.factor_y as u32;
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Horizontal Factor:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.slider(
+                "##factor_x",
+                1,
+                Subsampling::MAX_FACTOR as u32,
+                &mut factor_x,
+            );
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Vertical Factor:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.slider(
+                "##factor_y",
+                1,
+                Subsampling::MAX_FACTOR as u32,
+                &mut factor_y,
+            );
+
+            *subsampling = Subsampling::new(
+                (factor_x as usize).clamp(1, Subsampling::MAX_FACTOR),
+                (factor_y as usize).clamp(1, Subsampling::MAX_FACTOR),
+            );
+        });
+    });
+
+    ui.disabled(*subsampling == Subsampling::default(), || {
+        indent_block(ui, || {
+            let mut downsample_index = DownsampleFilter::ALL
+                .iter()
+                .position(|&filter| filter == chroma_filters.downsample)
+                .unwrap_or(0);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Downsampling:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            if ui.combo_simple_string(
+                "##downsample_filter",
+                &mut downsample_index,
+                &DOWNSAMPLE_FILTER_ITEMS,
+            ) {
+                chroma_filters.downsample = DownsampleFilter::ALL[downsample_index];
+            }
+
+            let mut upsample_index = UpsampleFilter::ALL
+                .iter()
+                .position(|&filter| filter == chroma_filters.upsample)
+                .unwrap_or(0);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Upsampling:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            if ui.combo_simple_string(
+                "##upsample_filter",
+                &mut upsample_index,
+                &UPSAMPLE_FILTER_ITEMS,
+            ) {
+                chroma_filters.upsample = UpsampleFilter::ALL[upsample_index];
+            }
+
+            let mut siting_index = ChromaSiting::ALL
+                .iter()
+                .position(|&siting| siting == chroma_filters.siting)
+                .unwrap_or(0);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Siting:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            if ui.combo_simple_string("##chroma_siting", &mut siting_index, &SITING_ITEMS) {
+                chroma_filters.siting = ChromaSiting::ALL[siting_index];
+            }
+        });
+    });
+}
+
+pub fn error_popup(ui: &imgui::Ui, error_message: &mut Option<String>) {
+    if error_message.is_some() {
+        ui.open_popup("Error");
+    }
+
+    ui.modal_popup_config("Error")
+        .always_auto_resize(true)
+        .build(|| {
+            if let Some(error_message) = error_message.as_ref() {
+                ui.text(error_message);
+            }
+            if ui.button("Ok") {
+                *error_message = None;
+                ui.close_current_popup();
+            }
+        });
+}
+
+pub fn rate_control(
+    ui: &imgui::Ui,
+    column: f32,
+    use_rate_control: &mut bool,
+    rate_target_index: &mut usize,
+    rate_target_bytes: &mut i32,
+    rate_target_psnr: &mut f32,
+    use_compression_rate: bool,
+) {
+    // Results would be saved with the compression rate instead of the quality
+    // found
+    if use_compression_rate {
+        *use_rate_control = false;
+        ui.text_disabled("Compression rate ignores rate control");
+    }
+
+    ui.disabled(use_compression_rate, || {
+        ui.align_text_to_frame_padding();
+        ui.checkbox("Use Rate Control", use_rate_control);
+    });
+
+    ui.disabled(!*use_rate_control, || {
+        indent_block(ui, || {
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Target:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.combo_simple_string("##rate_target", rate_target_index, &RATE_TARGET_ITEMS);
+
+            ui.align_text_to_frame_padding();
+            if *rate_target_index == 0 {
+                ui.bullet_text("Target Size (bytes):");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                if ui
+                    .input_int("##rate_target_bytes", rate_target_bytes)
+                    .step(1000)
+                    .build()
+                {
+                    *rate_target_bytes = (*rate_target_bytes).max(1);
+                }
+            } else {
+                ui.bullet_text("Target PSNR (dB):");
+                ui.same_line();
+                ui.set_next_item_width(column - ui.cursor_pos()[0]);
+                ui.slider("##rate_target_psnr", 10.0f32, 60.0f32, rate_target_psnr);
+            }
+        });
+    });
+}
+
+pub fn rate_control_result(ui: &imgui::Ui, rate_control_result: &RateControlResult) {
+    let threshold_error = match rate_control_result.threshold_error {
+        Some(threshold_error) => format!(" / Threshold Error {:.2}", threshold_error),
+        None => String::new(),
+    };
+
+    ui.text(format!(
+        "Rate Control: Quality {:.2}{} / {} bytes / PSNR {:.3} dB / {} Renders / {}",
+        rate_control_result.quality,
+        threshold_error,
+        rate_control_result.bytes,
+        rate_control_result.psnr,
+        rate_control_result.renders,
+        if rate_control_result.target_met {
+            "Target Met"
+        } else {
+            "Target Not Met"
+        }
+    ));
 }
 
 pub fn quad_tree(
@@ -239,6 +633,441 @@ pub fn zoom_layer(
     style.pop();
 }
 
+pub fn jpeg_file(ui: &imgui::Ui, jpeg_file: &JpegFile) {
+    if !ui.collapsing_header("Jpeg File", imgui::TreeNodeFlags::empty()) {
+        return;
+    }
+
+    indent_block(ui, || {
+        ui.bullet_text(format!(
+            "Process: {}",
+            if jpeg_file.progressive {
+                "Progressive"
+            } else {
+                "Baseline"
+            }
+        ));
+
+        ui.bullet_text(format!(
+            "Chroma Subsampling: {}",
+            match jpeg_file.subsampling() {
+                Some(subsampling) => subsampling.name(),
+                None if jpeg_file.components.len() == 1 => String::from("Grayscale"),
+                None => String::from("Custom"),
+            }
+        ));
+
+        for component in &jpeg_file.components {
+            let coefficient_count = component.blocks.len() * 64;
+            ui.bullet_text(format!(
+                "Component {}: Sampling {}x{} / Table {} / Blocks {} / Non Zero {:.2}%",
+                component.id,
+                component.h,
+                component.v,
+                component.q_table_index,
+                component.blocks.len(),
+                component.non_zero_count as f32 * 100.0f32 / coefficient_count as f32
+            ));
+        }
+
+        for (index, q_table) in jpeg_file.q_tables.iter().enumerate() {
+            if let Some(q_table) = q_table {
+                ui.bullet_text(format!("Quantization Table {}:", index));
+                indent_block(ui, || {
+                    for row in q_table.chunks(8) {
+                        ui.text(
+                            row.iter()
+                                .map(|value| format!("{:4}", value))
+                                .collect::<String>(),
+                        );
+                    }
+                });
+            }
+        }
+    });
+}
+
+pub fn quant_tables(
+    ui: &imgui::Ui,
+    column: f32,
+    jpeg: &mut Jpeg,
+    q_tables: &mut QuantTables,
+    q_table_channel: &mut usize,
+) {
+    let mut use_custom_qtable = jpeg.q_tables.is_some();
+
+    ui.align_text_to_frame_padding();
+    let mut changed = ui.checkbox("Use Custom Quantization Table", &mut use_custom_qtable);
+
+    indent_block(ui, || {
+        let mut size_index = QUANT_TABLE_SIZES
+            .iter()
+            .position(|&size| size == q_tables.size)
+            .unwrap_or(2);
+
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Table Size:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        if ui.combo_simple_string(
+            "##q_table_size",
+            &mut size_index,
+            &BLOCK_SIZE_ITEMS[..QUANT_TABLE_SIZES.len()],
+        ) {
+            *q_tables = q_tables.resize(QUANT_TABLE_SIZES[size_index]);
+            changed = true;
+        }
+
+        ui.align_text_to_frame_padding();
+        ui.bullet_text("Table:");
+        ui.same_line();
+        ui.set_next_item_width(column - ui.cursor_pos()[0]);
+        ui.combo_simple_string("##q_table_channel", q_table_channel, &Q_TABLE_CHANNEL_ITEMS);
+
+        let size = q_tables.size;
+        let q_table = if *q_table_channel == 0 {
+            &mut q_tables.luma
+        } else {
+            &mut q_tables.chroma
+        };
+
+        let height = (ui.frame_height_with_spacing() * size as f32
+            + ui.clone_style().window_padding[1] * 2.0f32)
+            .min(Q_TABLE_GRID_HEIGHT);
+
+        ui.child_window("##q_table_grid")
+            .size([0.0f32, height])
+            .horizontal_scrollbar(true)
+            .build(|| {
+                for y in 0..size {
+                    for x in 0..size {
+                        if x > 0 {
+                            ui.same_line();
+                        }
+
+                        let index = y * size + x;
+                        ui.set_next_item_width(Q_TABLE_CELL_WIDTH);
+                        if imgui::Drag::new(format!("##q_table_{}", index))
+                            .range(1.0f32, 65535.0f32)
+                            .speed(0.25f32)
+                            .display_format("%.0f")
+                            .flags(imgui::SliderFlags::ALWAYS_CLAMP)
+                            .build(ui, &mut q_table[index])
+                        {
+                            changed = true;
+                        }
+                    }
+                }
+            });
+    });
+
+    if changed {
+        jpeg.q_tables = if use_custom_qtable {
+            Some(q_tables.clone())
+        } else {
+            None
+        };
+    }
+}
+
+// Returns true when the painted mask has to be shown or hidden
+pub fn roi_mask(
+    ui: &imgui::Ui,
+    column: f32,
+    jpeg: &Jpeg,
+    use_paint_roi: &mut bool,
+    roi_brush_size: &mut f32,
+    roi_paint_value: &mut i32,
+) -> bool {
+    match &jpeg.roi_mask {
+        Some(roi_mask) => ui.text(format!("Mask: {}x{}", roi_mask.width, roi_mask.height)),
+        None => ui.text("No mask, painting starts a black one"),
+    }
+
+    if jpeg.use_compression_rate {
+        ui.text_disabled("Compression rate ignores the mask");
+    }
+
+    ui.align_text_to_frame_padding();
+    let changed = ui.checkbox("Paint Mask", use_paint_roi);
+
+    ui.disabled(!*use_paint_roi, || {
+        indent_block(ui, || {
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Brush Size:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.slider("##roi_brush_size", 1.0f32, 200.0f32, roi_brush_size);
+
+            ui.align_text_to_frame_padding();
+            ui.bullet_text("Value:");
+            ui.same_line();
+            ui.set_next_item_width(column - ui.cursor_pos()[0]);
+            ui.slider("##roi_paint_value", 0, 255, roi_paint_value);
+        });
+    });
+
+    changed
+}
+
+// Paints the mask under the mouse on the last image, returns true when the
+// mask changed
+pub fn roi_paint_layer(
+    ui: &imgui::Ui,
+    my_image: &MyImage,
+    roi_mask: &mut Option<RoiMask>,
+    roi_brush_size: f32,
+    roi_paint_value: i32,
+) -> bool {
+    let mouse_pos = ui.io().mouse_pos;
+
+    let rect_min = ui.item_rect_min();
+    let rect_size = ui.item_rect_size();
+    let pixel_size = rect_size[0] / my_image.width as f32;
+
+    ui.get_foreground_draw_list()
+        .add_circle(mouse_pos, roi_brush_size * pixel_size, BRUSH_COL)
+        .build();
+
+    if !ui.is_mouse_down(imgui::MouseButton::Left) {
+        return false;
+    }
+
+    let x = my_image.width as f32 * ((mouse_pos[0] - rect_min[0]) / rect_size[0]);
+    let y = my_image.height as f32 * ((mouse_pos[1] - rect_min[1]) / rect_size[1]);
+
+    roi_mask
+        .get_or_insert_with(|| RoiMask::new(my_image.width, my_image.height))
+        .paint(
+            x,
+            y,
+            roi_brush_size,
+            roi_paint_value as u8,
+            my_image.width,
+            my_image.height,
+        );
+
+    true
+}
+
+pub fn size_estimate(ui: &imgui::Ui, size_estimate: &SizeEstimate) {
+    ui.text(format!(
+        "Estimated Size: {} bytes / {:.3} bpp / Ratio {:.2}:1",
+        size_estimate.bytes, size_estimate.bpp, size_estimate.ratio
+    ));
+}
+
+pub fn metrics(ui: &imgui::Ui, metrics: &Metrics) {
+    ui.text(format!(
+        "RGB: PSNR {:.3} dB / MSE {:.3}",
+        metrics.psnr, metrics.mse
+    ));
+
+    let channel = |name: &str, channel: &ChannelMetrics| {
+        ui.text(format!(
+            "{}: PSNR {:.3} dB / MSE {:.3} / SSIM {:.5} / MS-SSIM {:.5}",
+            name, channel.psnr, channel.mse, channel.ssim, channel.ms_ssim
+        ));
+    };
+
+    channel("Y", &metrics.y);
+    channel("Cb", &metrics.cb);
+    channel("Cr", &metrics.cr);
+    if let Some(alpha) = &metrics.alpha {
+        channel("Alpha", alpha);
+    }
+}
+
+pub fn sweep(ui: &imgui::Ui, column: f32, sweep: &mut Sweep) {
+    ui.align_text_to_frame_padding();
+    ui.bullet_text("Quality Start:");
+    ui.same_line();
+    ui.set_next_item_width(column - ui.cursor_pos()[0]);
+    ui.slider(
+        "##sweep_quality_start",
+        1.0f32,
+        100.0f32,
+        &mut sweep.quality_start,
+    );
+
+    ui.align_text_to_frame_padding();
+    ui.bullet_text("Quality End:");
+    ui.same_line();
+    ui.set_next_item_width(column - ui.cursor_pos()[0]);
+    ui.slider(
+        "##sweep_quality_end",
+        1.0f32,
+        100.0f32,
+        &mut sweep.quality_end,
+    );
+
+    ui.align_text_to_frame_padding();
+    ui.bullet_text("Quality Step:");
+    ui.same_line();
+    ui.set_next_item_width(column - ui.cursor_pos()[0]);
+    ui.slider(
+        "##sweep_quality_step",
+        1.0f32,
+        50.0f32,
+        &mut sweep.quality_step,
+    );
+
+    ui.align_text_to_frame_padding();
+    ui.bullet_text("Block Sizes:");
+    for (index, item) in BLOCK_SIZE_ITEMS.iter().enumerate() {
+        ui.same_line();
+        toggle_index(ui, item, index, &mut sweep.block_size_indices);
+    }
+
+    ui.align_text_to_frame_padding();
+    ui.bullet_text("Chroma Subsampling:");
+    for (index, item) in SUBSAMPLING_ITEMS[..Subsampling::PRESETS.len()]
+        .iter()
+        .enumerate()
+    {
+        ui.same_line();
+        toggle_index(ui, item, index, &mut sweep.subsampling_indices);
+    }
+
+    ui.align_text_to_frame_padding();
+    ui.checkbox("Sweep Jpeg", &mut sweep.use_jpeg);
+    ui.same_line();
+    ui.checkbox("Sweep QuadMind", &mut sweep.use_quad_mind);
+}
+
+pub fn sweep_plot(ui: &imgui::Ui, points: &[SweepPoint], plot_index: &mut usize) {
+    ui.align_text_to_frame_padding();
+    ui.bullet_text("Plot:");
+    ui.same_line();
+    ui.set_next_item_width(ui.content_region_avail()[0] * 0.25f32);
+    ui.combo_simple_string("##sweep_plot", plot_index, &SWEEP_PLOT_ITEMS);
+
+    let value = |point: &SweepPoint| match *plot_index {
+        0 => point.psnr,
+        1 => point.psnr_y,
+        2 => point.ssim,
+        _ => point.ms_ssim,
+    };
+
+    let mut series: Vec<(String, Vec<[f32; 2]>)> = Vec::new();
+    for point in points {
+        let name = point.series();
+        let position = match series
+            .iter()
+            .position(|(series_name, _)| *series_name == name)
+        {
+            Some(position) => position,
+            None => {
+                series.push((name, Vec::new()));
+                series.len() - 1
+            }
+        };
+        if value(point).is_finite() {
+            series[position].1.push([point.bpp, value(point)]);
+        }
+    }
+
+    let mut min = [f32::MAX, f32::MAX];
+    let mut max = [f32::MIN, f32::MIN];
+    for (_, values) in &series {
+        for value in values {
+            for i in 0..2 {
+                min[i] = min[i].min(value[i]);
+                max[i] = max[i].max(value[i]);
+            }
+        }
+    }
+    if min[0] > max[0] {
+        return;
+    }
+    for i in 0..2 {
+        if max[i] <= min[i] {
+            max[i] = min[i] + 1.0f32;
+        }
+    }
+
+    let origin = ui.cursor_screen_pos();
+    let size = [ui.content_region_avail()[0], SWEEP_PLOT_HEIGHT];
+    let margin = [60.0f32, 20.0f32];
+
+    let to_screen = |value: &[f32; 2]| {
+        [
+            origin[0]
+                + margin[0]
+                + (value[0] - min[0]) / (max[0] - min[0]) * (size[0] - margin[0] * 2.0f32),
+            origin[1]
+                + margin[1]
+                + (max[1] - value[1]) / (max[1] - min[1]) * (size[1] - margin[1] * 2.0f32),
+        ]
+    };
+
+    let draw_list = ui.get_window_draw_list();
+
+    draw_list
+        .add_rect(
+            [origin[0] + margin[0], origin[1] + margin[1]],
+            [
+                origin[0] + size[0] - margin[0],
+                origin[1] + size[1] - margin[1],
+            ],
+            BORDER_COL,
+        )
+        .build();
+
+    draw_list.add_text(
+        [origin[0], origin[1] + margin[1]],
+        TINT_COL,
+        format!("{:.3}", max[1]),
+    );
+    draw_list.add_text(
+        [origin[0], origin[1] + size[1] - margin[1] * 2.0f32],
+        TINT_COL,
+        format!("{:.3}", min[1]),
+    );
+    draw_list.add_text(
+        [origin[0] + margin[0], origin[1] + size[1] - margin[1]],
+        TINT_COL,
+        format!("{:.3} bpp", min[0]),
+    );
+    draw_list.add_text(
+        [
+            origin[0] + size[0] - margin[0] * 2.0f32,
+            origin[1] + size[1] - margin[1],
+        ],
+        TINT_COL,
+        format!("{:.3} bpp", max[0]),
+    );
+
+    for (index, (_, values)) in series.iter().enumerate() {
+        let color = SWEEP_PLOT_COLORS[index % SWEEP_PLOT_COLORS.len()];
+
+        let mut values = values.clone();
+        values.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+        for pair in values.windows(2) {
+            draw_list
+                .add_line(to_screen(&pair[0]), to_screen(&pair[1]), color)
+                .thickness(1.5f32)
+                .build();
+        }
+        for value in &values {
+            draw_list
+                .add_circle(to_screen(value), 3.0f32, color)
+                .filled(true)
+                .build();
+        }
+    }
+
+    ui.dummy(size);
+
+    for (index, (name, _)) in series.iter().enumerate() {
+        if index > 0 {
+            ui.same_line();
+        }
+        ui.text_colored(SWEEP_PLOT_COLORS[index % SWEEP_PLOT_COLORS.len()], name);
+    }
+}
+
 pub fn image(ui: &imgui::Ui, image_texture: u32, size: [f32; 2], uv0: [f32; 2], uv1: [f32; 2]) {
     imgui::Image::new(imgui::TextureId::new(image_texture as usize), size)
         .tint_col(TINT_COL)
@@ -275,6 +1104,18 @@ where
     ui.unindent();
 }
 
+fn toggle_index(ui: &imgui::Ui, label: &str, index: usize, indices: &mut Vec<usize>) {
+    let mut selected = indices.contains(&index);
+    if ui.checkbox(label, &mut selected) {
+        if selected {
+            indices.push(index);
+            indices.sort();
+        } else {
+            indices.retain(|&value| value != index);
+        }
+    }
+}
+
 fn increase_max<T>(value: &mut T, max: &mut T, inc: T, dec: T)
 where
     T: PartialOrd + std::ops::AddAssign + std::ops::SubAssign,
@@ -290,6 +1131,34 @@ pub const UV_MAX: [f32; 2] = [1.0f32, 1.0f32];
 
 const TINT_COL: [f32; 4] = [1.0f32, 1.0f32, 1.0f32, 1.0f32];
 const BORDER_COL: [f32; 4] = [0.5f32, 0.5f32, 0.5f32, 1.0f32];
+const BRUSH_COL: [f32; 4] = [1.0f32, 1.0f32, 0.0f32, 1.0f32];
+
+const RATE_TARGET_ITEMS: [&str; 2] = ["Size", "PSNR"];
+
+const Q_MATRIX_ITEMS: [&str; 4] = ["Tiled", "Resampled", "Generated", "Perceptual"];
+const Q_TABLE_CHANNEL_ITEMS: [&str; 2] = ["Luma", "Chroma"];
+const Q_TABLE_CELL_WIDTH: f32 = 40.0f32;
+const Q_TABLE_GRID_HEIGHT: f32 = 300.0f32;
+
+const SWEEP_PLOT_HEIGHT: f32 = 300.0f32;
+const SWEEP_PLOT_ITEMS: [&str; 4] = ["PSNR", "PSNR Y", "SSIM Y", "MS-SSIM Y"];
+const SWEEP_PLOT_COLORS: [[f32; 4]; 6] = [
+    [0.90f32, 0.30f32, 0.30f32, 1.0f32],
+    [0.30f32, 0.80f32, 0.30f32, 1.0f32],
+    [0.35f32, 0.55f32, 1.00f32, 1.0f32],
+    [0.95f32, 0.80f32, 0.25f32, 1.0f32],
+    [0.80f32, 0.40f32, 0.90f32, 1.0f32],
+    [0.30f32, 0.85f32, 0.85f32, 1.0f32],
+];
 
-const BLOCK_SIZE_ITEMS: [&str; 9] = ["2", "4", "8", "16", "32", "64", "128", "256", "512"];
-const SUBSAMPLING_ITEMS: [&str; 6] = ["4:4:4", "4:4:0", "4:2:2", "4:2:0", "4:1:1", "4:1:0"];
+pub const BLOCK_SIZE_ITEMS: [&str; 9] = ["2", "4", "8", "16", "32", "64", "128", "256", "512"];
+pub const SUBSAMPLING_ITEMS: [&str; 8] = [
+    "4:4:4", "4:4:0", "4:2:2", "4:2:0", "4:1:1", "4:1:0", "3:1:1", "Custom",
+];
+pub const DOWNSAMPLE_FILTER_ITEMS: [&str; 3] = ["Box", "Triangle", "Lanczos"];
+pub const UPSAMPLE_FILTER_ITEMS: [&str; 3] = ["Nearest", "Bilinear", "Fancy"];
+pub const SITING_ITEMS: [&str; 2] = ["Centered", "Co-sited"];
+pub const TRANSFORM_ITEMS: [&str; 7] =
+    ["YCbCr", "YCoCg", "YCoCg-R", "RCT", "ICT", "CIELAB", "OKLab"];
+pub const MATRIX_ITEMS: [&str; 3] = ["BT.601", "BT.709", "BT.2020"];
+pub const RANGE_ITEMS: [&str; 2] = ["Full", "Limited"];
@@ -1,17 +1,12 @@
-extern crate bincode;
 extern crate image;
-extern crate miniz_oxide;
 extern crate native_dialog;
-extern crate serde;
-extern crate sha2;
-extern crate threadpool;
 
 extern crate gl;
 extern crate glfw;
 
 extern crate imgui;
 
-extern crate fast_generated_dct;
+extern crate jpegview_core;
 
 use std::{
     env,
@@ -24,19 +19,37 @@ use gl::types::GLuint;
 use glfw::{Action, Context, Key};
 use native_dialog::FileDialog;
 
+mod cli;
 mod imgui_glfw;
 
 mod imgui_layout;
-mod jpeg;
-mod my_image;
-mod quad_mind;
-mod quad_tree;
-
-use jpeg::Jpeg;
-use my_image::MyImage;
-use quad_tree::{QuadNodeRef, QuadTree};
+mod opengl_image;
+
+use jpegview_core::{
+    bitrate::SizeEstimate,
+    chroma::Subsampling,
+    jfif,
+    jpeg::Jpeg,
+    jpeg_file::{self, JpegFile},
+    load_image,
+    metrics::Metrics,
+    my_image::MyImage,
+    quad_mind::{self, QuadMindConfig},
+    quad_tree::{QuadNodeRef, QuadTree},
+    quant_table::QuantTables,
+    rate_control::{self, RateControlResult, RateTarget},
+    roi_mask::RoiMask,
+    sweep::{self, Sweep, SweepPoint},
+    Vec3d,
+};
+use opengl_image::OpenglImage;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let cargo_pkg_version = env!("CARGO_PKG_VERSION");
     let working_dir = env::current_dir().expect("Could not get current dir");
 
@@ -154,8 +167,40 @@ fn main() {
     let mut jpeg = Jpeg::new(8, 90.0f32, 1.0f32, 2, false, use_threads, true, false);
     let mut quad_tree = QuadTree::new(50, 4, 64, false, true, 10.0f32);
 
-    let mut use_ycbcr = true;
-    let mut subsampling_index = 0;
+    let mut q_tables = QuantTables::new(8, false);
+    let mut q_table_channel = 0;
+
+    let mut use_color_transform = true;
+    let mut subsampling = Subsampling::default();
+
+    // Measured again when the color space changes
+    let mut round_trip_error = (jpeg.color_space, jpeg.color_space.round_trip_error());
+
+    let mut use_optimized_huffman = false;
+    let mut jfif_size: Option<usize> = None;
+
+    let mut opt_jpeg_file: Option<JpegFile> = None;
+    let mut opt_metrics: Option<Metrics> = None;
+    let mut opt_size_estimate: Option<SizeEstimate> = None;
+
+    let mut use_rate_control = false;
+    let mut rate_target_index = 0;
+    let mut rate_target_bytes = 50000;
+    let mut rate_target_psnr = 30.0f32;
+    let mut opt_rate_control: Option<RateControlResult> = None;
+
+    let mut use_paint_roi = false;
+    let mut roi_brush_size = 20.0f32;
+    let mut roi_paint_value = 255;
+    let mut update_roi_overlay = false;
+
+    let mut opt_error_message: Option<String> = None;
+
+    let mut sweep = Sweep::new();
+    let mut sweep_count = 0;
+    let mut sweep_plot_index = 0;
+    let sweep_points: Arc<Mutex<Vec<SweepPoint>>> = Arc::new(Mutex::new(Vec::new()));
+    let mut sweep_thread: Option<thread::JoinHandle<()>> = None;
 
     let mut image_textures = OpenglImages::new();
 
@@ -178,18 +223,15 @@ fn main() {
             .decode()
             .expect("Could not decode image");
 
-        let image_width = image.width() as usize;
-        let image_height = image.height() as usize;
+        let mut my_image = MyImage::from_dynamic_image(image, path);
 
-        let mut my_image = MyImage::new(
-            image.into_rgb8().into_vec(),
-            image_width,
-            image_height,
-            path,
+        my_image.apply_transform(
+            use_color_transform,
+            subsampling,
+            jpeg.chroma_filters,
+            jpeg.color_space,
         );
 
-        my_image.apply_transform(use_ycbcr, subsampling_index);
-
         image_textures.my_image_to_opengl(&my_image);
 
         opt_my_image = Some(my_image);
@@ -256,36 +298,63 @@ fn main() {
             .build(|| {
                 ui.menu_bar(|| {
                     if ui.menu_item("Open image") {
-                        if let Some((my_image, jpeg_)) =
-                            open_image(&working_dir, use_ycbcr, subsampling_index)
+                        match open_image(&working_dir, &jpeg, use_color_transform, &mut subsampling)
                         {
-                            if let Some(jpeg_) = jpeg_ {
-                                jpeg = jpeg_;
+                            Ok(Some((my_image, quad_mind_config, jpeg_file_))) => {
+                                // Reopening a qmi file restores the settings it was encoded with
+                                if let Some(quad_mind_config) = quad_mind_config {
+                                    jpeg = quad_mind_config.jpeg;
+                                    use_color_transform = quad_mind_config.use_color_transform;
+                                    use_jpeg = true;
+                                    use_quad_tree = true;
+
+                                    if let Some(settings) = quad_mind_config.settings {
+                                        quad_tree = settings.quad_tree;
+                                        subsampling = settings.subsampling;
+                                        min_size_index =
+                                            quad_tree.min_size.trailing_zeros() as usize - 1;
+                                        max_size_index =
+                                            quad_tree.max_size.trailing_zeros() as usize - 1;
+                                    }
+                                }
+                                opt_jpeg_file = jpeg_file_;
+                                opt_metrics = None;
+                                opt_size_estimate = None;
+                                opt_rate_control = None;
+
+                                if opt_my_image.is_some() {
+                                    image_textures.destroy();
+                                }
+
+                                image_textures.my_image_to_opengl(&my_image);
+                                update_roi_overlay = true;
+
+                                opt_my_image = Some(my_image);
+                                jfif_size = None;
                             }
-
-                            if opt_my_image.is_some() {
-                                image_textures.destroy();
-                            }
-
-                            image_textures.my_image_to_opengl(&my_image);
-
-                            opt_my_image = Some(my_image);
+                            Ok(None) => {}
+                            Err(error) => opt_error_message = Some(error),
                         }
                     }
                     if opt_my_image.is_some() {
                         if let Some(my_image) = &opt_my_image {
                             if ui.menu_item("Save image") {
-                                save_image(
+                                if let Err(error) = save_image(
                                     &working_dir,
                                     use_jpeg,
-                                    use_ycbcr,
+                                    use_color_transform,
                                     use_threads,
                                     use_quad_tree,
+                                    use_optimized_huffman,
+                                    subsampling,
                                     &jpeg,
+                                    &quad_tree,
                                     my_image,
                                     &quad_mind_list,
                                     &quad_mind_dct_zig_zag,
-                                )
+                                ) {
+                                    opt_error_message = Some(error);
+                                }
                             }
                         }
                     }
@@ -322,12 +391,38 @@ fn main() {
                         &mut jpeg,
                         &mut use_jpeg,
                         &mut use_threads,
+                        &mut use_optimized_huffman,
                         threads_available,
                     );
 
                     imgui_layout::separator();
 
-                    imgui_layout::ycbcr(ui, first_column, &mut use_ycbcr, &mut subsampling_index);
+                    if round_trip_error.0 != jpeg.color_space {
+                        round_trip_error = (jpeg.color_space, jpeg.color_space.round_trip_error());
+                    }
+
+                    imgui_layout::color_transform(
+                        ui,
+                        first_column,
+                        &mut use_color_transform,
+                        &mut subsampling,
+                        &mut jpeg.chroma_filters,
+                        &mut jpeg.color_space,
+                        &round_trip_error.1,
+                        use_quad_tree,
+                    );
+
+                    imgui_layout::separator();
+
+                    imgui_layout::rate_control(
+                        ui,
+                        first_column,
+                        &mut use_rate_control,
+                        &mut rate_target_index,
+                        &mut rate_target_bytes,
+                        &mut rate_target_psnr,
+                        jpeg.use_compression_rate,
+                    );
 
                     ui.next_column();
                     let second_column = ui.column_width(0) + (ui.column_width(1) * 0.90f32);
@@ -359,25 +454,256 @@ fn main() {
 
                     ui.separator();
 
+                    if let Some(jpeg_file) = &opt_jpeg_file {
+                        imgui_layout::jpeg_file(ui, jpeg_file);
+
+                        ui.separator();
+                    }
+
+                    if ui.collapsing_header("Quantization Tables", imgui::TreeNodeFlags::empty()) {
+                        imgui_layout::quant_tables(
+                            ui,
+                            first_column,
+                            &mut jpeg,
+                            &mut q_tables,
+                            &mut q_table_channel,
+                        );
+
+                        if ui.button("Import Tables") {
+                            match import_quant_tables(&working_dir) {
+                                Ok(Some(q_tables_)) => {
+                                    q_tables = q_tables_;
+                                    jpeg.q_tables = Some(q_tables.clone());
+                                }
+                                Ok(None) => {}
+                                Err(error) => opt_error_message = Some(error),
+                            }
+                        }
+                        ui.same_line();
+                        if ui.button("Export Tables") {
+                            if let Err(error) = export_quant_tables(&working_dir, &q_tables) {
+                                opt_error_message = Some(error);
+                            }
+                        }
+                        ui.same_line();
+                        if ui.button("Reset Tables") {
+                            q_tables = QuantTables::new(q_tables.size, jpeg.use_gen_qtable);
+                            if jpeg.q_tables.is_some() {
+                                jpeg.q_tables = Some(q_tables.clone());
+                            }
+                        }
+
+                        ui.separator();
+                    }
+
+                    if ui.collapsing_header("Region of Interest", imgui::TreeNodeFlags::empty()) {
+                        update_roi_overlay |= imgui_layout::roi_mask(
+                            ui,
+                            first_column,
+                            &jpeg,
+                            &mut use_paint_roi,
+                            &mut roi_brush_size,
+                            &mut roi_paint_value,
+                        );
+
+                        if ui.button("Import Mask") {
+                            match import_roi_mask(&working_dir) {
+                                Ok(Some(roi_mask)) => {
+                                    jpeg.roi_mask = Some(roi_mask);
+                                    update_roi_overlay = true;
+                                }
+                                Ok(None) => {}
+                                Err(error) => opt_error_message = Some(error),
+                            }
+                        }
+                        ui.disabled(jpeg.roi_mask.is_none(), || {
+                            ui.same_line();
+                            if ui.button("Export Mask") {
+                                if let Some(roi_mask) = &jpeg.roi_mask {
+                                    if let Err(error) = export_roi_mask(&working_dir, roi_mask) {
+                                        opt_error_message = Some(error);
+                                    }
+                                }
+                            }
+                            ui.same_line();
+                            if ui.button("Clear Mask") {
+                                jpeg.roi_mask = None;
+                                update_roi_overlay = true;
+                            }
+                        });
+
+                        ui.separator();
+                    }
+
                     if ui.button_with_size("Compress", [ui.content_region_avail()[0], 0.0f32]) {
+                        jfif_size = None;
+                        opt_rate_control = None;
+
+                        let rate_target = if rate_target_index == 0 {
+                            RateTarget::Bytes(rate_target_bytes.max(1) as usize)
+                        } else {
+                            RateTarget::Psnr(rate_target_psnr)
+                        };
+
+                        let mut estimated_bytes = None;
                         if use_quad_tree && use_jpeg {
-                            (quad_mind_list, quad_mind_dct_zig_zag) = quad_mind::render_quad_mind(
-                                &mut jpeg,
-                                my_image,
-                                &quad_tree,
-                                use_ycbcr,
-                                use_threads,
-                                subsampling_index,
-                            );
+                            if use_rate_control {
+                                let rate_control_result;
+                                (rate_control_result, quad_mind_list, quad_mind_dct_zig_zag) =
+                                    rate_control::rate_control_quad_mind(
+                                        &mut jpeg,
+                                        &mut quad_tree,
+                                        my_image,
+                                        rate_target,
+                                        use_color_transform,
+                                        use_threads,
+                                        subsampling,
+                                    );
+                                estimated_bytes = Some(rate_control_result.bytes);
+                                opt_rate_control = Some(rate_control_result);
+                            } else {
+                                let file_size;
+                                (quad_mind_list, quad_mind_dct_zig_zag, file_size) =
+                                    quad_mind::render_quad_mind(
+                                        &mut jpeg,
+                                        my_image,
+                                        &quad_tree,
+                                        use_color_transform,
+                                        use_threads,
+                                        subsampling,
+                                    );
+                                estimated_bytes = Some(file_size);
+                            }
                         } else if !use_quad_tree && !use_jpeg {
-                            my_image.apply_transform(use_ycbcr, subsampling_index);
+                            my_image.apply_transform(
+                                use_color_transform,
+                                subsampling,
+                                jpeg.chroma_filters,
+                                jpeg.color_space,
+                            );
                         } else if use_quad_tree {
-                            quad_tree.render(my_image, use_ycbcr, subsampling_index);
+                            estimated_bytes = Some(quad_tree.render(
+                                my_image,
+                                use_color_transform,
+                                subsampling,
+                                jpeg.chroma_filters,
+                                jpeg.color_space,
+                            ));
                         } else if use_jpeg {
-                            jpeg.render(my_image, use_ycbcr, use_threads, subsampling_index);
+                            if use_rate_control {
+                                let rate_control_result = rate_control::rate_control_jpeg(
+                                    &mut jpeg,
+                                    my_image,
+                                    rate_target,
+                                    use_color_transform,
+                                    use_threads,
+                                    subsampling,
+                                );
+                                estimated_bytes = Some(rate_control_result.bytes);
+                                opt_rate_control = Some(rate_control_result);
+                            } else {
+                                estimated_bytes = Some(jpeg.render(
+                                    my_image,
+                                    use_color_transform,
+                                    use_threads,
+                                    subsampling,
+                                ));
+                            }
+                            jfif_size = jfif::encode_jfif(
+                                my_image,
+                                &jpeg,
+                                use_color_transform,
+                                subsampling,
+                                use_optimized_huffman,
+                            )
+                            .ok()
+                            .map(|data| data.len());
                         }
                         my_image.update_opengl_image(image_textures.final_result, true);
                         my_image.update_opengl_image(image_textures.final_result_zoom, true);
+
+                        opt_metrics = Some(Metrics::new(my_image));
+                        opt_size_estimate = estimated_bytes
+                            .map(|bytes| SizeEstimate::new(bytes, my_image.width, my_image.height));
+                    }
+
+                    if let Some(size_estimate) = &opt_size_estimate {
+                        imgui_layout::size_estimate(ui, size_estimate);
+                    }
+
+                    if let Some(jfif_size) = jfif_size {
+                        ui.same_line();
+                        ui.text(format!("/ Jpeg File Size: {} bytes", jfif_size));
+                    }
+
+                    if let Some(rate_control_result) = &opt_rate_control {
+                        imgui_layout::rate_control_result(ui, rate_control_result);
+                    }
+
+                    if let Some(metrics) = &opt_metrics {
+                        imgui_layout::metrics(ui, metrics);
+                    }
+
+                    if ui.collapsing_header("Rate Distortion Sweep", imgui::TreeNodeFlags::empty())
+                    {
+                        let sweep_column = ui.content_region_avail()[0] * 0.45f32;
+                        imgui_layout::sweep(ui, sweep_column, &mut sweep);
+
+                        let sweep_running = sweep_thread
+                            .as_ref()
+                            .is_some_and(|handle| !handle.is_finished());
+
+                        ui.disabled(sweep_running || sweep.point_count() == 0, || {
+                            if ui.button("Run Sweep") {
+                                sweep_points.lock().unwrap().clear();
+                                sweep_count = sweep.point_count();
+
+                                let sweep = sweep.clone();
+                                let jpeg = jpeg.clone();
+                                let quad_tree = QuadTree::new(
+                                    quad_tree.max_depth,
+                                    quad_tree.min_size,
+                                    quad_tree.max_size,
+                                    quad_tree.use_pow_2,
+                                    false,
+                                    quad_tree.threshold_error,
+                                );
+                                let original_image = my_image.original_image.clone();
+                                let original_alpha = my_image.original_alpha.clone();
+                                let (width, height) = (my_image.width, my_image.height);
+                                let arc_sweep_points = Arc::clone(&sweep_points);
+
+                                sweep_thread = Some(thread::spawn(move || {
+                                    sweep.run(
+                                        &jpeg,
+                                        &quad_tree,
+                                        &original_image,
+                                        original_alpha.as_deref(),
+                                        width,
+                                        height,
+                                        use_color_transform,
+                                        use_threads,
+                                        |point| arc_sweep_points.lock().unwrap().push(point),
+                                    );
+                                }));
+                            }
+                        });
+
+                        let points = sweep_points.lock().unwrap();
+
+                        ui.same_line();
+                        ui.disabled(sweep_running || points.is_empty(), || {
+                            if ui.button("Export Sweep") {
+                                if let Err(error) = export_sweep(&working_dir, &points) {
+                                    opt_error_message = Some(error);
+                                }
+                            }
+                        });
+
+                        ui.same_line();
+                        ui.text(format!("Points: {} / {}", points.len(), sweep_count));
+
+                        imgui_layout::sweep_plot(ui, &points, &mut sweep_plot_index);
                     }
 
                     use_scroll = true;
@@ -385,6 +711,15 @@ fn main() {
                     let new_width = ui.content_region_avail()[0] / 2.0f32 - item_spacing;
                     let new_height = new_width * (my_image.height as f32 / my_image.width as f32);
 
+                    if update_roi_overlay {
+                        update_roi_overlay = false;
+                        update_original_texture(
+                            &image_textures,
+                            my_image,
+                            jpeg.roi_mask.as_ref().filter(|_| use_paint_roi),
+                        );
+                    }
+
                     imgui_layout::image(
                         ui,
                         image_textures.original,
@@ -393,7 +728,16 @@ fn main() {
                         imgui_layout::UV_MAX,
                     );
 
-                    if use_zoom && ui.is_item_hovered() {
+                    if use_paint_roi && ui.is_item_hovered() {
+                        use_scroll = false;
+                        update_roi_overlay |= imgui_layout::roi_paint_layer(
+                            ui,
+                            my_image,
+                            &mut jpeg.roi_mask,
+                            roi_brush_size,
+                            roi_paint_value,
+                        );
+                    } else if use_zoom && ui.is_item_hovered() {
                         use_scroll = false;
                         imgui_layout::zoom_layer(
                             ui,
@@ -433,9 +777,16 @@ fn main() {
                         close_file = false;
 
                         opt_my_image = None;
+                        opt_jpeg_file = None;
+                        opt_metrics = None;
+                        opt_size_estimate = None;
+                        opt_rate_control = None;
+                        jfif_size = None;
                         image_textures.destroy();
                     }
                 }
+
+                imgui_layout::error_popup(ui, &mut opt_error_message);
             });
 
         unsafe {
@@ -450,11 +801,14 @@ fn main() {
     image_textures.destroy();
 }
 
+type OpenedImage = (MyImage, Option<QuadMindConfig>, Option<JpegFile>);
+
 fn open_image(
     working_dir: &PathBuf,
-    use_ycbcr: bool,
-    subsampling_index: usize,
-) -> Option<(MyImage, Option<Jpeg>)> {
+    jpeg: &Jpeg,
+    use_color_transform: bool,
+    subsampling: &mut Subsampling,
+) -> Result<Option<OpenedImage>, String> {
     let file_dialog_path = FileDialog::new()
         .set_location(working_dir)
         .add_filter("Image Files", &["jpg", "jpeg", "png", "bmp", "qmi"])
@@ -468,61 +822,140 @@ fn open_image(
     if let Some(path) = file_dialog_path {
         let ext = path
             .extension()
-            .expect("Could not get open file dialog path ext")
-            .to_str()
-            .unwrap();
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+
+        // Keep the coefficients of jpg files to show how they were compressed
+        if ext == "jpg" || ext == "jpeg" {
+            if let Ok((mut my_image, jpeg_file)) = jpeg_file::load_jpeg(&path, jpeg.use_fast_dct) {
+                if let Some(file_subsampling) = jpeg_file.subsampling() {
+                    *subsampling = file_subsampling;
+                }
 
-        let (my_image, jpeg) = match ext {
-            "jpg" | "jpeg" | "png" | "bmp" => {
-                let image = image::io::Reader::open(&path)
-                    .expect("Could not open image")
-                    .decode()
-                    .expect("Could not decode image");
-
-                let image_width = image.width() as usize;
-                let image_height = image.height() as usize;
-
-                let mut my_image = MyImage::new(
-                    image.into_rgb8().into_vec(),
-                    image_width,
-                    image_height,
-                    path.to_str().unwrap().to_string(),
+                my_image.apply_transform(
+                    use_color_transform,
+                    *subsampling,
+                    jpeg.chroma_filters,
+                    jpeg.color_space,
                 );
 
-                my_image.apply_transform(use_ycbcr, subsampling_index);
-
-                (my_image, None)
+                return Ok(Some((my_image, None, Some(jpeg_file))));
             }
-            "qmi" => {
-                let quad_mind =
-                    quad_mind::load_quad_mind(&path).expect("Could not load quad mind image");
-                (quad_mind.0, Some(quad_mind.1))
-            }
-            _ => {
-                return None;
-            }
-        };
+        }
 
-        Some((my_image, jpeg))
+        load_image(
+            &path,
+            use_color_transform,
+            *subsampling,
+            jpeg.chroma_filters,
+            jpeg.color_space,
+        )
+        .map(|(my_image, quad_mind_config)| Some((my_image, quad_mind_config, None)))
     } else {
-        None
+        Ok(None)
+    }
+}
+
+fn import_quant_tables(working_dir: &PathBuf) -> Result<Option<QuantTables>, String> {
+    let file_dialog_path = FileDialog::new()
+        .set_location(working_dir)
+        .add_filter("Quantization Tables", &["txt", "json", "jpg", "jpeg"])
+        .show_open_single_file()
+        .expect("Could not open file dialog");
+
+    match file_dialog_path {
+        Some(path) => QuantTables::load(&path).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn export_quant_tables(working_dir: &PathBuf, q_tables: &QuantTables) -> Result<(), String> {
+    let file_dialog_path = FileDialog::new()
+        .set_location(working_dir)
+        .add_filter("Text File", &["txt"])
+        .add_filter("JSON File", &["json"])
+        .show_save_single_file()
+        .expect("Could not open save file dialog");
+
+    match file_dialog_path {
+        Some(path) => q_tables.save(&path),
+        None => Ok(()),
+    }
+}
+
+fn import_roi_mask(working_dir: &PathBuf) -> Result<Option<RoiMask>, String> {
+    let file_dialog_path = FileDialog::new()
+        .set_location(working_dir)
+        .add_filter("Mask Image", &["png", "bmp", "jpg", "jpeg"])
+        .show_open_single_file()
+        .expect("Could not open file dialog");
+
+    match file_dialog_path {
+        Some(path) => RoiMask::load(&path).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn export_roi_mask(working_dir: &PathBuf, roi_mask: &RoiMask) -> Result<(), String> {
+    let file_dialog_path = FileDialog::new()
+        .set_location(working_dir)
+        .add_filter("PNG Image", &["png"])
+        .show_save_single_file()
+        .expect("Could not open save file dialog");
+
+    match file_dialog_path {
+        Some(path) => roi_mask.save(&path),
+        None => Ok(()),
+    }
+}
+
+// Shows the mask over the original image while it is painted
+fn update_original_texture(
+    image_textures: &OpenglImages,
+    my_image: &MyImage,
+    roi_mask: Option<&RoiMask>,
+) {
+    match roi_mask {
+        Some(roi_mask) => my_image.update_opengl_pixels(
+            image_textures.original,
+            &roi_mask.draw_overlay(&my_image.original_image, my_image.width, my_image.height),
+        ),
+        None => my_image.update_opengl_image(image_textures.original, false),
+    }
+}
+
+fn export_sweep(working_dir: &PathBuf, points: &[SweepPoint]) -> Result<(), String> {
+    let file_dialog_path = FileDialog::new()
+        .set_location(working_dir)
+        .add_filter("CSV File", &["csv"])
+        .add_filter("JSON File", &["json"])
+        .show_save_single_file()
+        .expect("Could not open save file dialog");
+
+    match file_dialog_path {
+        Some(path) => sweep::save_sweep(&path, points),
+        None => Ok(()),
     }
 }
 
 fn save_image(
     working_dir: &PathBuf,
     use_jpeg: bool,
-    use_ycbcr: bool,
+    use_color_transform: bool,
     use_threads: bool,
     use_quad_tree: bool,
+    use_optimized_huffman: bool,
+    subsampling: Subsampling,
     jpeg: &Jpeg,
+    quad_tree: &QuadTree,
     my_image: &MyImage,
     quad_node_list: &[QuadNodeRef],
     quad_dct_zig_zag: &Vec3d<i32>,
-) {
+) -> Result<(), String> {
     let file_dialog_path = FileDialog::new()
         .set_location(working_dir)
         .add_filter("PNG Image", &["png"])
+        .add_filter("JPG JPEG Image", &["jpg", "jpeg"])
         .add_filter("QUADMIND Image", &["qmi"])
         .show_save_single_file()
         .expect("Could not open save file dialog");
@@ -535,34 +968,44 @@ fn save_image(
             .unwrap();
 
         match ext {
-            "png" => {
-                if use_jpeg {
-                    image::save_buffer(
-                        path,
-                        &my_image.final_image,
-                        my_image.width as u32,
-                        my_image.height as u32,
-                        image::ColorType::Rgb8,
-                    )
-                    .expect("Could not save image")
-                }
+            "png" if use_jpeg => {
+                my_image
+                    .save_final_image(&path)
+                    .map_err(|error| error.to_string())?;
             }
-            "qmi" => {
-                if use_quad_tree && use_jpeg {
-                    quad_mind::save_quad_mind(
+            "jpg" | "jpeg" => {
+                if use_quad_tree {
+                    return Err(String::from("QuadTree images can only be saved as qmi"));
+                }
+                if use_jpeg {
+                    jfif::save_jfif(
                         &path,
-                        quad_node_list,
-                        quad_dct_zig_zag,
                         my_image,
                         jpeg,
-                        use_ycbcr,
-                        use_threads,
-                    )
+                        use_color_transform,
+                        subsampling,
+                        use_optimized_huffman,
+                    )?;
                 }
             }
+            "qmi" if use_quad_tree && use_jpeg => {
+                quad_mind::save_quad_mind(
+                    &path,
+                    quad_node_list,
+                    quad_dct_zig_zag,
+                    my_image,
+                    jpeg,
+                    quad_tree,
+                    use_color_transform,
+                    use_threads,
+                    subsampling,
+                )
+                .map_err(|error| error.to_string())?;
+            }
             _ => {}
         }
     }
+    Ok(())
 }
 
 struct OpenglImages {
@@ -596,13 +1039,3 @@ impl OpenglImages {
         }
     }
 }
-
-pub type Vec2d<T> = Vec<Vec<T>>;
-pub type Vec3d<T> = Vec<Vec<Vec<T>>>;
-
-pub fn unwrap_arc_mutex<T>(value: Arc<Mutex<T>>) -> T
-where
-    T: std::fmt::Debug,
-{
-    Arc::try_unwrap(value).unwrap().into_inner().unwrap()
-}
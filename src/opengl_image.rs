@@ -0,0 +1,105 @@
+use gl::types::{GLfloat, GLint, GLsizei, GLuint};
+use jpegview_core::my_image::MyImage;
+use std::borrow::Cow;
+
+const CHECKERBOARD_SIZE: usize = 8;
+const CHECKERBOARD_COLORS: [f32; 2] = [204.0f32, 153.0f32];
+
+pub trait OpenglImage {
+    fn create_opengl_image(&self, use_final: bool, use_linear: bool) -> GLuint;
+    fn update_opengl_image(&self, image_texture: GLuint, use_final: bool);
+    fn update_opengl_pixels(&self, image_texture: GLuint, pixels: &[u8]);
+}
+
+impl OpenglImage for MyImage {
+    fn create_opengl_image(&self, use_final: bool, use_linear: bool) -> GLuint {
+        let color: [GLfloat; 4] = [0.2f32, 0.2f32, 0.2f32, 1.0f32];
+        let mut image_texture: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut image_texture);
+            gl::BindTexture(gl::TEXTURE_2D, image_texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            if use_linear {
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            } else {
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            }
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_BORDER as GLint,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_BORDER as GLint,
+            );
+            gl::TexParameterfv(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_BORDER_COLOR,
+                color.as_ptr() as *const f32,
+            );
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGB as GLint,
+                self.width as GLsizei,
+                self.height as GLsizei,
+                0,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                display_pixels(self, use_final).as_ptr() as *const std::os::raw::c_void,
+            );
+        }
+        image_texture
+    }
+    fn update_opengl_image(&self, image_texture: GLuint, use_final: bool) {
+        self.update_opengl_pixels(image_texture, &display_pixels(self, use_final));
+    }
+    // Pixels with the size of the image, like an overlay of it
+    fn update_opengl_pixels(&self, image_texture: GLuint, pixels: &[u8]) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, image_texture);
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const std::os::raw::c_void,
+            );
+        }
+    }
+}
+
+// Images with alpha are shown over a checkerboard
+fn display_pixels(my_image: &MyImage, use_final: bool) -> Cow<'_, [u8]> {
+    let (pixels, alpha) = if use_final {
+        (&my_image.final_image, &my_image.final_alpha)
+    } else {
+        (&my_image.original_image, &my_image.original_alpha)
+    };
+
+    let alpha = match alpha {
+        Some(alpha) => alpha,
+        None => return Cow::Borrowed(pixels),
+    };
+
+    let mut result = Vec::with_capacity(pixels.len());
+    for (index, (pixel, &value)) in pixels.chunks_exact(3).zip(alpha).enumerate() {
+        let x = (index % my_image.width) / CHECKERBOARD_SIZE;
+        let y = (index / my_image.width) / CHECKERBOARD_SIZE;
+        let background = CHECKERBOARD_COLORS[(x + y) % 2];
+
+        let opacity = value as f32 / 255.0f32;
+        for &color in pixel {
+            result.push((color as f32 * opacity + background * (1.0f32 - opacity) + 0.5f32) as u8);
+        }
+    }
+    Cow::Owned(result)
+}